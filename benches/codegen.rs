@@ -0,0 +1,56 @@
+use std::thread;
+use std::time::Instant;
+
+use kaleidoscope::parse::parser::Parser;
+use kaleidoscope::codegen::llvm_generator::LLVMGeneratorContext;
+use kaleidoscope::codegen::ir_generator::IRGenerator;
+
+const TERM_COUNTS: [usize; 3] = [25_000, 50_000, 100_000];
+
+// the parser, the code generator and the drop of the tree all recurse once per term
+const STACK_SIZE: usize = 1 << 30;
+
+// each size is timed a few times and the fastest run kept, to smooth out noise
+const RUNS: usize = 3;
+
+// copying subtrees would make the largest size 4 times slower per term than the smallest
+const MAX_SLOWDOWN: f64 = 2.0;
+
+/*
+    Times code generation for def bench(x) x + x + ... + x with a growing number of terms.
+    The expression is a left-leaning tree as deep as it has terms, so the time per term only
+    stays flat when codegen does not copy subtrees. Returns the time per term in ns.
+*/
+fn bench_codegen(terms: usize) -> f64 {
+    let src = format!("def bench(x) {}", vec!["x"; terms].join(" + "));
+    let ast = Parser::new(&src).next().unwrap().unwrap();
+
+    let elapsed = (0..RUNS).map(|_| {
+        let mut context = LLVMGeneratorContext::new();
+        let start = Instant::now();
+        unsafe {
+            ast.generate(&mut context);
+        }
+        start.elapsed()
+    }).min().unwrap();
+
+    let per_term = elapsed.as_nanos() as f64 / terms as f64;
+    println!("{:>7} terms: {:>8.2} ms, {:>6.0} ns/term", terms, elapsed.as_secs_f64() * 1e3, per_term);
+    per_term
+}
+
+fn main() {
+    thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(|| {
+            let per_term: Vec<f64> = TERM_COUNTS.iter().map(|&terms| bench_codegen(terms)).collect();
+
+            let slowdown = per_term[per_term.len() - 1] / per_term[0];
+            println!("slowdown per term from {} to {} terms: {:.2}x", TERM_COUNTS[0], TERM_COUNTS[TERM_COUNTS.len() - 1], slowdown);
+            assert!(slowdown <= MAX_SLOWDOWN,
+                    "codegen does not scale linearly: {:.2}x slower per term, at most {}x expected", slowdown, MAX_SLOWDOWN);
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+}
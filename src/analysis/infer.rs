@@ -0,0 +1,522 @@
+use std::collections::HashMap;
+
+use crate::analysis::type_check::{is_integral, Signature, TypeError};
+use crate::parse::token::Span;
+use crate::syntax::ast::*;
+use crate::syntax::types::*;
+use crate::syntax::visit::{walk_expr, Visitor};
+
+// the type of an expression while inferring: already known, or a variable solved at the end
+#[derive(PartialEq, Clone, Copy)]
+enum Term {
+    Known(Type),
+    Var(usize)
+}
+
+// what a constraint comes from, e.g. "argument 1 of g", with the bytes of the expression using the value
+#[derive(Clone)]
+struct Origin {
+    context: String,
+    span: Option<Span>
+}
+
+#[derive(Default)]
+struct Variable {
+    // "parameter x" or "return type", intermediate results have no name and are never reported
+    subject: Option<String>,
+    // used in arithmetic or ordering, where a bool is converted anyway and says nothing about the type
+    numeric: bool,
+    // an integral literal, which follows the type of the other operand like in the TypeChecker
+    literal: bool,
+    // for the result of arithmetic, its type is the wider one of the operands unless it is demanded
+    operands: Vec<Term>,
+    // types demanded by the uses of the variable, e.g. by the parameter it is passed to
+    required: Vec<(Type, Origin)>,
+    // variables whose demanded type is passed on to this one, e.g. from a sum to its operands
+    demanded_by: Vec<(usize, Origin)>,
+    // types the variable meets where conversions are allowed, e.g. the other operand of +
+    hints: Vec<(Term, Origin)>
+}
+
+struct Solution {
+    ty: Type,
+    // taken from a requirement, only then is it passed on to the variables demanded by this one
+    demanded: bool
+}
+
+// two constraints that disagree, both with the place they come from
+struct Disagreement {
+    conflicting: bool,
+    first: (Type, Origin),
+    second: (Type, Origin)
+}
+
+/*
+    Infers the parameter and return types left out in a definition from the uses in its body,
+    in the style of Hindley-Milner: every missing type is a variable, the body adds constraints
+    on them and they are solved once the whole body is seen. As conversions are implicit, there
+    are two kinds of constraints:
+        - requirements, where a use demands an exact type, e.g. an argument of a typed parameter
+          or the body of a function with a return type. They are passed on through operations:
+          in g(x + 1) with g(n: i64), both x + 1 and x are i64.
+        - hints, where a value meets another type that it would be converted to or from, e.g.
+          x in x * 0.5. They only decide the type when there is no requirement.
+    Two different requirements are a conflict and two different numeric hints are ambiguous,
+    both are reported for the parameter or return type with the expressions they come from. A type without any constraint falls back
+    to f64, as every value used to be a double. Calls to the function itself use its variables,
+    functions defined before are monomorphic and only checked against their signature, like the
+    consts and globals with their types.
+*/
+struct Inference<'s> {
+    function: String,
+    signatures: &'s HashMap<String, Signature>,
+    structs: &'s HashMap<String, Vec<(String, Type)>>,
+    // the span of every expression of the body, found by its address as the body is not moved
+    spans: HashMap<*const ExprAst, Span>,
+    variables: Vec<Variable>,
+    scope: HashMap<String, Term>,
+    params: Vec<Term>,
+    return_term: Term
+}
+
+/*
+    Returns the prototype with every type filled in, from the annotations, an earlier declaration or
+    inference. spans are the spans of the expressions of the body as the parser gives them, see
+    SpannedAst, the errors have no spans without them.
+*/
+pub fn infer_prototype(proto: &FuncAst, body: &ExprAst, spans: &[Span], signatures: &HashMap<String, Signature>,
+                       structs: &HashMap<String, Vec<(String, Type)>>, globals: &HashMap<String, Type>) -> Result<FuncAst, Vec<TypeError>> {
+    let (name, args, return_type) = match proto {
+        FuncAst::Prototype { name, args, return_type } => (name, args, return_type),
+        FuncAst::Function { .. } => panic!("Expected Prototype Ast!")
+    };
+    let declared = signatures.get(name);
+
+    let mut inference = Inference {
+        function: name.clone(),
+        signatures,
+        structs,
+        spans: ExprSpans::collect(body, spans),
+        variables: Vec::new(),
+        scope: HashMap::new(),
+        params: Vec::new(),
+        return_term: Term::Known(DEFAULT_TYPE)
+    };
+    // the parameters shadow the globals
+    inference.scope.extend(globals.iter().map(|(name, ty)| (name.clone(), Term::Known(*ty))));
+    for (i, arg) in args.iter().enumerate() {
+        let term = match arg.ty.or_else(|| declared.and_then(|signature| signature.params.get(i).copied())) {
+            Some(ty) => Term::Known(ty),
+            None => inference.fresh(Some(format!("parameter {}", arg.name)))
+        };
+        inference.params.push(term);
+        inference.scope.insert(arg.name.clone(), term);
+    }
+    inference.return_term = match return_type.or_else(|| declared.map(|signature| signature.return_type)) {
+        Some(ty) => Term::Known(ty),
+        None => inference.fresh(Some("return type".to_string()))
+    };
+
+    let body_term = inference.infer_expr(body);
+    let origin = inference.origin(format!("return value of {}", name), body);
+    inference.flow(body_term, inference.return_term, &origin);
+
+    let (solutions, errors) = inference.solve();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let resolve = |term: Term| match term {
+        Term::Known(ty) => ty,
+        Term::Var(var) => solutions[var].as_ref().map_or(DEFAULT_TYPE, |solution| solution.ty)
+    };
+    let args = args.iter().zip(&inference.params)
+        .map(|(arg, term)| Param { name: arg.name.clone(), ty: Some(resolve(*term)) })
+        .collect();
+    Ok(FuncAst::Prototype { name: name.clone(), args, return_type: Some(resolve(inference.return_term)) })
+}
+
+// pairs the expressions of a body with their spans, taking every expression after its children like the parser
+struct ExprSpans<'a> {
+    spans: std::slice::Iter<'a, Span>,
+    found: HashMap<*const ExprAst, Span>
+}
+
+impl<'a> ExprSpans<'a> {
+    // a body that does not have as many expressions as spans, e.g. one built by hand, gets none
+    fn collect(body: &ExprAst, spans: &'a [Span]) -> HashMap<*const ExprAst, Span> {
+        let mut collector = ExprSpans { spans: spans.iter(), found: HashMap::new() };
+        collector.visit_expr(body);
+        if collector.found.len() != spans.len() {
+            return HashMap::new();
+        }
+        collector.found
+    }
+}
+
+impl Visitor for ExprSpans<'_> {
+    fn visit_expr(&mut self, expr: &ExprAst) {
+        walk_expr(self, expr);
+        if let Some(span) = self.spans.next() {
+            self.found.insert(expr, *span);
+        }
+    }
+}
+
+impl<'s> Inference<'s> {
+    fn origin(&self, context: String, expr: &ExprAst) -> Origin {
+        Origin { context, span: self.spans.get(&(expr as *const ExprAst)).copied() }
+    }
+
+    fn fresh(&mut self, subject: Option<String>) -> Term {
+        self.variables.push(Variable { subject, ..Variable::default() });
+        Term::Var(self.variables.len() - 1)
+    }
+
+    fn fresh_result(&mut self, operands: Vec<Term>) -> Term {
+        self.variables.push(Variable { numeric: true, operands, ..Variable::default() });
+        Term::Var(self.variables.len() - 1)
+    }
+
+    fn mark_numeric(&mut self, term: Term) {
+        if let Term::Var(var) = term {
+            self.variables[var].numeric = true;
+        }
+    }
+
+    fn require(&mut self, term: Term, ty: Type, origin: &Origin) {
+        if let Term::Var(var) = term {
+            self.variables[var].required.push((ty, origin.clone()));
+        }
+    }
+
+    // results of arithmetic only take the type of their operands, hints would make them disagree with it
+    fn hint(&mut self, term: Term, hint: Term, origin: &Origin) {
+        match term {
+            Term::Var(var) if self.variables[var].operands.is_empty() => {
+                self.variables[var].hints.push((hint, origin.clone()));
+            },
+            _ => {}
+        }
+    }
+
+    // a value of type from that is converted to type to, as an argument or an assigned value
+    fn flow(&mut self, from: Term, to: Term, origin: &Origin) {
+        match (from, to) {
+            (_, Term::Known(ty)) => self.require(from, ty, origin),
+            (Term::Var(from_var), Term::Var(to_var)) => {
+                self.variables[from_var].demanded_by.push((to_var, origin.clone()));
+                self.hint(to, from, origin);
+            },
+            (Term::Known(_), Term::Var(_)) => self.hint(to, from, origin)
+        }
+    }
+
+    // both operands hint each other, as the narrower one is converted to the type of the other
+    fn infer_operands(&mut self, expr: &ExprAst, op: &str, lhs: &ExprAst, rhs: &ExprAst, numeric: bool) -> (Term, Term) {
+        let lhs = self.infer_expr(lhs);
+        let rhs = self.infer_expr(rhs);
+        let origin = self.origin(format!("operand of {}", op), expr);
+        self.hint(lhs, rhs, &origin);
+        self.hint(rhs, lhs, &origin);
+        if numeric {
+            self.mark_numeric(lhs);
+            self.mark_numeric(rhs);
+        }
+        (lhs, rhs)
+    }
+
+    fn infer_expr(&mut self, expr: &ExprAst) -> Term {
+        match expr {
+            ExprAst::NumberExpr { number } if is_integral(*number) => {
+                self.variables.push(Variable { literal: true, ..Variable::default() });
+                Term::Var(self.variables.len() - 1)
+            },
+            ExprAst::NumberExpr { .. } => Term::Known(Type::F64),
+            ExprAst::StringExpr { .. } => Term::Known(Type::Str),
+            ExprAst::VariableExpr { name } => match (self.scope.get(name), self.signatures.get(name)) {
+                (Some(term), _) => *term,
+                (None, Some(signature)) => Term::Known(Type::Function(signature.function_type())),
+                (None, None) => self.fresh(None)
+            },
+            ExprAst::UnaryExpr { op, operand } if op == "!" => {
+                self.infer_expr(operand);
+                Term::Known(Type::Bool)
+            },
+            ExprAst::UnaryExpr { operand, .. } => match self.infer_expr(operand) {
+                Term::Known(ty) => Term::Known(if ty == Type::Bool { Type::I64 } else { ty }),
+                term => {
+                    self.mark_numeric(term);
+                    term
+                }
+            },
+            ExprAst::BinaryExpr { op, lhs, rhs } => self.infer_binary_expr(expr, op, lhs, rhs),
+            ExprAst::CallExpr { callee, args } => self.infer_call_expr(expr, callee, args),
+            ExprAst::CastExpr { ty, operand } => {
+                self.infer_expr(operand);
+                Term::Known(*ty)
+            },
+            ExprAst::BlockExpr { exprs } => {
+                let mut term = None;
+                for expr in exprs {
+                    term = Some(self.infer_expr(expr));
+                }
+                term.unwrap_or_else(|| self.fresh(None))
+            },
+            ExprAst::ArrayExpr { elements } => {
+                for (i, element) in elements.iter().enumerate() {
+                    let element = self.infer_expr(element);
+                    let origin = self.origin(format!("element {} of array", i + 1), expr);
+                    self.flow(element, Term::Known(Type::F64), &origin);
+                }
+                Term::Known(Type::Array)
+            },
+            ExprAst::IndexExpr { array, index } => {
+                let array = self.infer_expr(array);
+                self.require(array, Type::Array, &self.origin("indexed value".to_string(), expr));
+                let index = self.infer_expr(index);
+                self.flow(index, Term::Known(Type::I64), &self.origin("array index".to_string(), expr));
+                Term::Known(Type::F64)
+            },
+            ExprAst::FieldExpr { object, field } => self.infer_field_expr(expr, object, field),
+            ExprAst::LambdaExpr { params, return_type, body } => self.infer_lambda_expr(params, *return_type, body)
+        }
+    }
+
+    /*
+        The body constrains the variables the lambda captures. Its own unannotated parameters are
+        taken as f64, although the TypeChecker can still give them the types of the function type
+        expected where the lambda is used.
+    */
+    fn infer_lambda_expr(&mut self, params: &[Param], return_type: Option<Type>, body: &ExprAst) -> Term {
+        let param_types: Vec<Type> = params.iter().map(|param| param.ty.unwrap_or(DEFAULT_TYPE)).collect();
+        let outer = self.scope.clone();
+        for (param, ty) in params.iter().zip(&param_types) {
+            self.scope.insert(param.name.clone(), Term::Known(*ty));
+        }
+        let body_term = self.infer_expr(body);
+        self.scope = outer;
+
+        let return_type = match (return_type, body_term) {
+            (Some(ty), _) => {
+                self.flow(body_term, Term::Known(ty), &self.origin("return value of lambda".to_string(), body));
+                ty
+            },
+            (None, Term::Known(ty)) => ty,
+            (None, Term::Var(_)) => DEFAULT_TYPE
+        };
+        Term::Known(Type::Function(FunctionType::new(param_types, return_type)))
+    }
+
+    // an untyped value whose field is accessed is the struct with that field, if only one has it
+    fn infer_field_expr(&mut self, expr: &ExprAst, object: &ExprAst, field: &str) -> Term {
+        let object = self.infer_expr(object);
+        let struct_name = match object {
+            Term::Known(Type::Struct(name)) => Some(name.as_str().to_string()),
+            Term::Known(_) => None,
+            Term::Var(_) => {
+                let mut candidates = self.structs.iter()
+                    .filter(|(_, fields)| fields.iter().any(|(name, _)| name == field))
+                    .map(|(name, _)| name.clone());
+                match (candidates.next(), candidates.next()) {
+                    (Some(name), None) => {
+                        let origin = self.origin(format!("access to field {}", field), expr);
+                        self.require(object, Type::Struct(StructName::new(&name)), &origin);
+                        Some(name)
+                    },
+                    _ => None
+                }
+            }
+        };
+        // unknown fields are reported by the TypeChecker
+        let field_type = struct_name
+            .and_then(|name| self.structs.get(&name))
+            .and_then(|fields| fields.iter().find(|(name, _)| name == field))
+            .map(|(_, ty)| *ty);
+        match field_type {
+            Some(ty) => Term::Known(ty),
+            None => self.fresh(None)
+        }
+    }
+
+    fn infer_binary_expr(&mut self, expr: &ExprAst, op: &str, lhs: &ExprAst, rhs: &ExprAst) -> Term {
+        match op {
+            "=" => {
+                let variable = self.infer_expr(lhs);
+                let value = self.infer_expr(rhs);
+                self.flow(value, variable, &self.origin(format!("assignment to {}", lhs), expr));
+                variable
+            },
+            "&&" | "||" => {
+                self.infer_expr(lhs);
+                self.infer_expr(rhs);
+                Term::Known(Type::Bool)
+            },
+            "^" => {
+                let base = self.infer_expr(lhs);
+                let exponent = self.infer_expr(rhs);
+                self.hint(base, Term::Known(Type::F64), &self.origin("base of ^".to_string(), expr));
+                self.hint(exponent, Term::Known(Type::F64), &self.origin("exponent of ^".to_string(), expr));
+                Term::Known(Type::F64)
+            },
+            "==" | "!=" => {
+                self.infer_operands(expr, op, lhs, rhs, false);
+                Term::Known(Type::Bool)
+            },
+            "<" | ">" | "<=" | ">=" => {
+                self.infer_operands(expr, op, lhs, rhs, true);
+                Term::Known(Type::Bool)
+            },
+            _ => {
+                let (lhs, rhs) = self.infer_operands(expr, op, lhs, rhs, true);
+                if let (Term::Known(lhs_type), Term::Known(rhs_type)) = (lhs, rhs) {
+                    let is_integral = |ty| matches!(ty, Type::I64 | Type::Bool);
+                    return Term::Known(if is_integral(lhs_type) && is_integral(rhs_type) { Type::I64 } else { Type::F64 });
+                }
+                let result = self.fresh_result(vec![lhs, rhs]);
+                if let Term::Var(result) = result {
+                    for operand in [lhs, rhs] {
+                        if let Term::Var(operand) = operand {
+                            let origin = self.origin(format!("result of {}", op), expr);
+                            self.variables[operand].demanded_by.push((result, origin));
+                        }
+                    }
+                }
+                result
+            }
+        }
+    }
+
+    fn infer_call_expr(&mut self, expr: &ExprAst, callee: &str, args: &[ExprAst]) -> Term {
+        let args: Vec<Term> = args.iter().map(|arg| self.infer_expr(arg)).collect();
+        let (params, return_term) = if let Some(term) = self.scope.get(callee).copied() {
+            let function_type = match term {
+                Term::Known(Type::Function(function_type)) => function_type,
+                // calls of other values are reported by the TypeChecker
+                Term::Known(_) => return self.fresh(None),
+                Term::Var(_) => {
+                    // an untyped function value takes and returns f64, like an untyped function
+                    let function_type = FunctionType::new(vec![DEFAULT_TYPE; args.len()], DEFAULT_TYPE);
+                    self.require(term, Type::Function(function_type), &self.origin(format!("call of {}", callee), expr));
+                    function_type
+                }
+            };
+            (function_type.params().iter().map(|ty| Term::Known(*ty)).collect(), Term::Known(function_type.return_type()))
+        } else if callee == self.function {
+            (self.params.clone(), self.return_term)
+        } else if let Some(signature) = self.signatures.get(callee) {
+            (signature.params.iter().map(|ty| Term::Known(*ty)).collect(), Term::Known(signature.return_type))
+        } else {
+            // unknown functions are reported by the SemanticAnalyzer
+            return self.fresh(None);
+        };
+        for (i, (arg, param)) in args.into_iter().zip(params).enumerate() {
+            let origin = self.origin(format!("argument {} of {}", i + 1, callee), expr);
+            self.flow(arg, param, &origin);
+        }
+        return_term
+    }
+
+    /*
+        Hints can go through other variables, e.g. through the result of an operation, and form
+        cycles through recursive calls, so the solutions are recomputed until none changes.
+    */
+    fn solve(&self) -> (Vec<Option<Solution>>, Vec<TypeError>) {
+        let mut solutions: Vec<Option<Solution>> = (0..self.variables.len()).map(|_| None).collect();
+        for _ in 0..=self.variables.len() {
+            let mut changed = false;
+            for var in 0..self.variables.len() {
+                let (solution, _) = self.solve_variable(var, &solutions);
+                let same = match (&solution, &solutions[var]) {
+                    (Some(new), Some(old)) => new.ty == old.ty && new.demanded == old.demanded,
+                    (None, None) => true,
+                    _ => false
+                };
+                if !same {
+                    solutions[var] = solution;
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        let errors = self.variables.iter().enumerate().filter_map(|(var, variable)| {
+            let subject = variable.subject.clone()?;
+            let Disagreement { conflicting, first, second } = self.solve_variable(var, &solutions).1?;
+            let function = self.function.clone();
+            let (first, first_origin, second, second_origin) = (first.0, first.1, second.0, second.1);
+            Some(if conflicting {
+                TypeError::ConflictingInference {
+                    function, subject,
+                    first, first_context: first_origin.context, first_span: first_origin.span,
+                    second, second_context: second_origin.context, second_span: second_origin.span
+                }
+            } else {
+                TypeError::AmbiguousInference {
+                    function, subject,
+                    first, first_context: first_origin.context, first_span: first_origin.span,
+                    second, second_context: second_origin.context, second_span: second_origin.span
+                }
+            })
+        }).collect();
+        (solutions, errors)
+    }
+
+    fn solve_variable(&self, var: usize, solutions: &[Option<Solution>]) -> (Option<Solution>, Option<Disagreement>) {
+        let variable = &self.variables[var];
+
+        let mut required = variable.required.clone();
+        for (demanding, origin) in &variable.demanded_by {
+            if let Some(Solution { ty, demanded: true }) = solutions[*demanding] {
+                required.push((ty, origin.clone()));
+            }
+        }
+        if let Some(first) = required.first() {
+            let disagreement = required.iter().find(|(ty, _)| *ty != first.0)
+                .map(|second| Disagreement { conflicting: true, first: first.clone(), second: second.clone() });
+            return (Some(Solution { ty: first.0, demanded: true }), disagreement);
+        }
+
+        if !variable.operands.is_empty() {
+            return (self.solve_result(&variable.operands, solutions), None);
+        }
+
+        let mut hints: Vec<(Type, Origin)> = variable.hints.iter().filter_map(|(hint, origin)| match hint {
+            Term::Known(ty) => Some((*ty, origin.clone())),
+            Term::Var(hint) => solutions[*hint].as_ref().map(|solution| (solution.ty, origin.clone()))
+        }).collect();
+        // a bool converts to any numeric type, it only decides the type when nothing else does
+        if variable.numeric || hints.iter().any(|(ty, _)| *ty != Type::Bool) {
+            hints.retain(|(ty, _)| *ty != Type::Bool);
+        }
+        match hints.first() {
+            None => (None, None),
+            Some(first) => match hints.iter().find(|(ty, _)| *ty != first.0) {
+                // only i64 and f64 are left, f64 holds both
+                Some(second) => (
+                    Some(Solution { ty: Type::F64, demanded: false }),
+                    Some(Disagreement { conflicting: false, first: first.clone(), second: second.clone() })
+                ),
+                None => (Some(Solution { ty: first.0, demanded: false }), None)
+            }
+        }
+    }
+
+    // the wider of the operands, where an unsolved literal follows the other one and anything else unsolved is f64
+    fn solve_result(&self, operands: &[Term], solutions: &[Option<Solution>]) -> Option<Solution> {
+        let types: Vec<Type> = operands.iter().filter_map(|operand| match operand {
+            Term::Known(ty) => Some(*ty),
+            Term::Var(var) => match &solutions[*var] {
+                Some(solution) => Some(solution.ty),
+                None if self.variables[*var].literal => None,
+                None => Some(DEFAULT_TYPE)
+            }
+        }).collect();
+        if types.is_empty() {
+            return None;
+        }
+        let ty = if types.iter().all(|ty| matches!(ty, Type::I64 | Type::Bool)) { Type::I64 } else { Type::F64 };
+        Some(Solution { ty, demanded: false })
+    }
+}
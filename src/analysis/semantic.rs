@@ -0,0 +1,308 @@
+use core::fmt;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Formatter;
+
+use crate::syntax::ast::*;
+use crate::syntax::types::Type;
+use crate::syntax::visit::*;
+use crate::syntax::vocabulary::{get_builtin_function, BUILTIN_FUNCTIONS};
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum SemanticError {
+    // function is None for top-level expressions, a variable can also name a function to use it as a value
+    UnknownVariable { name: String, function: Option<String> },
+    UnknownFunction { name: String },
+    ArityMismatch { callee: String, expected: usize, found: usize },
+    DuplicateParameter { function: String, name: String },
+    ConflictingDeclaration { name: String, expected: usize, found: usize },
+    Redefinition { name: String },
+    // a def, extern or struct named like a builtin function such as len
+    BuiltinRedeclaration { name: String },
+    DuplicateField { ty: String, name: String },
+    // a type that is neither builtin nor a struct declared before
+    UnknownType { name: String },
+    // a const or global named like a function, a struct or another global, or the other way around
+    GlobalRedefinition { name: String },
+    ConstantAssignment { name: String },
+    NonConstantValue { name: String }
+}
+
+impl fmt::Display for SemanticError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SemanticError::UnknownVariable { name, function: Some(function) } =>
+                write!(f, "Unknown variable '{}' in function {}.", name, function),
+            SemanticError::UnknownVariable { name, function: None } =>
+                write!(f, "Unknown variable '{}', top-level expressions have no variables.", name),
+            SemanticError::UnknownFunction { name } =>
+                write!(f, "Unknown function '{}', it has to be declared with def or extern before it is called.", name),
+            SemanticError::ArityMismatch { callee, expected, found } =>
+                write!(f, "Function {} expects {} argument(s), found {}.", callee, expected, found),
+            SemanticError::DuplicateParameter { function, name } =>
+                write!(f, "Duplicate parameter '{}' in prototype of {}.", name, function),
+            SemanticError::ConflictingDeclaration { name, expected, found } =>
+                write!(f, "Function {} was declared with {} parameter(s), redeclared with {}.", name, expected, found),
+            SemanticError::Redefinition { name } =>
+                write!(f, "Function {} is already defined.", name),
+            SemanticError::BuiltinRedeclaration { name } =>
+                write!(f, "Function {} is built in and cannot be declared again.", name),
+            SemanticError::DuplicateField { ty, name } =>
+                write!(f, "Duplicate field '{}' in struct {}.", name, ty),
+            SemanticError::UnknownType { name } =>
+                write!(f, "Unknown type '{}', expected i64, f64, bool, str, [f64], fn(...) -> type or a struct declared before.", name),
+            SemanticError::GlobalRedefinition { name } =>
+                write!(f, "Global {} clashes with a function, a struct or a global of the same name.", name),
+            SemanticError::ConstantAssignment { name } =>
+                write!(f, "Cannot assign to constant {}.", name),
+            SemanticError::NonConstantValue { name } =>
+                write!(f, "The value of {} is not a constant expression, it can only use literals, constants, functions, \
+                           conversions to numbers and operators other than =, &&, || and ^.", name)
+        }
+    }
+}
+
+/*
+    Resolves names before any code is generated, so that errors are reported without LLVM:
+        - every variable against the scopes of the enclosing function and lambdas, or else against
+          the consts and globals declared so far, or else against the functions declared so far,
+          which can be used as values
+        - every call against the variables in scope and the globals, whose function types are left to the
+          TypeChecker, or else against the prototypes declared so far and the builtin functions,
+          including its number of arguments
+        - every prototype against duplicate parameters and earlier declarations of the same name
+        - every type against the structs declared so far, which also rules out a struct that
+          contains itself
+    A struct also declares its constructor, a function with one parameter per field. The value of a
+    const or global has to be a constant expression, and a const cannot be assigned.
+    Items are checked in order like codegen generates them: a function can call itself, but not
+    one that is declared after it. All the errors of an item are collected instead of stopping at
+    the first one, and declarations are kept even when broken to avoid follow-up errors.
+*/
+#[derive(Default, Clone)]
+pub struct SemanticAnalyzer {
+    // number of parameters of every declared function
+    prototypes: HashMap<String, usize>,
+    defined: HashSet<String>,
+    structs: HashSet<String>,
+    // every const and global, true for the consts
+    globals: HashMap<String, bool>,
+    scopes: Vec<HashSet<String>>,
+    function: Option<String>,
+    errors: Vec<SemanticError>
+}
+
+impl SemanticAnalyzer {
+    pub fn new() -> SemanticAnalyzer {
+        let mut analyzer = SemanticAnalyzer::default();
+        for (name, params, _) in BUILTIN_FUNCTIONS {
+            analyzer.prototypes.insert(name.to_string(), params.len());
+        }
+        analyzer
+    }
+
+    // checks a single top-level item, its declarations stay known for the next items
+    pub fn analyze(&mut self, ast: &GenericAst) -> Result<(), Vec<SemanticError>> {
+        self.visit_generic_ast(ast);
+        if self.errors.is_empty() {
+            return Ok(());
+        }
+        Err(std::mem::take(&mut self.errors))
+    }
+
+    fn is_in_scope(&self, name: &str) -> bool {
+        self.scopes.iter().any(|scope| scope.contains(name))
+    }
+
+    fn check_type(&mut self, ty: Option<Type>) {
+        match ty {
+            Some(Type::Struct(name)) if !self.structs.contains(name.as_str()) => {
+                self.errors.push(SemanticError::UnknownType { name: name.as_str().to_string() });
+            },
+            Some(Type::Function(function_type)) => {
+                for param in function_type.params() {
+                    self.check_type(Some(*param));
+                }
+                self.check_type(Some(function_type.return_type()));
+            },
+            _ => {}
+        }
+    }
+
+    // computed when compiling: LLVM folds operations on constants into a constant
+    fn is_constant_expr(&self, expr: &ExprAst) -> bool {
+        match expr {
+            ExprAst::NumberExpr { .. } | ExprAst::StringExpr { .. } => true,
+            // a function is a constant value, unknown names are reported on their own
+            ExprAst::VariableExpr { name } => self.globals.get(name) != Some(&false),
+            ExprAst::UnaryExpr { operand, .. } => self.is_constant_expr(operand),
+            ExprAst::BinaryExpr { op, lhs, rhs } =>
+                !["=", "&&", "||", "^"].contains(&op.as_str()) && self.is_constant_expr(lhs) && self.is_constant_expr(rhs),
+            // str(x) formats at runtime
+            ExprAst::CastExpr { ty, operand } => *ty != Type::Str && self.is_constant_expr(operand),
+            _ => false
+        }
+    }
+
+    // builtin functions are generated inline, they have no function to take the address of
+    fn is_function_value(&self, name: &str) -> bool {
+        self.prototypes.contains_key(name) && get_builtin_function(name).is_none()
+    }
+}
+
+impl Visitor for SemanticAnalyzer {
+    fn visit_struct(&mut self, struct_ast: &StructAst) {
+        let name = &struct_ast.name;
+        if get_builtin_function(name).is_some() {
+            self.errors.push(SemanticError::BuiltinRedeclaration { name: name.clone() });
+            return;
+        }
+
+        // the fields are checked before the struct is known, so it cannot contain itself
+        let mut seen = HashSet::new();
+        for field in &struct_ast.fields {
+            if !seen.insert(&field.name) {
+                self.errors.push(SemanticError::DuplicateField { ty: name.clone(), name: field.name.clone() });
+            }
+            self.check_type(field.ty);
+        }
+
+        if self.globals.contains_key(name) {
+            self.errors.push(SemanticError::GlobalRedefinition { name: name.clone() });
+            return;
+        }
+        // the constructor clashes with any function of the same name, even a mere extern
+        if self.prototypes.contains_key(name) {
+            self.errors.push(SemanticError::Redefinition { name: name.clone() });
+            return;
+        }
+        self.prototypes.insert(name.clone(), struct_ast.fields.len());
+        self.structs.insert(name.clone());
+    }
+
+    fn visit_prototype(&mut self, name: &str, args: &[Param], return_type: Option<Type>) {
+        if get_builtin_function(name).is_some() {
+            self.errors.push(SemanticError::BuiltinRedeclaration { name: name.to_string() });
+            return;
+        }
+
+        let mut seen = HashSet::new();
+        for arg in args {
+            if !seen.insert(&arg.name) {
+                self.errors.push(SemanticError::DuplicateParameter { function: name.to_string(), name: arg.name.clone() });
+            }
+            self.check_type(arg.ty);
+        }
+        self.check_type(return_type);
+
+        if self.structs.contains(name) {
+            self.errors.push(SemanticError::Redefinition { name: name.to_string() });
+            return;
+        }
+        if self.globals.contains_key(name) {
+            self.errors.push(SemanticError::GlobalRedefinition { name: name.to_string() });
+            return;
+        }
+
+        match self.prototypes.get(name) {
+            Some(&expected) if expected != args.len() => {
+                self.errors.push(SemanticError::ConflictingDeclaration { name: name.to_string(), expected, found: args.len() });
+            },
+            Some(_) => {},
+            None => {
+                self.prototypes.insert(name.to_string(), args.len());
+            }
+        }
+    }
+
+    fn visit_global(&mut self, global: &GlobalAst) {
+        let name = &global.name;
+        self.check_type(global.ty);
+        // the value is checked before the global is known, so it cannot use itself
+        self.visit_expr(&global.value);
+        if !self.is_constant_expr(&global.value) {
+            self.errors.push(SemanticError::NonConstantValue { name: name.clone() });
+        }
+
+        if get_builtin_function(name).is_some() {
+            self.errors.push(SemanticError::BuiltinRedeclaration { name: name.clone() });
+            return;
+        }
+        if self.prototypes.contains_key(name) || self.globals.contains_key(name) {
+            self.errors.push(SemanticError::GlobalRedefinition { name: name.clone() });
+            return;
+        }
+        self.globals.insert(name.clone(), global.constant);
+    }
+
+    fn visit_function(&mut self, proto: &FuncAst, body: &ExprAst) {
+        if let FuncAst::Prototype { name, args, .. } = proto {
+            if !self.defined.insert(name.clone()) {
+                self.errors.push(SemanticError::Redefinition { name: name.clone() });
+            }
+            self.visit_func(proto);
+
+            self.function = Some(name.clone());
+            self.scopes.push(args.iter().map(|arg| arg.name.clone()).collect());
+            self.visit_expr(body);
+            self.scopes.pop();
+            self.function = None;
+        } else {
+            panic!("Expected Prototype Ast!");
+        }
+    }
+
+    fn visit_variable_expr(&mut self, name: &str) {
+        if !self.is_in_scope(name) && !self.globals.contains_key(name) && !self.is_function_value(name) {
+            self.errors.push(SemanticError::UnknownVariable { name: name.to_string(), function: self.function.clone() });
+        }
+    }
+
+    fn visit_binary_expr(&mut self, op: &str, lhs: &ExprAst, rhs: &ExprAst) {
+        if let (ExprAst::VariableExpr { name }, "=") = (lhs, op) {
+            if !self.is_in_scope(name) && self.globals.get(name) == Some(&true) {
+                self.errors.push(SemanticError::ConstantAssignment { name: name.clone() });
+            }
+        }
+        walk_binary_expr(self, lhs, rhs);
+    }
+
+    fn visit_call_expr(&mut self, callee: &str, args: &[ExprAst]) {
+        if self.is_in_scope(callee) || self.globals.contains_key(callee) {
+            walk_call_expr(self, args);
+            return;
+        }
+        match self.prototypes.get(callee) {
+            Some(&expected) if expected != args.len() => {
+                self.errors.push(SemanticError::ArityMismatch { callee: callee.to_string(), expected, found: args.len() });
+            },
+            Some(_) => {},
+            None => self.errors.push(SemanticError::UnknownFunction { name: callee.to_string() })
+        }
+        walk_call_expr(self, args);
+    }
+
+    fn visit_lambda_expr(&mut self, params: &[Param], return_type: Option<Type>, body: &ExprAst) {
+        let mut seen = HashSet::new();
+        for param in params {
+            if !seen.insert(&param.name) {
+                self.errors.push(SemanticError::DuplicateParameter { function: "lambda".to_string(), name: param.name.clone() });
+            }
+            self.check_type(param.ty);
+        }
+        self.check_type(return_type);
+
+        // the variables of the enclosing function stay visible, they are captured
+        self.scopes.push(params.iter().map(|param| param.name.clone()).collect());
+        self.visit_expr(body);
+        self.scopes.pop();
+    }
+}
+
+// checks a whole program, returning the errors of every item
+pub fn analyze_program(items: &[GenericAst]) -> Vec<SemanticError> {
+    let mut analyzer = SemanticAnalyzer::new();
+    items.iter()
+        .filter_map(|item| analyzer.analyze(item).err())
+        .flatten()
+        .collect()
+}
@@ -0,0 +1,473 @@
+use core::fmt;
+use std::collections::HashMap;
+use std::fmt::Formatter;
+
+use crate::analysis::infer::infer_prototype;
+use crate::parse::token::Span;
+use crate::syntax::ast::*;
+use crate::syntax::types::*;
+use crate::syntax::vocabulary::BUILTIN_FUNCTIONS;
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Signature {
+    pub params: Vec<Type>,
+    pub return_type: Type
+}
+
+impl Signature {
+    // the type of the function used as a value
+    pub fn function_type(&self) -> FunctionType {
+        FunctionType::new(self.params.clone(), self.return_type)
+    }
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let params: Vec<&str> = self.params.iter().map(|param| param.name()).collect();
+        write!(f, "({}) -> {}", params.join(", "), self.return_type)
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum TypeError {
+    // context tells where the value is used, e.g. "argument 1 of f"
+    ImplicitConversion { from: Type, to: Type, context: String },
+    // a conversion that cannot be written explicitly either, e.g. from str to f64
+    InvalidConversion { from: Type, to: Type, context: String },
+    InvalidOperand { op: String, ty: Type },
+    ConflictingSignature { name: String, expected: Signature, found: Signature },
+    // subject is the inferred "parameter x" or "return type", with two of its constraints
+    // the spans are those of the expressions the types come from, when the source is known
+    ConflictingInference {
+        function: String, subject: String,
+        first: Type, first_context: String, first_span: Option<Span>,
+        second: Type, second_context: String, second_span: Option<Span>
+    },
+    AmbiguousInference {
+        function: String, subject: String,
+        first: Type, first_context: String, first_span: Option<Span>,
+        second: Type, second_context: String, second_span: Option<Span>
+    },
+    UnknownField { ty: Type, field: String },
+    // a call of a variable, which has to hold a function value taking as many arguments
+    NotCallable { name: String, ty: Type },
+    ArityMismatch { callee: String, ty: Type, found: usize }
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeError::ImplicitConversion { from, to, context } =>
+                write!(f, "Cannot implicitly convert {} to {} in {}, use {}(...) to convert explicitly.", from, to, context, to),
+            TypeError::InvalidConversion { from, to, context } =>
+                write!(f, "Cannot convert {} to {} in {}.", from, to, context),
+            TypeError::InvalidOperand { op, ty } =>
+                write!(f, "Operator {} cannot be applied to {}.", op, ty),
+            TypeError::ConflictingSignature { name, expected, found } =>
+                write!(f, "Function {} was declared as {}, redeclared as {}.", name, expected, found),
+            TypeError::ConflictingInference { function, subject, first, first_context, first_span, second, second_context, second_span } =>
+                write!(f, "Conflicting types for {} of {}: {} from {}, {} from {}. Annotate it to choose one.",
+                       subject, function, first, origin(first_context, first_span), second, origin(second_context, second_span)),
+            TypeError::AmbiguousInference { function, subject, first, first_context, first_span, second, second_context, second_span } =>
+                write!(f, "Ambiguous type for {} of {}: {} from {}, {} from {}. Annotate it to choose one.",
+                       subject, function, first, origin(first_context, first_span), second, origin(second_context, second_span)),
+            TypeError::UnknownField { ty, field } =>
+                write!(f, "Type {} has no field {}.", ty, field),
+            TypeError::NotCallable { name, ty } =>
+                write!(f, "Variable {} of type {} cannot be called.", name, ty),
+            TypeError::ArityMismatch { callee, ty, found } =>
+                write!(f, "Function value {} of type {} called with {} argument(s).", callee, ty, found)
+        }
+    }
+}
+
+// e.g. "argument 1 of g at bytes 30..34"
+fn origin(context: &str, span: &Option<Span>) -> String {
+    match span {
+        Some(span) => format!("{} at bytes {}..{}", context, span.start, span.end),
+        None => context.to_string()
+    }
+}
+
+/*
+    Gives every expression a type and returns the item with every implicit conversion written
+    out as a CastExpr, so that code generation only ever sees operands of matching types.
+        - arithmetic converts both operands to the wider of them, bools count as i64
+        - '^' is always computed on f64
+        - comparisons produce bool, '==' and '!=' compare bools without converting them
+        - '!', '&&' and '||' take the truth value of any type (not 0) and produce bool
+        - a str only takes part in calls, assignments and str(x), no operator applies to it
+        - the elements of an array are f64 and its indices i64, an array itself only takes part
+          in calls, assignments and indexing
+        - a struct is built by calling its constructor with its fields in order, it only takes
+          part in calls, assignments and field access
+        - a function used by name without a call is a function value, like a lambda; a lambda
+          takes the types it leaves out from the function type expected where it is used, or
+          else f64 for its parameters and the type of its body for its return value
+        - a const or global has the type of its annotation or else the type of its value, every
+          function declared after it sees it like a variable of the outermost scope
+        - a number literal with an integral value is an i64 where an i64 is expected or where
+          the other operand is an i64, and an f64 otherwise: 2 / 4 is still 0.5
+    The types left out in a definition are inferred from its body, see infer_prototype, and in
+    an extern they are f64. It expects names to be resolved by the SemanticAnalyzer first,
+    unknown names are left to it.
+*/
+#[derive(Default, Clone)]
+pub struct TypeChecker {
+    signatures: HashMap<String, Signature>,
+    // the fields of every struct in order, with their types
+    structs: HashMap<String, Vec<(String, Type)>>,
+    // the type of every const and global
+    globals: HashMap<String, Type>,
+    variables: HashMap<String, Type>,
+    errors: Vec<TypeError>
+}
+
+impl TypeChecker {
+    pub fn new() -> TypeChecker {
+        let mut checker = TypeChecker::default();
+        for (name, params, return_type) in BUILTIN_FUNCTIONS {
+            checker.signatures.insert(name.to_string(), Signature { params: params.to_vec(), return_type: *return_type });
+        }
+        checker
+    }
+
+    // checks a single top-level item, its signatures stay known for the next items
+    pub fn check(&mut self, ast: &GenericAst) -> Result<GenericAst, Vec<TypeError>> {
+        self.check_with_spans(ast, &[])
+    }
+
+    // same as check(), with the spans of the expressions of the item from SpannedAst to point at in errors
+    pub fn check_with_spans(&mut self, ast: &GenericAst, spans: &[Span]) -> Result<GenericAst, Vec<TypeError>> {
+        let checked = match ast {
+            GenericAst::FuncAst(func) => GenericAst::FuncAst(self.check_func(func, spans)),
+            GenericAst::StructAst(struct_ast) => GenericAst::StructAst(self.declare_struct(struct_ast)),
+            GenericAst::GlobalAst(global) => GenericAst::GlobalAst(self.declare_global(global)),
+            // the imported file is checked item by item once it is resolved
+            GenericAst::ImportAst(import) => GenericAst::ImportAst(import.clone()),
+            GenericAst::ExprAst(expr) => {
+                self.variables = self.globals.clone();
+                GenericAst::ExprAst(self.check_expr(expr, None).0)
+            }
+        };
+        if self.errors.is_empty() {
+            return Ok(checked);
+        }
+        Err(std::mem::take(&mut self.errors))
+    }
+
+    fn check_func(&mut self, func: &FuncAst, spans: &[Span]) -> FuncAst {
+        match func {
+            FuncAst::Prototype { .. } => self.declare(func),
+            FuncAst::Function { proto, body } => {
+                let proto = match infer_prototype(proto, body, spans, &self.signatures, &self.structs, &self.globals) {
+                    Ok(inferred) => self.declare(&inferred),
+                    Err(errors) => {
+                        // the body is not checked against types that could not be inferred
+                        self.errors.extend(errors);
+                        return func.clone();
+                    }
+                };
+                if let FuncAst::Prototype { name, args, return_type } = &proto {
+                    self.variables = self.globals.clone();
+                    self.variables.extend(args.iter().map(|arg| (arg.name.clone(), arg.ty.unwrap_or(DEFAULT_TYPE))));
+                    let return_type = return_type.unwrap_or(DEFAULT_TYPE);
+                    let body = self.check_expr(body, Some(return_type));
+                    let body = self.coerce(body, return_type, || format!("return value of {}", name));
+                    FuncAst::Function { proto: Box::new(proto), body: Box::new(body) }
+                } else {
+                    panic!("Expected Prototype Ast!");
+                }
+            }
+        }
+    }
+
+    // registers the signature of a prototype and returns it with the default types filled in
+    fn declare(&mut self, proto: &FuncAst) -> FuncAst {
+        if let FuncAst::Prototype { name, args, return_type } = proto {
+            let args: Vec<Param> = args.iter()
+                .map(|arg| Param { name: arg.name.clone(), ty: Some(arg.ty.unwrap_or(DEFAULT_TYPE)) })
+                .collect();
+            let return_type = return_type.unwrap_or(DEFAULT_TYPE);
+
+            let signature = Signature { params: args.iter().map(|arg| arg.ty.unwrap()).collect(), return_type };
+            match self.signatures.get(name) {
+                Some(expected) if *expected != signature => {
+                    self.errors.push(TypeError::ConflictingSignature { name: name.clone(), expected: expected.clone(), found: signature });
+                },
+                Some(_) => {},
+                None => {
+                    self.signatures.insert(name.clone(), signature);
+                }
+            }
+            FuncAst::Prototype { name: name.clone(), args, return_type: Some(return_type) }
+        } else {
+            panic!("Expected Prototype Ast!");
+        }
+    }
+
+    // registers the fields and the constructor of a struct and returns it with the default types filled in
+    fn declare_struct(&mut self, struct_ast: &StructAst) -> StructAst {
+        let fields: Vec<Param> = struct_ast.fields.iter()
+            .map(|field| Param { name: field.name.clone(), ty: Some(field.ty.unwrap_or(DEFAULT_TYPE)) })
+            .collect();
+        let name = &struct_ast.name;
+        let params = fields.iter().map(|field| field.ty.unwrap()).collect();
+        self.signatures.insert(name.clone(), Signature { params, return_type: Type::Struct(StructName::new(name)) });
+        self.structs.insert(name.clone(), fields.iter().map(|field| (field.name.clone(), field.ty.unwrap())).collect());
+        StructAst { name: name.clone(), fields }
+    }
+
+    // registers the type of a const or global and returns it with its type filled in
+    fn declare_global(&mut self, global: &GlobalAst) -> GlobalAst {
+        self.variables = self.globals.clone();
+        let value = self.check_expr(&global.value, global.ty);
+        let (value, ty) = match global.ty {
+            Some(ty) => (self.coerce(value, ty, || format!("value of {}", global.name)), ty),
+            None => value
+        };
+        self.globals.insert(global.name.clone(), ty);
+        GlobalAst { name: global.name.clone(), ty: Some(ty), value, constant: global.constant }
+    }
+
+    // returns the checked expression and its type, before any conversion to the expected type
+    fn check_expr(&mut self, expr: &ExprAst, expected: Option<Type>) -> (ExprAst, Type) {
+        match expr {
+            ExprAst::NumberExpr { number } => {
+                if expected == Some(Type::I64) && is_integral(*number) {
+                    (ExprAst::CastExpr { ty: Type::I64, operand: Box::new(expr.clone()) }, Type::I64)
+                } else {
+                    (expr.clone(), Type::F64)
+                }
+            },
+            ExprAst::StringExpr { .. } => (expr.clone(), Type::Str),
+            ExprAst::VariableExpr { name } => {
+                let ty = match self.variables.get(name) {
+                    Some(ty) => *ty,
+                    None => self.signatures.get(name).map_or(DEFAULT_TYPE, |signature| Type::Function(signature.function_type()))
+                };
+                (expr.clone(), ty)
+            },
+            ExprAst::UnaryExpr { op, operand } if op == "!" => {
+                let operand = self.check_truth_value(op, operand);
+                (ExprAst::UnaryExpr { op: op.clone(), operand: Box::new(operand) }, Type::Bool)
+            },
+            ExprAst::UnaryExpr { op, operand } => {
+                let operand = self.check_expr(operand, expected);
+                if !operand.1.is_scalar() {
+                    self.errors.push(TypeError::InvalidOperand { op: op.clone(), ty: operand.1 });
+                }
+                let ty = numeric(operand.1);
+                let operand = self.coerce(operand, ty, || format!("operand of {}", op));
+                (ExprAst::UnaryExpr { op: op.clone(), operand: Box::new(operand) }, ty)
+            },
+            ExprAst::BinaryExpr { op, lhs, rhs } => self.check_binary_expr(op, lhs, rhs, expected),
+            ExprAst::CallExpr { callee, args } => {
+                // a variable shadows the function of the same name
+                let signature = match self.variables.get(callee).copied() {
+                    Some(Type::Function(function_type)) => {
+                        if function_type.params().len() != args.len() {
+                            self.errors.push(TypeError::ArityMismatch { callee: callee.clone(), ty: Type::Function(function_type), found: args.len() });
+                        }
+                        Some(Signature { params: function_type.params().to_vec(), return_type: function_type.return_type() })
+                    },
+                    Some(ty) => {
+                        self.errors.push(TypeError::NotCallable { name: callee.clone(), ty });
+                        None
+                    },
+                    None => self.signatures.get(callee).cloned()
+                };
+                let args = args.iter().enumerate().map(|(i, arg)| {
+                    match signature.as_ref().and_then(|signature| signature.params.get(i).copied()) {
+                        Some(param_type) => {
+                            let arg = self.check_expr(arg, Some(param_type));
+                            self.coerce(arg, param_type, || format!("argument {} of {}", i + 1, callee))
+                        },
+                        None => self.check_expr(arg, None).0
+                    }
+                }).collect();
+                let return_type = signature.map_or(DEFAULT_TYPE, |signature| signature.return_type);
+                (ExprAst::CallExpr { callee: callee.clone(), args }, return_type)
+            },
+            ExprAst::CastExpr { ty, operand } => {
+                let (operand, operand_type) = self.check_expr(operand, None);
+                if operand_type == *ty {
+                    return (operand, *ty);
+                }
+                if !operand_type.converts_explicitly_to(*ty) {
+                    self.errors.push(TypeError::InvalidConversion { from: operand_type, to: *ty, context: format!("{}(...)", ty) });
+                }
+                (ExprAst::CastExpr { ty: *ty, operand: Box::new(operand) }, *ty)
+            },
+            ExprAst::BlockExpr { exprs } => {
+                let mut ty = DEFAULT_TYPE;
+                let exprs = exprs.iter().enumerate().map(|(i, expr)| {
+                    let (expr, expr_type) = self.check_expr(expr, if i + 1 == exprs.len() { expected } else { None });
+                    ty = expr_type;
+                    expr
+                }).collect();
+                (ExprAst::BlockExpr { exprs }, ty)
+            },
+            ExprAst::ArrayExpr { elements } => {
+                let elements = elements.iter().enumerate().map(|(i, element)| {
+                    let element = self.check_expr(element, Some(Type::F64));
+                    self.coerce(element, Type::F64, || format!("element {} of array", i + 1))
+                }).collect();
+                (ExprAst::ArrayExpr { elements }, Type::Array)
+            },
+            ExprAst::IndexExpr { array, index } => {
+                let (array, array_type) = self.check_expr(array, None);
+                if array_type != Type::Array {
+                    self.errors.push(TypeError::InvalidOperand { op: "[]".to_string(), ty: array_type });
+                }
+                let index = self.check_expr(index, Some(Type::I64));
+                let index = self.coerce(index, Type::I64, || "array index".to_string());
+                (ExprAst::IndexExpr { array: Box::new(array), index: Box::new(index) }, Type::F64)
+            },
+            ExprAst::FieldExpr { object, field } => {
+                let (object, object_type) = self.check_expr(object, None);
+                let field_type = match object_type {
+                    Type::Struct(name) => self.structs.get(name.as_str())
+                        .and_then(|fields| fields.iter().find(|(field_name, _)| field_name == field))
+                        .map(|(_, ty)| *ty),
+                    _ => None
+                };
+                if field_type.is_none() {
+                    self.errors.push(TypeError::UnknownField { ty: object_type, field: field.clone() });
+                }
+                (ExprAst::FieldExpr { object: Box::new(object), field: field.clone() }, field_type.unwrap_or(DEFAULT_TYPE))
+            },
+            ExprAst::LambdaExpr { params, return_type, body } => self.check_lambda_expr(params, *return_type, body, expected)
+        }
+    }
+
+    // the lambda is returned with all its types filled in, the variables in scope stay visible in its body
+    fn check_lambda_expr(&mut self, params: &[Param], return_type: Option<Type>, body: &ExprAst, expected: Option<Type>) -> (ExprAst, Type) {
+        let expected = match expected {
+            Some(Type::Function(function_type)) if function_type.params().len() == params.len() => Some(function_type),
+            _ => None
+        };
+        let params: Vec<Param> = params.iter().enumerate().map(|(i, param)| {
+            let ty = param.ty.or_else(|| expected.map(|function_type| function_type.params()[i])).unwrap_or(DEFAULT_TYPE);
+            Param { name: param.name.clone(), ty: Some(ty) }
+        }).collect();
+
+        let outer = self.variables.clone();
+        self.variables.extend(params.iter().map(|param| (param.name.clone(), param.ty.unwrap())));
+        let (body, return_type) = match return_type.or_else(|| expected.map(|function_type| function_type.return_type())) {
+            Some(return_type) => {
+                let body = self.check_expr(body, Some(return_type));
+                (self.coerce(body, return_type, || "return value of lambda".to_string()), return_type)
+            },
+            None => self.check_expr(body, None)
+        };
+        self.variables = outer;
+
+        let function_type = FunctionType::new(params.iter().map(|param| param.ty.unwrap()).collect(), return_type);
+        (ExprAst::LambdaExpr { params, return_type: Some(return_type), body: Box::new(body) }, Type::Function(function_type))
+    }
+
+    fn check_binary_expr(&mut self, op: &str, lhs: &ExprAst, rhs: &ExprAst, expected: Option<Type>) -> (ExprAst, Type) {
+        let build = |lhs: ExprAst, rhs: ExprAst| ExprAst::BinaryExpr { op: op.to_string(), lhs: Box::new(lhs), rhs: Box::new(rhs) };
+        match op {
+            "=" => {
+                // the target is a variable, an element of an array or a field of a struct
+                let (target, target_type) = self.check_expr(lhs, None);
+                let value = self.check_expr(rhs, Some(target_type));
+                let value = self.coerce(value, target_type, || format!("assignment to {}", lhs));
+                (build(target, value), target_type)
+            },
+            "&&" | "||" => {
+                let lhs = self.check_truth_value(op, lhs);
+                let rhs = self.check_truth_value(op, rhs);
+                (build(lhs, rhs), Type::Bool)
+            },
+            "^" => {
+                let lhs = self.check_expr(lhs, Some(Type::F64));
+                let lhs = self.coerce(lhs, Type::F64, || "base of ^".to_string());
+                let rhs = self.check_expr(rhs, Some(Type::F64));
+                let rhs = self.coerce(rhs, Type::F64, || "exponent of ^".to_string());
+                (build(lhs, rhs), Type::F64)
+            },
+            "==" | "!=" | "<" | ">" | "<=" | ">=" => {
+                let (lhs, rhs, _) = self.check_operands(op, lhs, rhs, None);
+                (build(lhs, rhs), Type::Bool)
+            },
+            _ => {
+                let hint = expected.filter(|ty| *ty != Type::Bool);
+                let (lhs, rhs, ty) = self.check_operands(op, lhs, rhs, hint);
+                (build(lhs, rhs), ty)
+            }
+        }
+    }
+
+    /*
+        Converts both operands to a common type. The operand that is not an integral literal is
+        checked first, so that the literal can follow its type.
+    */
+    fn check_operands(&mut self, op: &str, lhs: &ExprAst, rhs: &ExprAst, hint: Option<Type>) -> (ExprAst, ExprAst, Type) {
+        let (lhs, rhs) = if is_integral_literal(lhs) && !is_integral_literal(rhs) {
+            let rhs = self.check_expr(rhs, hint);
+            (self.check_expr(lhs, Some(numeric(rhs.1))), rhs)
+        } else {
+            let lhs = self.check_expr(lhs, hint);
+            let rhs = self.check_expr(rhs, Some(numeric(lhs.1)));
+            (lhs, rhs)
+        };
+        if let Some(ty) = [lhs.1, rhs.1].into_iter().find(|ty| !ty.is_scalar()) {
+            self.errors.push(TypeError::InvalidOperand { op: op.to_string(), ty });
+            // the result of an operator is a number, which keeps the error from spreading
+            return (lhs.0, rhs.0, DEFAULT_TYPE);
+        }
+
+        let is_bool_equality = (op == "==" || op == "!=") && lhs.1 == Type::Bool && rhs.1 == Type::Bool;
+        let ty = if is_bool_equality { Type::Bool } else { wider(numeric(lhs.1), numeric(rhs.1)) };
+        let lhs = self.coerce(lhs, ty, || format!("lhs of {}", op));
+        let rhs = self.coerce(rhs, ty, || format!("rhs of {}", op));
+        (lhs, rhs, ty)
+    }
+
+    // the truth value of an expression of any type: anything but 0 is true
+    fn check_truth_value(&mut self, op: &str, expr: &ExprAst) -> ExprAst {
+        match self.check_expr(expr, None) {
+            (expr, Type::Bool) => expr,
+            (expr, ty) if !ty.is_scalar() => {
+                self.errors.push(TypeError::InvalidOperand { op: op.to_string(), ty });
+                expr
+            },
+            (expr, _) => ExprAst::CastExpr { ty: Type::Bool, operand: Box::new(expr) }
+        }
+    }
+
+    fn coerce<F: FnOnce() -> String>(&mut self, (expr, from): (ExprAst, Type), to: Type, context: F) -> ExprAst {
+        if from == to {
+            return expr;
+        }
+        if !from.converts_explicitly_to(to) {
+            self.errors.push(TypeError::InvalidConversion { from, to, context: context() });
+        } else if !from.converts_implicitly_to(to) {
+            self.errors.push(TypeError::ImplicitConversion { from, to, context: context() });
+        }
+        ExprAst::CastExpr { ty: to, operand: Box::new(expr) }
+    }
+}
+
+// bools take part in arithmetic as i64
+fn numeric(ty: Type) -> Type {
+    match ty {
+        Type::Bool => Type::I64,
+        ty => ty
+    }
+}
+
+fn wider(lhs: Type, rhs: Type) -> Type {
+    if lhs == Type::I64 && rhs == Type::I64 { Type::I64 } else { Type::F64 }
+}
+
+pub(crate) fn is_integral(number: f64) -> bool {
+    number.fract() == 0.0 && number.abs() < i64::MAX as f64
+}
+
+fn is_integral_literal(expr: &ExprAst) -> bool {
+    matches!(expr, ExprAst::NumberExpr { number } if is_integral(*number))
+}
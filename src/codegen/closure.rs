@@ -0,0 +1,221 @@
+use std::collections::{HashMap, HashSet};
+use std::ffi::{CStr, CString};
+
+use llvm_sys::prelude::*;
+use llvm_sys::core::*;
+use llvm_sys::LLVMLinkage;
+
+use crate::codegen::llvm_generator::{build_return, llvm_type, named_function, LLVMGeneratorContext};
+use crate::codegen::runtime::{build_body, build_malloc};
+use crate::syntax::ast::*;
+use crate::syntax::types::*;
+use crate::syntax::visit::*;
+
+/*
+    Closure conversion: a function value is a pair of a function pointer and an environment,
+    { ret (i8*, params...)*, i8* } in LLVM, and the function is called with the environment as
+    its first argument.
+        - a lambda is lifted to a function named after the one it appears in, f.lambda, and its
+          environment is a struct on the heap holding the variables it captures, never freed
+        - a function used as a value is wrapped in f.closure, which ignores its null environment
+    Both names contain a '.', which no Kaleidoscope function can be named.
+    Captured variables are copied when the lambda is evaluated: assigning one in the lambda only
+    changes its own copy, like assigning a parameter only changes the copy of the callee.
+*/
+
+pub(crate) unsafe fn closure_type(context: &LLVMGeneratorContext, function_type: FunctionType) -> LLVMTypeRef {
+    let mut fields = [LLVMPointerType(lifted_function_type(context, function_type), 0), environment_type(context)];
+    LLVMStructTypeInContext(context.context, fields.as_mut_ptr(), fields.len() as u32, 0)
+}
+
+// the environment is passed as an opaque pointer, every lambda has a struct of its own behind it
+unsafe fn environment_type(context: &LLVMGeneratorContext) -> LLVMTypeRef {
+    LLVMPointerType(LLVMInt8TypeInContext(context.context), 0)
+}
+
+unsafe fn lifted_function_type(context: &LLVMGeneratorContext, function_type: FunctionType) -> LLVMTypeRef {
+    let mut params = vec![environment_type(context)];
+    params.extend(function_type.params().iter().map(|ty| llvm_type(context, *ty)));
+    let return_type = llvm_type(context, function_type.return_type());
+    LLVMFunctionType(return_type, params.as_mut_ptr(), params.len() as u32, 0)
+}
+
+// the function type is the one of the called value, a function pointer says nothing about it once pointers are opaque
+pub(crate) unsafe fn build_closure_call(context: &mut LLVMGeneratorContext, closure: LLVMValueRef, function_type: FunctionType, args: &[LLVMValueRef]) -> LLVMValueRef {
+    let function = LLVMBuildExtractValue(context.builder, closure, 0, "functiontmp\0".as_ptr() as *const i8);
+    let environment = LLVMBuildExtractValue(context.builder, closure, 1, "environmenttmp\0".as_ptr() as *const i8);
+    let function_type = lifted_function_type(context, function_type);
+
+    let mut call_args = vec![environment];
+    call_args.extend_from_slice(args);
+    LLVMBuildCall2(context.builder, function_type, function, call_args.as_mut_ptr(), call_args.len() as u32, "calltmp\0".as_ptr() as *const i8)
+}
+
+// a constant like a string literal: it needs no builder, so functions can be used as values anywhere
+pub(crate) unsafe fn build_function_value(context: &mut LLVMGeneratorContext, name: &str) -> LLVMValueRef {
+    let function = named_function(context, name);
+    if function.is_null() {
+        panic!("Unknown function referenced {}", name);
+    }
+    let function_type = context.function_types[name];
+
+    let wrapper_name = CString::new(format!("{}.closure", name)).unwrap();
+    let mut wrapper = LLVMGetNamedFunction(context.module, wrapper_name.as_ptr());
+    if wrapper.is_null() {
+        let param_count = LLVMCountParamTypes(function_type) as usize;
+        let mut param_types = vec![std::ptr::null_mut(); param_count];
+        LLVMGetParamTypes(function_type, param_types.as_mut_ptr());
+        let mut wrapper_params = vec![environment_type(context)];
+        wrapper_params.extend(param_types);
+        let wrapper_type = LLVMFunctionType(LLVMGetReturnType(function_type), wrapper_params.as_mut_ptr(), wrapper_params.len() as u32, 0);
+        wrapper = LLVMAddFunction(context.module, wrapper_name.as_ptr(), wrapper_type);
+        // every module taking the function as a value has its own copy
+        LLVMSetLinkage(wrapper, LLVMLinkage::LLVMLinkOnceODRLinkage);
+        name_environment(wrapper);
+        for i in 0..param_count {
+            let mut length = 0;
+            let param_name = LLVMGetValueName2(LLVMGetParam(function, i as u32), &mut length);
+            LLVMSetValueName2(LLVMGetParam(wrapper, i as u32 + 1), param_name, length);
+        }
+
+        build_body(context, wrapper, |context| {
+            let mut args: Vec<LLVMValueRef> = (1..=param_count).map(|i| LLVMGetParam(wrapper, i as u32)).collect();
+            let result = LLVMBuildCall2(context.builder, function_type, function, args.as_mut_ptr(), args.len() as u32, "calltmp\0".as_ptr() as *const i8);
+            LLVMBuildRet(context.builder, result);
+        });
+    }
+
+    let mut fields = [wrapper, LLVMConstNull(environment_type(context))];
+    LLVMConstStructInContext(context.context, fields.as_mut_ptr(), fields.len() as u32, 0)
+}
+
+pub(crate) unsafe fn build_lambda(context: &mut LLVMGeneratorContext, params: &[Param], return_type: Option<Type>, body: &ExprAst) -> LLVMValueRef {
+    // unannotated types are only left when the type checker did not run
+    let param_types = params.iter().map(|param| param.ty.unwrap_or(DEFAULT_TYPE)).collect();
+    let function_type = FunctionType::new(param_types, return_type.unwrap_or(DEFAULT_TYPE));
+
+    let captures: Vec<(String, LLVMValueRef)> = free_variables(params, body).into_iter()
+        .filter_map(|name| context.named_values.get(&name).map(|variable| (name, *variable)))
+        .collect();
+    let mut capture_types: Vec<LLVMTypeRef> = captures.iter().map(|(_, variable)| LLVMGetAllocatedType(*variable)).collect();
+    let environment_struct = LLVMStructTypeInContext(context.context, capture_types.as_mut_ptr(), capture_types.len() as u32, 0);
+
+    let lifted_name = CString::new(format!("{}.lambda", enclosing_function_name(context))).unwrap();
+    let lifted = LLVMAddFunction(context.module, lifted_name.as_ptr(), lifted_function_type(context, function_type));
+    name_environment(lifted);
+    for (i, param) in params.iter().enumerate() {
+        LLVMSetValueName2(LLVMGetParam(lifted, i as u32 + 1), param.name.as_ptr() as *const i8, param.name.len());
+    }
+
+    // the body only sees its parameters and its copies of the captured variables
+    let outer_values = std::mem::take(&mut context.named_values);
+    let mut inner_closure_types: HashMap<String, FunctionType> = captures.iter()
+        .filter_map(|(name, _)| context.closure_types.get(name).map(|function_type| (name.clone(), *function_type)))
+        .collect();
+    inner_closure_types.extend(closure_types(params));
+    let outer_closure_types = std::mem::replace(&mut context.closure_types, inner_closure_types);
+    // the lambda cannot call itself by name, a call of the enclosing function is a call
+    let outer_tail_recursion = context.tail_recursion.take();
+    build_body(context, lifted, |context| {
+        let environment = LLVMBuildBitCast(context.builder, LLVMGetParam(lifted, 0), LLVMPointerType(environment_struct, 0),
+                                           "environmenttmp\0".as_ptr() as *const i8);
+        for (i, (name, _)) in captures.iter().enumerate() {
+            let field = LLVMBuildStructGEP2(context.builder, environment_struct, environment, i as u32, "capturetmp\0".as_ptr() as *const i8);
+            let value = LLVMBuildLoad2(context.builder, capture_types[i], field, context.c_name(name));
+            build_variable(context, name, value);
+        }
+        for (i, param) in params.iter().enumerate() {
+            build_variable(context, &param.name, LLVMGetParam(lifted, i as u32 + 1));
+        }
+        build_return(context, body);
+    });
+    context.named_values = outer_values;
+    context.closure_types = outer_closure_types;
+    context.tail_recursion = outer_tail_recursion;
+
+    let environment = if captures.is_empty() {
+        LLVMConstNull(environment_type(context))
+    } else {
+        let memory = build_malloc(context, LLVMSizeOf(environment_struct));
+        let pointer = LLVMBuildBitCast(context.builder, memory, LLVMPointerType(environment_struct, 0), "environmenttmp\0".as_ptr() as *const i8);
+        for (i, (name, variable)) in captures.iter().enumerate() {
+            let value = LLVMBuildLoad2(context.builder, capture_types[i], *variable, context.c_name(name));
+            let field = LLVMBuildStructGEP2(context.builder, environment_struct, pointer, i as u32, "capturetmp\0".as_ptr() as *const i8);
+            LLVMBuildStore(context.builder, value, field);
+        }
+        memory
+    };
+
+    let closure = LLVMGetUndef(closure_type(context, function_type));
+    let closure = LLVMBuildInsertValue(context.builder, closure, lifted, 0, "closuretmp\0".as_ptr() as *const i8);
+    LLVMBuildInsertValue(context.builder, closure, environment, 1, "closuretmp\0".as_ptr() as *const i8)
+}
+
+// the parameters holding function values, with their types
+pub(crate) fn closure_types(params: &[Param]) -> HashMap<String, FunctionType> {
+    params.iter().filter_map(|param| match param.ty {
+        Some(Type::Function(function_type)) => Some((param.name.clone(), function_type)),
+        _ => None
+    }).collect()
+}
+
+unsafe fn name_environment(function: LLVMValueRef) {
+    LLVMSetValueName2(LLVMGetParam(function, 0), "environment".as_ptr() as *const i8, "environment".len());
+}
+
+// a stack slot for a variable of the lifted function, which '=' can store to
+unsafe fn build_variable(context: &mut LLVMGeneratorContext, name: &str, value: LLVMValueRef) {
+    let variable = LLVMBuildAlloca(context.builder, LLVMTypeOf(value), context.c_name(name));
+    LLVMBuildStore(context.builder, value, variable);
+    context.named_values.insert(name.to_string(), variable);
+}
+
+// empty for a lambda in a top-level expression, which has no function around it
+unsafe fn enclosing_function_name(context: &LLVMGeneratorContext) -> String {
+    let block = LLVMGetInsertBlock(context.builder);
+    if block.is_null() {
+        return String::new();
+    }
+    let mut length = 0;
+    let name = LLVMGetValueName2(LLVMGetBasicBlockParent(block), &mut length);
+    CStr::from_ptr(name).to_str().unwrap().to_string()
+}
+
+// the names a lambda uses without binding them, in the order they first appear
+fn free_variables(params: &[Param], body: &ExprAst) -> Vec<String> {
+    let mut free_variables = FreeVariables { bound: Vec::new(), names: Vec::new() };
+    free_variables.visit_lambda_expr(params, None, body);
+    free_variables.names
+}
+
+struct FreeVariables {
+    bound: Vec<HashSet<String>>,
+    names: Vec<String>
+}
+
+impl FreeVariables {
+    fn use_name(&mut self, name: &str) {
+        let is_bound = self.bound.iter().any(|scope| scope.contains(name));
+        if !is_bound && !self.names.iter().any(|free| free == name) {
+            self.names.push(name.to_string());
+        }
+    }
+}
+
+impl Visitor for FreeVariables {
+    fn visit_variable_expr(&mut self, name: &str) {
+        self.use_name(name);
+    }
+
+    // a called variable holds a function value, which has to be captured as well
+    fn visit_call_expr(&mut self, callee: &str, args: &[ExprAst]) {
+        self.use_name(callee);
+        walk_call_expr(self, args);
+    }
+
+    fn visit_lambda_expr(&mut self, params: &[Param], _return_type: Option<Type>, body: &ExprAst) {
+        self.bound.push(params.iter().map(|param| param.name.clone()).collect());
+        self.visit_expr(body);
+        self.bound.pop();
+    }
+}
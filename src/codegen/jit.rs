@@ -0,0 +1,132 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::mem::{size_of, MaybeUninit};
+use std::sync::Once;
+
+use llvm_sys::core::*;
+use llvm_sys::execution_engine::*;
+use llvm_sys::prelude::*;
+use llvm_sys::target::*;
+
+use crate::codegen::llvm_generator::LLVMGeneratorContext;
+
+/* Learning Note:
+    MCJIT compiles a whole module to machine code in memory, the functions are then called through
+    their address like any C function. External functions such as malloc and printf are resolved
+    against the symbols of the process.
+    A module given to MCJIT is owned by the execution engine and cannot be changed anymore, so every
+    compilation takes a copy: the context keeps generating code into its own module, and the next
+    compilation sees the functions added since. The global variables keep their memory instead:
+    in the copy, a global compiled before becomes a declaration mapped to the address it got the
+    first time, whose engine is kept alive. That is how a REPL line sees the value stored by the
+    line before.
+    The code is optimized (OptLevel 2), which turns the calls marked `tail` into jumps.
+*/
+#[derive(Default)]
+pub struct Jit {
+    engines: Vec<LLVMExecutionEngineRef>,
+    // the address of every global variable, in the engine that compiled it first
+    globals: HashMap<String, u64>
+}
+
+static INITIALIZE_NATIVE_TARGET: Once = Once::new();
+
+impl Jit {
+    pub fn new() -> Jit {
+        INITIALIZE_NATIVE_TARGET.call_once(|| unsafe {
+            LLVMLinkInMCJIT();
+            LLVM_InitializeNativeTarget();
+            LLVM_InitializeNativeAsmPrinter();
+        });
+        Jit::default()
+    }
+
+    // compiles the module of the context as it is now, the functions compiled before are replaced
+    pub fn compile(&mut self, context: &LLVMGeneratorContext) -> Result<(), String> {
+        unsafe {
+            let module = LLVMCloneModule(context.module);
+            let mapped = self.declare_compiled_globals(module);
+
+            let mut options = MaybeUninit::<LLVMMCJITCompilerOptions>::zeroed();
+            LLVMInitializeMCJITCompilerOptions(options.as_mut_ptr(), size_of::<LLVMMCJITCompilerOptions>());
+            let mut options = options.assume_init();
+            options.OptLevel = 2;
+
+            let mut engine = std::ptr::null_mut();
+            let mut error = std::ptr::null_mut();
+            if LLVMCreateMCJITCompilerForModule(&mut engine, module, &mut options, size_of::<LLVMMCJITCompilerOptions>(), &mut error) != 0 {
+                let message = CStr::from_ptr(error).to_str().unwrap().to_string();
+                LLVMDisposeMessage(error);
+                return Err(message);
+            }
+            for (declaration, address) in mapped {
+                LLVMAddGlobalMapping(engine, declaration, address as *mut _);
+            }
+            self.engines.push(engine);
+
+            for global in variable_globals(module) {
+                if let Entry::Vacant(entry) = self.globals.entry(value_name(global)) {
+                    let c_name = CString::new(entry.key().as_str()).unwrap();
+                    entry.insert(LLVMGetGlobalValueAddress(engine, c_name.as_ptr()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // replaces the globals compiled before with declarations, returned with the address to map them to
+    unsafe fn declare_compiled_globals(&self, module: LLVMModuleRef) -> Vec<(LLVMValueRef, u64)> {
+        let mut mapped = Vec::new();
+        for global in variable_globals(module) {
+            let name = value_name(global);
+            if let Some(address) = self.globals.get(&name) {
+                let declaration = LLVMAddGlobal(module, LLVMGlobalGetValueType(global), "\0".as_ptr() as *const i8);
+                LLVMReplaceAllUsesWith(global, declaration);
+                LLVMDeleteGlobal(global);
+                LLVMSetValueName2(declaration, name.as_ptr() as *const i8, name.len());
+                mapped.push((declaration, *address));
+            }
+        }
+        mapped
+    }
+
+    // the machine code of a function in the last compilation, to be transmuted to an extern "C" fn of its signature
+    pub fn function_address(&self, name: &str) -> Option<u64> {
+        let engine = *self.engines.last()?;
+        let name = CString::new(name).unwrap();
+        match unsafe { LLVMGetFunctionAddress(engine, name.as_ptr()) } {
+            0 => None,
+            address => Some(address)
+        }
+    }
+}
+
+impl Drop for Jit {
+    fn drop(&mut self) {
+        for engine in &self.engines {
+            unsafe {
+                LLVMDisposeExecutionEngine(*engine);
+            }
+        }
+    }
+}
+
+// the globals that can be assigned, constants are compiled again with the same value
+unsafe fn variable_globals(module: LLVMModuleRef) -> Vec<LLVMValueRef> {
+    let mut globals = Vec::new();
+    let mut global = LLVMGetFirstGlobal(module);
+    while !global.is_null() {
+        if LLVMIsGlobalConstant(global) == 0 {
+            globals.push(global);
+        }
+        global = LLVMGetNextGlobal(global);
+    }
+    globals
+}
+
+unsafe fn value_name(value: LLVMValueRef) -> String {
+    let mut length = 0;
+    let name = LLVMGetValueName2(value, &mut length);
+    CStr::from_ptr(name).to_str().unwrap().to_string()
+}
@@ -1,28 +1,62 @@
-use std::collections::HashMap;
-use std::ffi::{CStr};
+use std::collections::{HashMap, HashSet};
+use std::ffi::{c_void, CStr, CString};
 use std::os::raw::{c_char};
-use llvm_sys::analysis::{LLVMVerifierFailureAction, LLVMVerifyFunction};
+use llvm_sys::analysis::{LLVMVerifierFailureAction, LLVMVerifyFunction, LLVMVerifyModule};
 
 // llvm-sys
 use llvm_sys::prelude::*;
 use llvm_sys::core::*;
-use llvm_sys::LLVMRealPredicate::{LLVMRealOGT, LLVMRealOLT};
+use llvm_sys::linker::LLVMLinkModules2;
+use llvm_sys::LLVMRealPredicate::{LLVMRealOEQ, LLVMRealOGE, LLVMRealOGT, LLVMRealOLE, LLVMRealOLT, LLVMRealUNE};
+use llvm_sys::LLVMIntPredicate::{LLVMIntEQ, LLVMIntNE, LLVMIntSGE, LLVMIntSGT, LLVMIntSLE, LLVMIntSLT};
+use llvm_sys::{LLVMDiagnosticSeverity, LLVMTypeKind};
 
+use crate::codegen::closure::*;
 use crate::codegen::ir_generator::IRGenerator;
+use crate::codegen::runtime::*;
 use crate::syntax::ast::*;
-use crate::syntax::vocabulary::SYMBOL_OP_CHARS;
+use crate::syntax::types::*;
+use crate::syntax::vocabulary::get_builtin_function;
 
 pub struct LLVMGeneratorContext {
-    context: LLVMContextRef,
-    module: LLVMModuleRef,
-    builder: LLVMBuilderRef,
-    named_values: HashMap<String, LLVMValueRef>,
-    function_types: HashMap<String, LLVMTypeRef>
+    pub(crate) context: LLVMContextRef,
+    pub(crate) module: LLVMModuleRef,
+    pub(crate) builder: LLVMBuilderRef,
+    pub(crate) named_values: HashMap<String, LLVMValueRef>,
+    pub(crate) function_types: HashMap<String, LLVMTypeRef>,
+    c_names: HashMap<String, CString>,
+    // pointers to the global constant of every string literal
+    pub(crate) strings: HashMap<String, LLVMValueRef>,
+    // the LLVM type and the field names of every struct
+    structs: HashMap<String, (LLVMTypeRef, Vec<String>)>,
+    // the folded value of every const
+    constants: HashMap<String, LLVMValueRef>,
+    // every global variable, loaded and stored like a stack slot
+    globals: HashMap<String, LLVMValueRef>,
+    // the type of every variable in scope holding a function value, which a call through it is built from
+    pub(crate) closure_types: HashMap<String, FunctionType>,
+    // the same for the consts and globals
+    global_closure_types: HashMap<String, FunctionType>,
+    // set while generating a function that calls itself in tail position, see build_return
+    pub(crate) tail_recursion: Option<TailRecursion>
+}
+
+// the block a self tail call jumps back to, and the stack slots of the parameters it stores its arguments to
+pub(crate) struct TailRecursion {
+    name: String,
+    block: LLVMBasicBlockRef,
+    params: Vec<LLVMValueRef>
 }
 
 impl LLVMGeneratorContext
 {
     pub fn new() -> LLVMGeneratorContext
+    {
+        Self::with_module_name("default_module")
+    }
+
+    // the module is named after the file it is generated from
+    pub fn with_module_name(name: &str) -> LLVMGeneratorContext
     {
         unsafe
             {
@@ -34,10 +68,17 @@ impl LLVMGeneratorContext
             */
             let context = LLVMContextCreate();
             let builder = LLVMCreateBuilderInContext(context);
-            let module = LLVMModuleCreateWithNameInContext(
-                "default_module\0".as_ptr() as *const i8, context);
+            let module_name = CString::new(name).unwrap();
+            let module = LLVMModuleCreateWithNameInContext(module_name.as_ptr(), context);
             let named_values = HashMap::new();
             let function_types = HashMap::new();
+            let c_names = HashMap::new();
+            let strings = HashMap::new();
+            let structs = HashMap::new();
+            let constants = HashMap::new();
+            let globals = HashMap::new();
+            let closure_types = HashMap::new();
+            let global_closure_types = HashMap::new();
 
             LLVMGeneratorContext
             {
@@ -45,19 +86,302 @@ impl LLVMGeneratorContext
                 module,
                 builder,
                 named_values,
-                function_types
+                function_types,
+                c_names,
+                strings,
+                structs,
+                constants,
+                globals,
+                closure_types,
+                global_closure_types,
+                tail_recursion: None
             }
         }
     }
 
+    /*
+        LLVM expects nul-terminated names. Identifiers are interned as C strings once, instead of
+        allocating a new one every time a variable or function is referenced.
+        The pointer stays valid as long as the context: moving a CString does not move its buffer.
+    */
+    pub(crate) fn c_name(&mut self, name: &str) -> *const c_char {
+        if !self.c_names.contains_key(name) {
+            self.c_names.insert(name.to_string(), CString::new(name).unwrap());
+        }
+        self.c_names[name].as_ptr()
+    }
+
     pub fn get_module_as_string(&self) -> String {
         unsafe {
             return CStr::from_ptr(LLVMPrintModuleToString(self.module)).to_str().unwrap().to_string();
         }
     }
+
+    // checks the whole module, a function is only checked once generated, which prints its errors without failing
+    pub fn verify(&self) -> Result<(), String> {
+        unsafe {
+            let mut message = std::ptr::null_mut();
+            let failed = LLVMVerifyModule(self.module, LLVMVerifierFailureAction::LLVMReturnStatusAction, &mut message) != 0;
+            let description = CStr::from_ptr(message).to_string_lossy().trim_end().to_string();
+            LLVMDisposeMessage(message);
+            if failed {
+                return Err(format!("Generated an invalid module: {}", description));
+            }
+            Ok(())
+        }
+    }
+
+    /*
+        Adds name.run, a function without parameters or value that calls the function name and drops
+        what it returns. A struct or a closure is returned in registers or through a hidden pointer
+        to memory of the caller, depending on its layout, which only LLVM knows; a caller outside
+        LLVM, like the REPL, runs such a function through the wrapper for its effects instead.
+    */
+    pub fn build_discarding_wrapper(&mut self, name: &str) -> Option<String> {
+        unsafe {
+            let function = LLVMGetNamedFunction(self.module, self.c_name(name));
+            if function.is_null() {
+                return None;
+            }
+            let wrapper_name = format!("{}.run", name);
+            let wrapper_type = LLVMFunctionType(LLVMVoidTypeInContext(self.context), std::ptr::null_mut(), 0, 0);
+            let wrapper = LLVMAddFunction(self.module, self.c_name(&wrapper_name), wrapper_type);
+            let basic_block = LLVMAppendBasicBlockInContext(self.context, wrapper, "entry\0".as_ptr() as *const i8);
+            LLVMPositionBuilderAtEnd(self.builder, basic_block);
+            LLVMBuildCall2(self.builder, LLVMGlobalGetValueType(function), function, std::ptr::null_mut(), 0, "\0".as_ptr() as *const i8);
+            LLVMBuildRetVoid(self.builder);
+            Some(wrapper_name)
+        }
+    }
+
+    /* Learning Note:
+        An imported file is generated into a module of its own, which is then linked into the
+        module importing it: LLVMLinkModules2 moves the functions and globals over and destroys
+        the imported module. Both modules belong to the same LLVM context, so they share their
+        types, and a call declared in one is resolved against the definition in the other.
+        The runtime functions are generated into every module that uses them. They are linkonce_odr,
+        like the inline functions of a C++ header, so that the linker keeps one copy of them instead
+        of reporting a symbol defined twice.
+        Linking creates new values, so the consts and globals of the imported module are looked up
+        again by name afterwards. Whatever was generated is linked, the items of the file that
+        failed to check are left out by the caller.
+    */
+    pub fn link_module<F>(&mut self, name: &str, generate: F) -> Result<(), String>
+        where F: FnOnce(&mut LLVMGeneratorContext) {
+        unsafe {
+            let outer_block = LLVMGetInsertBlock(self.builder);
+            let outer_strings = std::mem::take(&mut self.strings);
+            let outer_names: HashSet<String> = self.constants.keys().chain(self.globals.keys()).cloned().collect();
+            let module_name = CString::new(name).unwrap();
+            let module = LLVMModuleCreateWithNameInContext(module_name.as_ptr(), self.context);
+            let outer_module = std::mem::replace(&mut self.module, module);
+
+            generate(self);
+
+            self.module = outer_module;
+            self.strings = outer_strings;
+            if outer_block.is_null() {
+                LLVMClearInsertionPosition(self.builder);
+            } else {
+                LLVMPositionBuilderAtEnd(self.builder, outer_block);
+            }
+            let linked = self.link(module, name);
+
+            let imported_names: Vec<String> = self.constants.keys().chain(self.globals.keys())
+                .filter(|name| !outer_names.contains(*name))
+                .cloned()
+                .collect();
+            for name in imported_names {
+                let global = match linked {
+                    Ok(()) => LLVMGetNamedGlobal(self.module, self.c_name(&name)),
+                    Err(_) => std::ptr::null_mut()
+                };
+                if global.is_null() {
+                    self.constants.remove(&name);
+                    self.globals.remove(&name);
+                } else if let Some(value) = self.constants.get_mut(&name) {
+                    *value = LLVMGetInitializer(global);
+                } else {
+                    self.globals.insert(name, global);
+                }
+            }
+            linked
+        }
+    }
+
+    // links a module into the module of the context, which destroys it even when linking fails
+    unsafe fn link(&mut self, module: LLVMModuleRef, name: &str) -> Result<(), String> {
+        // the default handler exits the process on a linker error
+        let mut errors = Vec::<String>::new();
+        LLVMContextSetDiagnosticHandler(self.context, Some(record_error), &mut errors as *mut Vec<String> as *mut c_void);
+        let failed = LLVMLinkModules2(self.module, module) != 0;
+        LLVMContextSetDiagnosticHandler(self.context, None, std::ptr::null_mut());
+        if failed {
+            return Err(format!("Could not link {}: {}", name, errors.join(" ")));
+        }
+        Ok(())
+    }
+}
+
+extern "C" fn record_error(info: LLVMDiagnosticInfoRef, errors: *mut c_void) {
+    unsafe {
+        if LLVMGetDiagInfoSeverity(info) != LLVMDiagnosticSeverity::LLVMDSError {
+            return;
+        }
+        let description = LLVMGetDiagInfoDescription(info);
+        (*(errors as *mut Vec<String>)).push(CStr::from_ptr(description).to_str().unwrap().to_string());
+        LLVMDisposeMessage(description);
+    }
 }
 
+/*
+    Code is generated straight from the borrowed AST: every node generates its children by reference,
+    so generating a tree of n nodes takes O(n) time regardless of its depth.
+*/
 impl IRGenerator<LLVMGeneratorContext, LLVMValueRef> for GenericAst
+{
+    unsafe fn generate(&self, context: &mut LLVMGeneratorContext) -> LLVMValueRef {
+        match self {
+            GenericAst::ExprAst(expr) => expr.generate(context),
+            GenericAst::FuncAst(func) => {
+                let func_ir = func.generate(context);
+                // only an extern is given the body of a runtime function, a def has its own
+                if let FuncAst::Prototype { name, .. } = func {
+                    define_runtime_function(context, name, func_ir);
+                }
+                func_ir
+            },
+            GenericAst::StructAst(struct_ast) => build_struct(context, struct_ast),
+            GenericAst::GlobalAst(global) => build_global(context, global),
+            GenericAst::ImportAst(import) => panic!("Import of {} is not resolved, its module has to be linked instead", import.path)
+        }
+    }
+}
+
+/* Learning Note:
+    A const or global is a global variable of the module, @counter = global i64 0, which any
+    function can load and store like the stack slot of a parameter. Its initializer has to be a
+    constant: the builder folds an operation on constants into a constant instead of emitting an
+    instruction, so the value is generated like any expression and computed while compiling.
+    A const is marked constant and its uses are replaced with its value, so that 2 * pi in a
+    function is folded as well.
+*/
+unsafe fn build_global(context: &mut LLVMGeneratorContext, global: &GlobalAst) -> LLVMValueRef {
+    let value = global.value.generate(context);
+    // checked by the SemanticAnalyzer
+    if LLVMIsConstant(value) == 0 {
+        panic!("The value of {} is not a constant", global.name);
+    }
+    let variable = LLVMAddGlobal(context.module, LLVMTypeOf(value), context.c_name(&global.name));
+    LLVMSetInitializer(variable, value);
+    if let Some(Type::Function(function_type)) = global.ty {
+        context.global_closure_types.insert(global.name.clone(), function_type);
+    }
+    if global.constant {
+        LLVMSetGlobalConstant(variable, 1);
+        context.constants.insert(global.name.clone(), value);
+    } else {
+        context.globals.insert(global.name.clone(), variable);
+    }
+    variable
+}
+
+// the function type of a variable in scope, or else of a const or global, that is called
+fn closure_type_of(context: &LLVMGeneratorContext, name: &str) -> FunctionType {
+    let closure_types = if context.named_values.contains_key(name) { &context.closure_types } else { &context.global_closure_types };
+    match closure_types.get(name) {
+        Some(function_type) => *function_type,
+        // checked by the TypeChecker, which gives every variable and global its type
+        None => panic!("Unknown function type of {}", name)
+    }
+}
+
+// the stack slot of a variable in scope, or else a global variable, with the type of its value
+unsafe fn variable_address(context: &LLVMGeneratorContext, name: &str) -> Option<(LLVMValueRef, LLVMTypeRef)> {
+    match context.named_values.get(name) {
+        Some(variable) => Some((*variable, LLVMGetAllocatedType(*variable))),
+        None => context.globals.get(name).map(|global| (*global, LLVMGlobalGetValueType(*global)))
+    }
+}
+
+/*
+    A declared function, null when there is none. Linking a module drops the externs it declares
+    but never calls, they are declared again from their type when a later item calls them.
+*/
+pub(crate) unsafe fn named_function(context: &mut LLVMGeneratorContext, name: &str) -> LLVMValueRef {
+    let function = LLVMGetNamedFunction(context.module, context.c_name(name));
+    match context.function_types.get(name).copied() {
+        Some(function_type) if function.is_null() => LLVMAddFunction(context.module, context.c_name(name), function_type),
+        _ => function
+    }
+}
+
+/* Learning Note:
+    A struct is a named LLVM struct type, %struct.Point = type { double, double }, prefixed like
+    clang does so that it cannot clash with the types of the runtime. Its values are SSA values
+    of that type: they are passed and returned by value, so a function gets a copy, while an
+    array field is a pointer and stays shared. Fields are read with extractvalue and replaced
+    with insertvalue, which both produce a new value instead of writing to memory.
+    The struct is generated as its constructor Point(x, y), which is printed by the REPL.
+*/
+unsafe fn build_struct(context: &mut LLVMGeneratorContext, struct_ast: &StructAst) -> LLVMValueRef {
+    let name = &struct_ast.name;
+    let struct_type = LLVMStructCreateNamed(context.context, context.c_name(&format!("struct.{}", name)));
+    let mut field_types: Vec<LLVMTypeRef> = struct_ast.fields.iter()
+        .map(|field| llvm_type(context, field.ty.unwrap_or(DEFAULT_TYPE)))
+        .collect();
+    LLVMStructSetBody(struct_type, field_types.as_mut_ptr(), field_types.len() as u32, 0);
+    let field_names = struct_ast.fields.iter().map(|field| field.name.clone()).collect();
+    context.structs.insert(name.clone(), (struct_type, field_names));
+
+    let proto = FuncAst::Prototype {
+        name: name.clone(),
+        args: struct_ast.fields.clone(),
+        return_type: Some(Type::Struct(StructName::new(name)))
+    };
+    let constructor = proto.generate(context);
+    build_body(context, constructor, |context| {
+        let mut value = LLVMGetUndef(struct_type);
+        for idx in 0..LLVMCountParams(constructor) {
+            value = LLVMBuildInsertValue(context.builder, value, LLVMGetParam(constructor, idx), idx, "structtmp\0".as_ptr() as *const i8);
+        }
+        LLVMBuildRet(context.builder, value);
+    });
+    constructor
+}
+
+// the position of a field in the struct type of value
+unsafe fn field_index(context: &LLVMGeneratorContext, value: LLVMValueRef, field: &str) -> u32 {
+    let value_type = LLVMTypeOf(value);
+    context.structs.values()
+        .find(|(struct_type, _)| *struct_type == value_type)
+        .and_then(|(_, field_names)| field_names.iter().position(|name| name == field))
+        .unwrap_or_else(|| panic!("Unknown field {}", field)) as u32
+}
+
+/*
+    p.x = v replaces the field in the value of p and stores the new value back to p, through every
+    level of a nested target like p.q.x = v.
+*/
+unsafe fn build_field_store(context: &mut LLVMGeneratorContext, target: &ExprAst, value: LLVMValueRef) {
+    match target {
+        ExprAst::VariableExpr {name} => match variable_address(context, name) {
+            Some((variable, _)) => {
+                LLVMBuildStore(context.builder, value, variable);
+            },
+            None => panic!("Unknown variable name: {}", name)
+        },
+        ExprAst::FieldExpr {object, field} => {
+            let object_ir = object.generate(context);
+            let index = field_index(context, object_ir, field);
+            let updated = LLVMBuildInsertValue(context.builder, object_ir, value, index, "structtmp\0".as_ptr() as *const i8);
+            build_field_store(context, object, updated);
+        },
+        _ => panic!("Destination of '=' must be a variable, an array element or a field")
+    }
+}
+
+impl IRGenerator<LLVMGeneratorContext, LLVMValueRef> for ExprAst
 {
     /*
         Learning Notes:
@@ -70,166 +394,506 @@ impl IRGenerator<LLVMGeneratorContext, LLVMValueRef> for GenericAst
     */
     unsafe fn generate(&self, context: &mut LLVMGeneratorContext) -> LLVMValueRef {
         match self {
-            GenericAst::ExprAst(expr) => {
-                match expr {
-                    ExprAst::NumberExpr {number} => {
-                        LLVMConstReal(LLVMBFloatTypeInContext(context.context), *number)
+            ExprAst::NumberExpr {number} => {
+                LLVMConstReal(llvm_type(context, Type::F64), *number)
+            },
+            ExprAst::StringExpr {value} => build_string_constant(context, value),
+            ExprAst::VariableExpr {name} => {
+                if let Some((variable, variable_type)) = variable_address(context, name) {
+                    LLVMBuildLoad2(context.builder,
+                                   variable_type,
+                                   variable,
+                                   context.c_name(name))
+                } else if let Some(value) = context.constants.get(name) {
+                    *value
+                } else {
+                    // checked by the SemanticAnalyzer: a name that is not a variable is a function
+                    build_function_value(context, name)
+                }
+            },
+            ExprAst::UnaryExpr {op, operand} => {
+                let operand_ir = operand.generate(context);
+
+                match op.as_str() {
+                    "-" if is_float(operand_ir) => {
+                        LLVMBuildFNeg(context.builder, operand_ir, "negtmp\0".as_ptr() as *const i8)
                     },
-                    ExprAst::VariableExpr {name} => {
-                        if let Some(value) = context.named_values.get(name) {
-                            *value
-                        } else {
-                            panic!("Unknown variable name: {}", name);
-                        }
+                    "-" => {
+                        LLVMBuildNeg(context.builder, operand_ir, "negtmp\0".as_ptr() as *const i8)
                     },
-                    ExprAst::BinaryExpr {op, lhs, rhs} => {
-                        let lhs_ir = GenericAst::ExprAst((**lhs).clone()).generate(context);
-                        let rhs_ir = GenericAst::ExprAst((**rhs).clone()).generate(context);
-
-                        if SYMBOL_OP_CHARS.contains(op) {
-                            match op {
-                                '+' => {
-                                    /*
-                                        Learning Note: Why is the builder passed in ?
-                                        The builder is used to construct LLVM instructions within a basic block.
-                                        The builder keeps track of the current insertion point in the basic block and
-                                        is responsible for generating and appending the LLVM instruction to the block.
-                                    */
-                                    LLVMBuildFAdd(context.builder, lhs_ir, rhs_ir, "addtmp\0".as_ptr() as *const i8)
-                                },
-                                '-' => {
-                                    LLVMBuildFSub(context.builder, lhs_ir, rhs_ir, "subtmp\0".as_ptr() as *const i8)
-                                },
-                                '*' => {
-                                    LLVMBuildFMul(context.builder, lhs_ir, rhs_ir, "multmp\0".as_ptr() as *const i8)
-                                },
-                                '/' => {
-                                    LLVMBuildFDiv(context.builder, lhs_ir, rhs_ir, "divtmp\0".as_ptr() as *const i8)
-                                },
-                                '>' => {
-                                    LLVMBuildFCmp(context.builder, LLVMRealOGT, lhs_ir, rhs_ir, "cmpgt\0".as_ptr() as *const i8)
-                                },
-                                '<' => {
-                                    LLVMBuildFCmp(context.builder, LLVMRealOLT, lhs_ir, rhs_ir, "cmplt\0".as_ptr() as *const i8)
-                                },
-                                _ => {
-                                    panic!("Implementation missing for operator {}", op)
-                                }
-                            }
-                        }
-                        else {
-                            panic!("Unknown operator {}", op)
-                        }
+                    "!" => {
+                        let is_true = build_truth_test(context, operand_ir);
+                        LLVMBuildNot(context.builder, is_true, "nottmp\0".as_ptr() as *const i8)
                     },
-                    ExprAst::CallExpr {callee, args} => {
-                        let func = LLVMGetNamedFunction(context.module, callee.as_ptr() as *const i8);
-                        if func.is_null() {
-                            panic!("Unknown function referenced {}", callee);
-                        }
-
-                        let call_arg_count = LLVMCountParams(func);
-                        if (call_arg_count as usize) != args.len() {
-                            panic!("Function {} called with unexpected number of arguments", callee);
-                        }
-
-                        let mut generated_args = Vec::new();
-
-                        // let mut type_arr: [LLVMTypeRef; 2] = [LLVMBFloatTypeInContext(context.context), LLVMBFloatTypeInContext(context.context)];
-                        // let _params = LLVMGetParamTypes(LLVMTypeOf(func), type_arr.as_mut_ptr());
-
-                        for arg in args.iter() {
-                            println!("Generated arg: {:?}", arg);
-                            generated_args.push(GenericAst::ExprAst(arg.clone()).generate(context));
-                        }
-
-                        let function_type = *context.function_types.get(callee).unwrap();
-
-                        LLVMBuildCall2(context.builder,
-                                       function_type,
-                                       func,
-                                       generated_args.as_mut_ptr(),
-                                       call_arg_count,
-                                       "calltmp\0".as_ptr() as *const i8)
+                    _ => {
+                        panic!("Unknown unary operator {}", op)
                     }
                 }
             },
-            GenericAst::FuncAst(func) => {
-                match func {
-                    FuncAst::Function {proto, body} => {
-                        let proto_unboxed = &**proto;
-
-                        if let FuncAst::Prototype { name, args : _} = proto_unboxed {
-                            let mut func_proto = LLVMGetNamedFunction(
-                                context.module,
-                                name.as_ptr() as *const i8);
-                            if func_proto.is_null() {
-                                func_proto = GenericAst::FuncAst((**proto).clone()).generate(context);
-                            }
-
-                            // TODO (saif) check if null again ?!
-                            // TODO (saif) check if empty ?!
-                            // let first_block = LLVMGetFirstBasicBlock(func_proto);
-                            // TODO (saif) check for redefinition ?
-
-                            let basic_block = LLVMAppendBasicBlockInContext(
-                                context.context,
-                                func_proto,
-                                "entry\0".as_ptr() as *const i8);
-                            LLVMPositionBuilderAtEnd(context.builder, basic_block);
-
-                            // TODO (saif) consider clearing the named_values map ?
-                            //context.named_values.clear();
-                            for idx in 0..LLVMCountParams(func_proto)  {
-                                let param = LLVMGetParam(func_proto, idx);
-                                let mut length: usize = 0;
-                                let name_buffer: *const c_char = unsafe { LLVMGetValueName2(param, &mut length) };
-                                LLVMGetValueName2(param, &mut length);
-                                context.named_values.insert(CStr::from_ptr(name_buffer).to_str().unwrap().to_string(), param);
-                            }
-
-                            let body_ir = GenericAst::ExprAst((**body).clone()).generate(context);
-                            // TODO (saif) optionals instead of nulls/panics?
-                            if !body_ir.is_null() {
-                                LLVMBuildRet(context.builder, body_ir);
-
-                                context.named_values.insert("cache\0".to_string(), body_ir);
-                                LLVMVerifyFunction(func_proto, LLVMVerifierFailureAction::LLVMPrintMessageAction);
-                            } else {
-                                //erase?
-                            }
-                            return func_proto;
-                        } else {
-                            panic!("Expected Prototype Ast!");
-                        }
+            ExprAst::BinaryExpr {op, lhs, rhs} if op == "=" => {
+                let name = match &**lhs {
+                    ExprAst::VariableExpr {name} => name,
+                    ExprAst::IndexExpr {array, index} => {
+                        let array_ir = array.generate(context);
+                        let index_ir = index.generate(context);
+                        let element_pointer = build_element_pointer(context, array_ir, index_ir);
+                        let value_ir = rhs.generate(context);
+                        LLVMBuildStore(context.builder, value_ir, element_pointer);
+                        return value_ir;
+                    },
+                    ExprAst::FieldExpr { .. } => {
+                        let value_ir = rhs.generate(context);
+                        build_field_store(context, lhs, value_ir);
+                        return value_ir;
                     },
-                    FuncAst::Prototype {name, args} => {
-                        // TODO (saif) remove assumption that our functions always return a float
-                        let return_type = LLVMBFloatTypeInContext(context.context);
-                        let mut arg_types = std::vec![LLVMBFloatTypeInContext(context.context); args.len()];
+                    _ => panic!("Destination of '=' must be a variable, an array element or a field")
+                };
+
+                let value_ir = rhs.generate(context);
+                if let Some((variable, _)) = variable_address(context, name) {
+                    LLVMBuildStore(context.builder, value_ir, variable);
+                    value_ir
+                } else {
+                    panic!("Unknown variable name: {}", name);
+                }
+            },
+            ExprAst::BinaryExpr {op, lhs, rhs} if op == "&&" || op == "||" => {
+                build_short_circuit(context, op == "&&", lhs, rhs, false)
+            },
+            ExprAst::BinaryExpr {op, lhs, rhs} => {
+                let lhs_ir = lhs.generate(context);
+                let rhs_ir = rhs.generate(context);
+
+                /*
+                    The type checker converts both operands to the same type, which picks the instruction:
+                    float instructions for f64, signed integer instructions for i64 and bool.
+                    NaN semantics of the float comparisons (IEEE 754):
+                        <, <=, >, >=, == use ordered predicates and are false when either side is NaN
+                        != uses an unordered predicate and is true when either side is NaN
+                    Comparisons produce a bool (i1).
+                */
+                let float = is_float(lhs_ir);
+                match op.as_str() {
+                    "+" if float => {
+                        /*
+                            Learning Note: Why is the builder passed in ?
+                            The builder is used to construct LLVM instructions within a basic block.
+                            The builder keeps track of the current insertion point in the basic block and
+                            is responsible for generating and appending the LLVM instruction to the block.
+                        */
+                        LLVMBuildFAdd(context.builder, lhs_ir, rhs_ir, "addtmp\0".as_ptr() as *const i8)
+                    },
+                    "+" => LLVMBuildAdd(context.builder, lhs_ir, rhs_ir, "addtmp\0".as_ptr() as *const i8),
+                    "-" if float => LLVMBuildFSub(context.builder, lhs_ir, rhs_ir, "subtmp\0".as_ptr() as *const i8),
+                    "-" => LLVMBuildSub(context.builder, lhs_ir, rhs_ir, "subtmp\0".as_ptr() as *const i8),
+                    "*" if float => LLVMBuildFMul(context.builder, lhs_ir, rhs_ir, "multmp\0".as_ptr() as *const i8),
+                    "*" => LLVMBuildMul(context.builder, lhs_ir, rhs_ir, "multmp\0".as_ptr() as *const i8),
+                    "/" if float => LLVMBuildFDiv(context.builder, lhs_ir, rhs_ir, "divtmp\0".as_ptr() as *const i8),
+                    // integer division truncates towards zero, dividing by zero is undefined
+                    "/" => LLVMBuildSDiv(context.builder, lhs_ir, rhs_ir, "divtmp\0".as_ptr() as *const i8),
+                    // the result has the sign of the dividend, like C's fmod and %
+                    "%" if float => LLVMBuildFRem(context.builder, lhs_ir, rhs_ir, "remtmp\0".as_ptr() as *const i8),
+                    "%" => LLVMBuildSRem(context.builder, lhs_ir, rhs_ir, "remtmp\0".as_ptr() as *const i8),
+                    ">" if float => LLVMBuildFCmp(context.builder, LLVMRealOGT, lhs_ir, rhs_ir, "cmpgt\0".as_ptr() as *const i8),
+                    ">" => LLVMBuildICmp(context.builder, LLVMIntSGT, lhs_ir, rhs_ir, "cmpgt\0".as_ptr() as *const i8),
+                    "<" if float => LLVMBuildFCmp(context.builder, LLVMRealOLT, lhs_ir, rhs_ir, "cmplt\0".as_ptr() as *const i8),
+                    "<" => LLVMBuildICmp(context.builder, LLVMIntSLT, lhs_ir, rhs_ir, "cmplt\0".as_ptr() as *const i8),
+                    ">=" if float => LLVMBuildFCmp(context.builder, LLVMRealOGE, lhs_ir, rhs_ir, "cmpge\0".as_ptr() as *const i8),
+                    ">=" => LLVMBuildICmp(context.builder, LLVMIntSGE, lhs_ir, rhs_ir, "cmpge\0".as_ptr() as *const i8),
+                    "<=" if float => LLVMBuildFCmp(context.builder, LLVMRealOLE, lhs_ir, rhs_ir, "cmple\0".as_ptr() as *const i8),
+                    "<=" => LLVMBuildICmp(context.builder, LLVMIntSLE, lhs_ir, rhs_ir, "cmple\0".as_ptr() as *const i8),
+                    "==" if float => LLVMBuildFCmp(context.builder, LLVMRealOEQ, lhs_ir, rhs_ir, "cmpeq\0".as_ptr() as *const i8),
+                    "==" => LLVMBuildICmp(context.builder, LLVMIntEQ, lhs_ir, rhs_ir, "cmpeq\0".as_ptr() as *const i8),
+                    "!=" if float => LLVMBuildFCmp(context.builder, LLVMRealUNE, lhs_ir, rhs_ir, "cmpne\0".as_ptr() as *const i8),
+                    "!=" => LLVMBuildICmp(context.builder, LLVMIntNE, lhs_ir, rhs_ir, "cmpne\0".as_ptr() as *const i8),
+                    "^" => {
+                        let intrinsic_name = "llvm.pow";
+                        let intrinsic_id = LLVMLookupIntrinsicID(intrinsic_name.as_ptr() as *const i8, intrinsic_name.len());
+                        let mut overload_types = [llvm_type(context, Type::F64)];
+                        let pow_func = LLVMGetIntrinsicDeclaration(context.module, intrinsic_id, overload_types.as_mut_ptr(), 1);
+                        let pow_type = LLVMIntrinsicGetType(context.context, intrinsic_id, overload_types.as_mut_ptr(), 1);
+
+                        let mut pow_args = [lhs_ir, rhs_ir];
+                        LLVMBuildCall2(context.builder, pow_type, pow_func, pow_args.as_mut_ptr(), 2, "powtmp\0".as_ptr() as *const i8)
+                    },
+                    _ => {
+                        panic!("Unknown operator {}", op)
+                    }
+                }
+            },
+            ExprAst::CastExpr {ty, operand} => {
+                let operand_ir = operand.generate(context);
+                build_cast(context, operand_ir, *ty)
+            },
+            ExprAst::BlockExpr {exprs} => {
+                let mut last_ir = std::ptr::null_mut();
+                for expr in exprs.iter() {
+                    last_ir = expr.generate(context);
+                }
+                last_ir
+            },
+            ExprAst::ArrayExpr {elements} => {
+                let elements_ir: Vec<LLVMValueRef> = elements.iter().map(|element| element.generate(context)).collect();
+                build_array(context, &elements_ir)
+            },
+            ExprAst::IndexExpr {array, index} => {
+                let array_ir = array.generate(context);
+                let index_ir = index.generate(context);
+                let element_pointer = build_element_pointer(context, array_ir, index_ir);
+                LLVMBuildLoad2(context.builder, llvm_type(context, Type::F64), element_pointer, "elementtmp\0".as_ptr() as *const i8)
+            },
+            ExprAst::FieldExpr {object, field} => {
+                let object_ir = object.generate(context);
+                let index = field_index(context, object_ir, field);
+                LLVMBuildExtractValue(context.builder, object_ir, index, "fieldtmp\0".as_ptr() as *const i8)
+            },
+            ExprAst::LambdaExpr {params, return_type, body} => build_lambda(context, params, *return_type, body),
+            ExprAst::CallExpr {callee, args} if variable_address(context, callee).is_some() || context.constants.contains_key(callee) => {
+                let function_type = closure_type_of(context, callee);
+                let closure = ExprAst::VariableExpr { name: callee.clone() }.generate(context);
+                let args_ir: Vec<LLVMValueRef> = args.iter().map(|arg| arg.generate(context)).collect();
+                build_closure_call(context, closure, function_type, &args_ir)
+            },
+            ExprAst::CallExpr {callee, args} if get_builtin_function(callee).is_some() => {
+                let args_ir: Vec<LLVMValueRef> = args.iter().map(|arg| arg.generate(context)).collect();
+                build_builtin_call(context, callee, &args_ir)
+            },
+            ExprAst::CallExpr {callee, args} => {
+                let func = named_function(context, callee);
+                if func.is_null() {
+                    panic!("Unknown function referenced {}", callee);
+                }
+
+                let call_arg_count = LLVMCountParams(func);
+                if (call_arg_count as usize) != args.len() {
+                    panic!("Function {} called with unexpected number of arguments", callee);
+                }
+
+                let mut generated_args = Vec::new();
+
+                // let mut type_arr: [LLVMTypeRef; 2] = [LLVMDoubleTypeInContext(context.context), LLVMDoubleTypeInContext(context.context)];
+                // let _params = LLVMGetParamTypes(LLVMTypeOf(func), type_arr.as_mut_ptr());
+
+                for arg in args.iter() {
+                    generated_args.push(arg.generate(context));
+                }
+
+                let function_type = *context.function_types.get(callee).unwrap();
+
+                LLVMBuildCall2(context.builder,
+                               function_type,
+                               func,
+                               generated_args.as_mut_ptr(),
+                               call_arg_count,
+                               "calltmp\0".as_ptr() as *const i8)
+            }
+        }
+    }
+}
+
+impl IRGenerator<LLVMGeneratorContext, LLVMValueRef> for FuncAst
+{
+    unsafe fn generate(&self, context: &mut LLVMGeneratorContext) -> LLVMValueRef {
+        match self {
+            FuncAst::Function {proto, body} => {
+                let proto_unboxed = &**proto;
+
+                if let FuncAst::Prototype { name, args, return_type } = proto_unboxed {
+                    let mut func_proto = LLVMGetNamedFunction(
+                        context.module,
+                        context.c_name(name));
+                    if func_proto.is_null() {
+                        func_proto = proto.generate(context);
+                    }
+
+                    // TODO (saif) check if null again ?!
+                    // TODO (saif) check if empty ?!
+                    // let first_block = LLVMGetFirstBasicBlock(func_proto);
+                    // TODO (saif) check for redefinition ?
+
+                    let basic_block = LLVMAppendBasicBlockInContext(
+                        context.context,
+                        func_proto,
+                        "entry\0".as_ptr() as *const i8);
+                    LLVMPositionBuilderAtEnd(context.builder, basic_block);
+
+                    // the variables of the previous function would otherwise be taken for captures and closure calls
+                    context.named_values.clear();
+                    context.closure_types = closure_types(args);
+                    let mut param_slots = Vec::new();
+                    for idx in 0..LLVMCountParams(func_proto)  {
+                        let param = LLVMGetParam(func_proto, idx);
+                        let mut length: usize = 0;
+                        let name_buffer: *const c_char = unsafe { LLVMGetValueName2(param, &mut length) };
+                        LLVMGetValueName2(param, &mut length);
 
                         /* Learning Note:
-                            the prototype with name is not registered in the module's symbol table
-                            until the function is defined.
+                            SSA values cannot be reassigned, so every parameter is copied into a stack slot
+                            (alloca) which '=' can store to. The mem2reg pass turns these back into SSA values.
                         */
-                        let function_type = LLVMFunctionType(return_type,
-                                                             arg_types.as_mut_ptr(),
-                                                             args.len() as u32,
-                                                             0);
-                        context.function_types.insert(name.clone(), function_type);
-                        let func_proto = LLVMAddFunction(context.module,
-                                                         name.as_ptr() as *const i8,
-                                                         function_type);
-
-                        // set the names of the variables
-                        for (idx, arg) in args.iter().enumerate() {
-                            LLVMSetValueName2(LLVMGetParam(func_proto, idx as u32),
-                                              arg.as_ptr() as *const i8,
-                                              arg.len() as usize)
-                        }
-                        func_proto
+                        let alloca = LLVMBuildAlloca(context.builder, LLVMTypeOf(param), name_buffer);
+                        LLVMBuildStore(context.builder, param, alloca);
+                        context.named_values.insert(CStr::from_ptr(name_buffer).to_str().unwrap().to_string(), alloca);
+                        param_slots.push(alloca);
                     }
+
+                    // a parameter of the same name shadows the function, its calls are closure calls
+                    let returns_bool = *return_type == Some(Type::Bool);
+                    context.tail_recursion = None;
+                    if !args.iter().any(|arg| arg.name == *name) && calls_itself_in_tail_position(body, name, returns_bool) {
+                        let block = LLVMAppendBasicBlockInContext(context.context, func_proto, "tailrecurse\0".as_ptr() as *const i8);
+                        LLVMBuildBr(context.builder, block);
+                        LLVMPositionBuilderAtEnd(context.builder, block);
+                        context.tail_recursion = Some(TailRecursion { name: name.clone(), block, params: param_slots });
+                    }
+
+                    let body_ir = build_return(context, body);
+                    context.tail_recursion = None;
+                    // TODO (saif) optionals instead of nulls/panics?
+                    if !body_ir.is_null() {
+                        context.named_values.insert("cache\0".to_string(), body_ir);
+                        LLVMVerifyFunction(func_proto, LLVMVerifierFailureAction::LLVMPrintMessageAction);
+                    } else {
+                        //erase?
+                    }
+                    // top-level expressions have no variables
+                    context.named_values.clear();
+                    context.closure_types.clear();
+                    return func_proto;
+                } else {
+                    panic!("Expected Prototype Ast!");
                 }
+            },
+            FuncAst::Prototype {name, args, return_type} => {
+                // unannotated types are only left when the type checker did not run
+                let return_type = llvm_type(context, return_type.unwrap_or(DEFAULT_TYPE));
+                let mut arg_types: Vec<LLVMTypeRef> = args.iter()
+                    .map(|arg| llvm_type(context, arg.ty.unwrap_or(DEFAULT_TYPE)))
+                    .collect();
+
+                /* Learning Note:
+                    the prototype with name is not registered in the module's symbol table
+                    until the function is defined.
+                */
+                let function_type = LLVMFunctionType(return_type,
+                                                     arg_types.as_mut_ptr(),
+                                                     args.len() as u32,
+                                                     0);
+                context.function_types.insert(name.clone(), function_type);
+                // declared again, like an extern of every file using it, it is the same function
+                let declared = LLVMGetNamedFunction(context.module, context.c_name(name));
+                if !declared.is_null() && LLVMGlobalGetValueType(declared) == function_type {
+                    return declared;
+                }
+                let func_proto = LLVMAddFunction(context.module,
+                                                 context.c_name(name),
+                                                 function_type);
+
+                // set the names of the variables
+                for (idx, arg) in args.iter().enumerate() {
+                    LLVMSetValueName2(LLVMGetParam(func_proto, idx as u32),
+                                      arg.name.as_ptr() as *const i8,
+                                      arg.name.len())
+                }
+                func_proto
             }
         }
     }
-}
\ No newline at end of file
+}
+
+pub(crate) unsafe fn llvm_type(context: &LLVMGeneratorContext, ty: Type) -> LLVMTypeRef {
+    match ty {
+        Type::I64 => LLVMInt64TypeInContext(context.context),
+        Type::F64 => LLVMDoubleTypeInContext(context.context),
+        Type::Bool => LLVMInt1TypeInContext(context.context),
+        Type::Str => LLVMPointerType(LLVMInt8TypeInContext(context.context), 0),
+        Type::Array => LLVMPointerType(array_type(context), 0),
+        Type::Struct(name) => match context.structs.get(name.as_str()) {
+            Some((struct_type, _)) => *struct_type,
+            None => panic!("Unknown struct {}", name.as_str())
+        },
+        Type::Function(function_type) => closure_type(context, function_type)
+    }
+}
+
+unsafe fn is_float(value: LLVMValueRef) -> bool {
+    LLVMGetTypeKind(LLVMTypeOf(value)) == LLVMTypeKind::LLVMDoubleTypeKind
+}
+
+unsafe fn is_bool(value: LLVMValueRef) -> bool {
+    let value_type = LLVMTypeOf(value);
+    LLVMGetTypeKind(value_type) == LLVMTypeKind::LLVMIntegerTypeKind && LLVMGetIntTypeWidth(value_type) == 1
+}
+
+// a value is true when it is not 0, NaN counts as true like in C
+unsafe fn build_truth_test(context: &mut LLVMGeneratorContext, value: LLVMValueRef) -> LLVMValueRef {
+    if is_bool(value) {
+        return value;
+    }
+    let zero = LLVMConstNull(LLVMTypeOf(value));
+    if is_float(value) {
+        LLVMBuildFCmp(context.builder, LLVMRealUNE, value, zero, "truthtmp\0".as_ptr() as *const i8)
+    } else {
+        LLVMBuildICmp(context.builder, LLVMIntNE, value, zero, "truthtmp\0".as_ptr() as *const i8)
+    }
+}
+
+/*
+    Conversions between the types, the source type is read from the value:
+        bool -> i64 and f64: false is 0 and true is 1
+        i64 <-> f64: f64 to i64 truncates towards zero, out of range values are undefined
+        anything -> bool: the truth test
+        anything -> str: formatted by the runtime
+*/
+unsafe fn build_cast(context: &mut LLVMGeneratorContext, value: LLVMValueRef, ty: Type) -> LLVMValueRef {
+    let to = llvm_type(context, ty);
+    if LLVMTypeOf(value) == to {
+        return value;
+    }
+    let name = "casttmp\0".as_ptr() as *const i8;
+    match ty {
+        Type::Bool => build_truth_test(context, value),
+        Type::Str => build_format(context, value),
+        Type::I64 if is_bool(value) => LLVMBuildZExt(context.builder, value, to, name),
+        Type::I64 => LLVMBuildFPToSI(context.builder, value, to, name),
+        Type::F64 if is_bool(value) => LLVMBuildUIToFP(context.builder, value, to, name),
+        Type::F64 => LLVMBuildSIToFP(context.builder, value, to, name),
+        // rejected by the type checker
+        Type::Array | Type::Struct(_) | Type::Function(_) => panic!("Cannot convert a value to {}", ty)
+    }
+}
+
+/*
+    && and || only evaluate their rhs when the lhs does not decide the result:
+        lhs_block: evaluate lhs, branch to rhs_block or straight to merge_block
+        rhs_block: evaluate rhs, branch to merge_block, or return it when it is a tail call (a self
+                   tail call has jumped already)
+        merge_block: phi of the lhs shortcut value and the rhs truth value
+*/
+unsafe fn build_short_circuit(context: &mut LLVMGeneratorContext, is_and: bool, lhs: &ExprAst, rhs: &ExprAst, tail: bool) -> LLVMValueRef {
+    let insert_block = LLVMGetInsertBlock(context.builder);
+    if insert_block.is_null() {
+        panic!("Operators && and || can only be used inside a function");
+    }
+    let function = LLVMGetBasicBlockParent(insert_block);
+
+    let lhs_ir = lhs.generate(context);
+    let lhs_truth = build_truth_test(context, lhs_ir);
+    let lhs_block = LLVMGetInsertBlock(context.builder);
+
+    let rhs_block = LLVMAppendBasicBlockInContext(context.context, function, "rhs\0".as_ptr() as *const i8);
+    let merge_block = LLVMAppendBasicBlockInContext(context.context, function, "merge\0".as_ptr() as *const i8);
+    if is_and {
+        LLVMBuildCondBr(context.builder, lhs_truth, rhs_block, merge_block);
+    } else {
+        LLVMBuildCondBr(context.builder, lhs_truth, merge_block, rhs_block);
+    }
+
+    LLVMPositionBuilderAtEnd(context.builder, rhs_block);
+    let rhs_ir = if tail { build_tail_expr(context, rhs) } else { rhs.generate(context) };
+    let jumped = has_jumped(context);
+    let rhs_truth = if jumped { rhs_ir } else { build_truth_test(context, rhs_ir) };
+    // generating the rhs may have moved the builder to another block
+    let rhs_end_block = LLVMGetInsertBlock(context.builder);
+    // a tail call returns from the rhs block and a self tail call jumps, only the shortcut reaches the merge block
+    let returns_rhs = jumped || (rhs_truth == rhs_ir && is_tail_call(rhs_ir));
+    if !jumped {
+        if returns_rhs {
+            LLVMBuildRet(context.builder, rhs_ir);
+        } else {
+            LLVMBuildBr(context.builder, merge_block);
+        }
+    }
+
+    LLVMPositionBuilderAtEnd(context.builder, merge_block);
+    let phi = LLVMBuildPhi(context.builder, LLVMInt1TypeInContext(context.context), "logictmp\0".as_ptr() as *const i8);
+    let mut incoming_values = [LLVMConstInt(LLVMInt1TypeInContext(context.context), !is_and as u64, 0), rhs_truth];
+    let mut incoming_blocks = [lhs_block, rhs_end_block];
+    let incoming_count = if returns_rhs { 1 } else { 2 };
+    LLVMAddIncoming(phi, incoming_values.as_mut_ptr(), incoming_blocks.as_mut_ptr(), incoming_count);
+
+    phi
+}
+
+/* Learning Note:
+    A call is in tail position when its value is returned right away: the body of a function, the
+    last expression of a block in tail position, and the rhs of && and || in tail position when the
+    function returns a bool. This covers self and mutual recursion:
+        def count(n: i64) -> bool n == 0 || count(n - 1)
+    A call of the function itself is not a call at all: its arguments are stored to the stack
+    slots of the parameters and it jumps back to the tailrecurse block, which follows the entry
+    block that sets up the slots. That loop runs in constant stack without any optimization.
+    Any other call is marked `tail` when it returns the type of the caller. It only passes values
+    (arrays, strings and environments live on the heap), never a pointer into the frame of the
+    caller, so the code generator can replace the call and the ret with a jump. It only does so
+    when optimizing, like the JIT at OptLevel 2: the C API of LLVM 15 only sets `tail`, `musttail`
+    needs LLVMSetTailCallKind, so mutual recursion compiled without optimizations grows the stack.
+*/
+pub(crate) unsafe fn build_return(context: &mut LLVMGeneratorContext, body: &ExprAst) -> LLVMValueRef {
+    let body_ir = build_tail_expr(context, body);
+    if !body_ir.is_null() && !has_jumped(context) {
+        LLVMBuildRet(context.builder, body_ir);
+    }
+    body_ir
+}
+
+// whether build_tail_expr finds a call of the function itself, which needs a block to jump back to
+fn calls_itself_in_tail_position(expr: &ExprAst, name: &str, returns_bool: bool) -> bool {
+    match expr {
+        ExprAst::BlockExpr {exprs} => exprs.last().is_some_and(|last| calls_itself_in_tail_position(last, name, returns_bool)),
+        ExprAst::BinaryExpr {op, rhs, ..} if returns_bool && (op == "&&" || op == "||") => calls_itself_in_tail_position(rhs, name, returns_bool),
+        ExprAst::CallExpr {callee, ..} => callee == name,
+        _ => false
+    }
+}
+
+// every argument is computed before a parameter is stored to, f(n - 1, n) passes the old n
+unsafe fn build_self_tail_call(context: &mut LLVMGeneratorContext, args: &[ExprAst], return_type: LLVMTypeRef) -> LLVMValueRef {
+    let args_ir: Vec<LLVMValueRef> = args.iter().map(|arg| arg.generate(context)).collect();
+    let tail_recursion = context.tail_recursion.as_ref().unwrap();
+    for (arg_ir, param) in args_ir.iter().zip(&tail_recursion.params) {
+        LLVMBuildStore(context.builder, *arg_ir, *param);
+    }
+    LLVMBuildBr(context.builder, tail_recursion.block);
+    // never used, the block has ended with the jump
+    LLVMGetUndef(return_type)
+}
+
+// a self tail call ends the block it is generated in
+unsafe fn has_jumped(context: &LLVMGeneratorContext) -> bool {
+    !LLVMGetBasicBlockTerminator(LLVMGetInsertBlock(context.builder)).is_null()
+}
+
+unsafe fn build_tail_expr(context: &mut LLVMGeneratorContext, expr: &ExprAst) -> LLVMValueRef {
+    let return_type = LLVMGetReturnType(LLVMGlobalGetValueType(LLVMGetBasicBlockParent(LLVMGetInsertBlock(context.builder))));
+    match expr {
+        ExprAst::BlockExpr {exprs} => match exprs.split_last() {
+            Some((last, exprs)) => {
+                for expr in exprs {
+                    expr.generate(context);
+                }
+                build_tail_expr(context, last)
+            },
+            None => std::ptr::null_mut()
+        },
+        ExprAst::BinaryExpr {op, lhs, rhs} if op == "&&" || op == "||" => {
+            let tail = return_type == LLVMInt1TypeInContext(context.context);
+            build_short_circuit(context, op == "&&", lhs, rhs, tail)
+        },
+        ExprAst::CallExpr {callee, args} if context.tail_recursion.as_ref().is_some_and(|tail_recursion| tail_recursion.name == *callee) =>
+            build_self_tail_call(context, args, return_type),
+        ExprAst::CallExpr { .. } => {
+            let call = expr.generate(context);
+            // a builtin like len may not be a call
+            if !LLVMIsACallInst(call).is_null() && LLVMTypeOf(call) == return_type {
+                LLVMSetTailCall(call, 1);
+            }
+            call
+        },
+        _ => expr.generate(context)
+    }
+}
+
+unsafe fn is_tail_call(value: LLVMValueRef) -> bool {
+    !LLVMIsACallInst(value).is_null() && LLVMIsTailCall(value) != 0
+}
@@ -0,0 +1,331 @@
+use std::ffi::CString;
+
+use llvm_sys::prelude::*;
+use llvm_sys::core::*;
+use llvm_sys::{LLVMIntPredicate, LLVMLinkage, LLVMUnnamedAddr};
+
+use crate::codegen::llvm_generator::{llvm_type, LLVMGeneratorContext};
+use crate::syntax::types::Type;
+
+/*
+    The runtime is generated into the module itself on top of the C library, so that a compiled
+    module only has to be linked against libc:
+        - print(s: str) and println(s: str) get a body when they are declared as externs with a
+          single str parameter, any other extern of that name stays an ordinary declaration
+        - str(x) formats a number or a bool into a new heap buffer, which is never freed
+        - arrays are allocated with malloc and grown with realloc, they are never freed either
+        - indexing an array outside of its bounds prints an error to stderr and exits with 1
+    The array functions are named array.*, which no Kaleidoscope function can be named.
+*/
+
+// the printf format every print function writes its argument with
+const PRINT_FUNCTIONS: [(&str, &str); 2] = [("print", "%s"), ("println", "%s\n")];
+
+// large enough for any i64 and for f64 in %g format
+const FORMAT_BUFFER_SIZE: u64 = 32;
+
+// fields of the array struct, with the names of their loaded values
+const LENGTH_FIELD: u32 = 0;
+const CAPACITY_FIELD: u32 = 1;
+const ELEMENTS_FIELD: u32 = 2;
+const FIELD_NAMES: [&str; 3] = ["length\0", "capacity\0", "elements\0"];
+
+const BOUNDS_ERROR_FORMAT: &str = "Index %lld out of bounds for array of length %lld\n";
+const STDERR_FILENO: u64 = 2;
+
+// defines the body of a declared runtime function, returns false when name is not one of them
+pub(crate) unsafe fn define_runtime_function(context: &mut LLVMGeneratorContext, name: &str, function: LLVMValueRef) -> bool {
+    let format = match PRINT_FUNCTIONS.iter().find(|(print_name, _)| *print_name == name) {
+        Some((_, format)) => *format,
+        None => return false
+    };
+    let str_type = llvm_type(context, Type::Str);
+    let is_declaration = LLVMCountBasicBlocks(function) == 0;
+    if !is_declaration || LLVMCountParams(function) != 1 || LLVMTypeOf(LLVMGetParam(function, 0)) != str_type {
+        return false;
+    }
+
+    LLVMSetLinkage(function, LLVMLinkage::LLVMLinkOnceODRLinkage);
+    build_body(context, function, |context| {
+        let (printf, printf_type) = libc_function(context, "printf", LLVMInt32TypeInContext(context.context), &[str_type], true);
+        let mut printf_args = [build_string_constant(context, format), LLVMGetParam(function, 0)];
+        LLVMBuildCall2(context.builder, printf_type, printf, printf_args.as_mut_ptr(), 2, "printtmp\0".as_ptr() as *const i8);
+        LLVMBuildRet(context.builder, LLVMConstNull(LLVMGetReturnType(LLVMGlobalGetValueType(function))));
+    });
+    true
+}
+
+// generates the body of a function starting at its entry block, the builder is moved back afterwards
+pub(crate) unsafe fn build_body<F>(context: &mut LLVMGeneratorContext, function: LLVMValueRef, body: F)
+    where F: FnOnce(&mut LLVMGeneratorContext) {
+    // top-level expressions are generated at the current position, which has to be kept
+    let previous_block = LLVMGetInsertBlock(context.builder);
+    let entry = LLVMAppendBasicBlockInContext(context.context, function, "entry\0".as_ptr() as *const i8);
+    LLVMPositionBuilderAtEnd(context.builder, entry);
+
+    body(context);
+
+    if previous_block.is_null() {
+        LLVMClearInsertionPosition(context.builder);
+    } else {
+        LLVMPositionBuilderAtEnd(context.builder, previous_block);
+    }
+}
+
+/* Learning Note:
+    A string literal is a private global constant array of bytes ending with a nul byte.
+    Its value is a pointer to the first byte, a constant expression (getelementptr) that does not
+    need a builder, so literals also work outside of functions. Equal literals share one global.
+*/
+pub(crate) unsafe fn build_string_constant(context: &mut LLVMGeneratorContext, value: &str) -> LLVMValueRef {
+    if let Some(pointer) = context.strings.get(value) {
+        return *pointer;
+    }
+
+    let data = LLVMConstStringInContext(context.context, value.as_ptr() as *const i8, value.len() as u32, 0);
+    let global = LLVMAddGlobal(context.module, LLVMTypeOf(data), ".str\0".as_ptr() as *const i8);
+    LLVMSetInitializer(global, data);
+    LLVMSetGlobalConstant(global, 1);
+    LLVMSetLinkage(global, LLVMLinkage::LLVMPrivateLinkage);
+    LLVMSetUnnamedAddress(global, LLVMUnnamedAddr::LLVMGlobalUnnamedAddr);
+
+    let zero = LLVMConstInt(LLVMInt64TypeInContext(context.context), 0, 0);
+    let mut indices = [zero, zero];
+    let pointer = LLVMConstInBoundsGEP2(LLVMTypeOf(data), global, indices.as_mut_ptr(), 2);
+    context.strings.insert(value.to_string(), pointer);
+    pointer
+}
+
+// str(x): "true" or "false" for a bool, printf's %lld for an i64 and %g for an f64
+pub(crate) unsafe fn build_format(context: &mut LLVMGeneratorContext, value: LLVMValueRef) -> LLVMValueRef {
+    let value_type = LLVMTypeOf(value);
+    if value_type == llvm_type(context, Type::Bool) {
+        let true_str = build_string_constant(context, "true");
+        let false_str = build_string_constant(context, "false");
+        return LLVMBuildSelect(context.builder, value, true_str, false_str, "strtmp\0".as_ptr() as *const i8);
+    }
+    let format = if value_type == llvm_type(context, Type::I64) { "%lld" } else { "%g" };
+
+    let str_type = llvm_type(context, Type::Str);
+    let size_type = LLVMInt64TypeInContext(context.context);
+    let size = LLVMConstInt(size_type, FORMAT_BUFFER_SIZE, 0);
+
+    let buffer = build_malloc(context, size);
+
+    let (snprintf, snprintf_type) = libc_function(context, "snprintf", LLVMInt32TypeInContext(context.context), &[str_type, size_type, str_type], true);
+    let mut snprintf_args = [buffer, size, build_string_constant(context, format), value];
+    LLVMBuildCall2(context.builder, snprintf_type, snprintf, snprintf_args.as_mut_ptr(), 4, "fmttmp\0".as_ptr() as *const i8);
+    buffer
+}
+
+/* Learning Note:
+    An array is a pointer to a struct on the heap, so that every copy of it sees what push does:
+        %array = type { i64, i64, double* }     ; length, capacity, elements
+    The elements are kept in a buffer of their own, which push reallocates when it is full.
+    A named struct type exists once per LLVM context, it is created on first use.
+*/
+pub(crate) unsafe fn array_type(context: &LLVMGeneratorContext) -> LLVMTypeRef {
+    let existing = LLVMGetTypeByName2(context.context, "array\0".as_ptr() as *const i8);
+    if !existing.is_null() {
+        return existing;
+    }
+
+    let array_type = LLVMStructCreateNamed(context.context, "array\0".as_ptr() as *const i8);
+    let size_type = LLVMInt64TypeInContext(context.context);
+    let mut fields = [size_type, size_type, element_pointer_type(context)];
+    LLVMStructSetBody(array_type, fields.as_mut_ptr(), fields.len() as u32, 0);
+    array_type
+}
+
+// [e1, e2, ...]: a new array with exactly the room for its elements
+pub(crate) unsafe fn build_array(context: &mut LLVMGeneratorContext, elements: &[LLVMValueRef]) -> LLVMValueRef {
+    let (array_new, array_new_type) = array_new_function(context);
+    let mut new_args = [LLVMConstInt(LLVMInt64TypeInContext(context.context), elements.len() as u64, 0)];
+    let array = LLVMBuildCall2(context.builder, array_new_type, array_new, new_args.as_mut_ptr(), 1, "arraytmp\0".as_ptr() as *const i8);
+
+    let buffer = load_field(context, array, ELEMENTS_FIELD);
+    for (i, element) in elements.iter().enumerate() {
+        let mut indices = [LLVMConstInt(LLVMInt64TypeInContext(context.context), i as u64, 0)];
+        let element_type = llvm_type(context, Type::F64);
+        let pointer = LLVMBuildInBoundsGEP2(context.builder, element_type, buffer, indices.as_mut_ptr(), 1, "elementptr\0".as_ptr() as *const i8);
+        LLVMBuildStore(context.builder, *element, pointer);
+    }
+    array
+}
+
+// the address of array[index], checked against the length of the array
+pub(crate) unsafe fn build_element_pointer(context: &mut LLVMGeneratorContext, array: LLVMValueRef, index: LLVMValueRef) -> LLVMValueRef {
+    let (array_at, array_at_type) = array_at_function(context);
+    let mut at_args = [array, index];
+    LLVMBuildCall2(context.builder, array_at_type, array_at, at_args.as_mut_ptr(), 2, "elementptr\0".as_ptr() as *const i8)
+}
+
+// a call to one of the BUILTIN_FUNCTIONS, with its arguments already generated
+pub(crate) unsafe fn build_builtin_call(context: &mut LLVMGeneratorContext, name: &str, args: &[LLVMValueRef]) -> LLVMValueRef {
+    match (name, args) {
+        ("len", [array]) => load_field(context, *array, LENGTH_FIELD),
+        ("push", [array, element]) => {
+            let (array_push, array_push_type) = array_push_function(context);
+            let mut push_args = [*array, *element];
+            LLVMBuildCall2(context.builder, array_push_type, array_push, push_args.as_mut_ptr(), 2, "pushtmp\0".as_ptr() as *const i8)
+        },
+        _ => panic!("Unknown builtin function {} with {} argument(s)", name, args.len())
+    }
+}
+
+// array.new(length) allocates an array of length elements, which are left uninitialized
+unsafe fn array_new_function(context: &mut LLVMGeneratorContext) -> (LLVMValueRef, LLVMTypeRef) {
+    let array_pointer_type = llvm_type(context, Type::Array);
+    let size_type = LLVMInt64TypeInContext(context.context);
+    runtime_function(context, "array.new", array_pointer_type, &[("length", size_type)], |context, function| {
+        let length = LLVMGetParam(function, 0);
+        let struct_memory = build_malloc(context, LLVMSizeOf(array_type(context)));
+        let array = LLVMBuildBitCast(context.builder, struct_memory, array_pointer_type, "array\0".as_ptr() as *const i8);
+
+        let buffer_size = LLVMBuildMul(context.builder, length, LLVMSizeOf(llvm_type(context, Type::F64)), "sizetmp\0".as_ptr() as *const i8);
+        let buffer_memory = build_malloc(context, buffer_size);
+        let buffer = LLVMBuildBitCast(context.builder, buffer_memory, element_pointer_type(context), "elements\0".as_ptr() as *const i8);
+
+        store_field(context, array, LENGTH_FIELD, length);
+        store_field(context, array, CAPACITY_FIELD, length);
+        store_field(context, array, ELEMENTS_FIELD, buffer);
+        LLVMBuildRet(context.builder, array);
+    })
+}
+
+/*
+    array.push(array, element) appends the element and returns the new length. A full buffer is
+    reallocated with twice its capacity plus one, so that pushing n elements copies O(n) of them.
+*/
+unsafe fn array_push_function(context: &mut LLVMGeneratorContext) -> (LLVMValueRef, LLVMTypeRef) {
+    let array_pointer_type = llvm_type(context, Type::Array);
+    let element_type = llvm_type(context, Type::F64);
+    let size_type = LLVMInt64TypeInContext(context.context);
+    runtime_function(context, "array.push", size_type, &[("array", array_pointer_type), ("element", element_type)], |context, function| {
+        let array = LLVMGetParam(function, 0);
+        let element = LLVMGetParam(function, 1);
+        let grow_block = LLVMAppendBasicBlockInContext(context.context, function, "grow\0".as_ptr() as *const i8);
+        let append_block = LLVMAppendBasicBlockInContext(context.context, function, "append\0".as_ptr() as *const i8);
+
+        let length = load_field(context, array, LENGTH_FIELD);
+        let capacity = load_field(context, array, CAPACITY_FIELD);
+        let is_full = LLVMBuildICmp(context.builder, LLVMIntPredicate::LLVMIntEQ, length, capacity, "isfull\0".as_ptr() as *const i8);
+        LLVMBuildCondBr(context.builder, is_full, grow_block, append_block);
+
+        LLVMPositionBuilderAtEnd(context.builder, grow_block);
+        let doubled = LLVMBuildMul(context.builder, capacity, LLVMConstInt(size_type, 2, 0), "doubled\0".as_ptr() as *const i8);
+        let new_capacity = LLVMBuildAdd(context.builder, doubled, LLVMConstInt(size_type, 1, 0), "capacity\0".as_ptr() as *const i8);
+        let buffer_size = LLVMBuildMul(context.builder, new_capacity, LLVMSizeOf(element_type), "sizetmp\0".as_ptr() as *const i8);
+        let old_buffer = load_field(context, array, ELEMENTS_FIELD);
+        let byte_pointer_type = llvm_type(context, Type::Str);
+        let old_memory = LLVMBuildBitCast(context.builder, old_buffer, byte_pointer_type, "oldmemory\0".as_ptr() as *const i8);
+        let (realloc, realloc_type) = libc_function(context, "realloc", byte_pointer_type, &[byte_pointer_type, size_type], false);
+        let mut realloc_args = [old_memory, buffer_size];
+        let new_memory = LLVMBuildCall2(context.builder, realloc_type, realloc, realloc_args.as_mut_ptr(), 2, "newmemory\0".as_ptr() as *const i8);
+        let new_buffer = LLVMBuildBitCast(context.builder, new_memory, element_pointer_type(context), "elements\0".as_ptr() as *const i8);
+        store_field(context, array, ELEMENTS_FIELD, new_buffer);
+        store_field(context, array, CAPACITY_FIELD, new_capacity);
+        LLVMBuildBr(context.builder, append_block);
+
+        LLVMPositionBuilderAtEnd(context.builder, append_block);
+        let buffer = load_field(context, array, ELEMENTS_FIELD);
+        let mut indices = [length];
+        let pointer = LLVMBuildInBoundsGEP2(context.builder, element_type, buffer, indices.as_mut_ptr(), 1, "elementptr\0".as_ptr() as *const i8);
+        LLVMBuildStore(context.builder, element, pointer);
+        let new_length = LLVMBuildAdd(context.builder, length, LLVMConstInt(size_type, 1, 0), "length\0".as_ptr() as *const i8);
+        store_field(context, array, LENGTH_FIELD, new_length);
+        LLVMBuildRet(context.builder, new_length);
+    })
+}
+
+/*
+    array.at(array, index) returns the address of an element. The index is compared unsigned
+    against the length, which rejects negative indices with the same comparison.
+*/
+unsafe fn array_at_function(context: &mut LLVMGeneratorContext) -> (LLVMValueRef, LLVMTypeRef) {
+    let array_pointer_type = llvm_type(context, Type::Array);
+    let size_type = LLVMInt64TypeInContext(context.context);
+    runtime_function(context, "array.at", element_pointer_type(context), &[("array", array_pointer_type), ("index", size_type)], |context, function| {
+        let array = LLVMGetParam(function, 0);
+        let index = LLVMGetParam(function, 1);
+        let in_bounds_block = LLVMAppendBasicBlockInContext(context.context, function, "inbounds\0".as_ptr() as *const i8);
+        let out_of_bounds_block = LLVMAppendBasicBlockInContext(context.context, function, "outofbounds\0".as_ptr() as *const i8);
+
+        let length = load_field(context, array, LENGTH_FIELD);
+        let is_in_bounds = LLVMBuildICmp(context.builder, LLVMIntPredicate::LLVMIntULT, index, length, "isinbounds\0".as_ptr() as *const i8);
+        LLVMBuildCondBr(context.builder, is_in_bounds, in_bounds_block, out_of_bounds_block);
+
+        LLVMPositionBuilderAtEnd(context.builder, out_of_bounds_block);
+        let int_type = LLVMInt32TypeInContext(context.context);
+        let str_type = llvm_type(context, Type::Str);
+        let (dprintf, dprintf_type) = libc_function(context, "dprintf", int_type, &[int_type, str_type], true);
+        let mut dprintf_args = [LLVMConstInt(int_type, STDERR_FILENO, 0), build_string_constant(context, BOUNDS_ERROR_FORMAT), index, length];
+        LLVMBuildCall2(context.builder, dprintf_type, dprintf, dprintf_args.as_mut_ptr(), 4, "errortmp\0".as_ptr() as *const i8);
+        let (exit, exit_type) = libc_function(context, "exit", LLVMVoidTypeInContext(context.context), &[int_type], false);
+        let mut exit_args = [LLVMConstInt(int_type, 1, 0)];
+        LLVMBuildCall2(context.builder, exit_type, exit, exit_args.as_mut_ptr(), 1, "\0".as_ptr() as *const i8);
+        LLVMBuildUnreachable(context.builder);
+
+        LLVMPositionBuilderAtEnd(context.builder, in_bounds_block);
+        let buffer = load_field(context, array, ELEMENTS_FIELD);
+        let mut indices = [index];
+        let element_type = llvm_type(context, Type::F64);
+        let pointer = LLVMBuildInBoundsGEP2(context.builder, element_type, buffer, indices.as_mut_ptr(), 1, "elementptr\0".as_ptr() as *const i8);
+        LLVMBuildRet(context.builder, pointer);
+    })
+}
+
+unsafe fn element_pointer_type(context: &LLVMGeneratorContext) -> LLVMTypeRef {
+    LLVMPointerType(llvm_type(context, Type::F64), 0)
+}
+
+unsafe fn load_field(context: &mut LLVMGeneratorContext, array: LLVMValueRef, field: u32) -> LLVMValueRef {
+    let array_type = array_type(context);
+    let pointer = LLVMBuildStructGEP2(context.builder, array_type, array, field, "fieldptr\0".as_ptr() as *const i8);
+    LLVMBuildLoad2(context.builder, LLVMStructGetTypeAtIndex(array_type, field), pointer, FIELD_NAMES[field as usize].as_ptr() as *const i8)
+}
+
+unsafe fn store_field(context: &mut LLVMGeneratorContext, array: LLVMValueRef, field: u32, value: LLVMValueRef) {
+    let pointer = LLVMBuildStructGEP2(context.builder, array_type(context), array, field, "fieldptr\0".as_ptr() as *const i8);
+    LLVMBuildStore(context.builder, value, pointer);
+}
+
+pub(crate) unsafe fn build_malloc(context: &mut LLVMGeneratorContext, size: LLVMValueRef) -> LLVMValueRef {
+    let size_type = LLVMInt64TypeInContext(context.context);
+    let (malloc, malloc_type) = libc_function(context, "malloc", llvm_type(context, Type::Str), &[size_type], false);
+    let mut malloc_args = [size];
+    LLVMBuildCall2(context.builder, malloc_type, malloc, malloc_args.as_mut_ptr(), 1, "memory\0".as_ptr() as *const i8)
+}
+
+// gets a function of the runtime, its body is generated the first time it is used
+unsafe fn runtime_function<F>(context: &mut LLVMGeneratorContext, name: &str, return_type: LLVMTypeRef,
+                              params: &[(&str, LLVMTypeRef)], body: F) -> (LLVMValueRef, LLVMTypeRef)
+    where F: FnOnce(&mut LLVMGeneratorContext, LLVMValueRef) {
+    let mut param_types: Vec<LLVMTypeRef> = params.iter().map(|(_, param_type)| *param_type).collect();
+    let function_type = LLVMFunctionType(return_type, param_types.as_mut_ptr(), param_types.len() as u32, 0);
+    let c_name = CString::new(name).unwrap();
+    let mut function = LLVMGetNamedFunction(context.module, c_name.as_ptr());
+    if function.is_null() {
+        function = LLVMAddFunction(context.module, c_name.as_ptr(), function_type);
+        // generated again by every module using it, see LLVMGeneratorContext::link_module
+        LLVMSetLinkage(function, LLVMLinkage::LLVMLinkOnceODRLinkage);
+        for (i, (param_name, _)) in params.iter().enumerate() {
+            LLVMSetValueName2(LLVMGetParam(function, i as u32), param_name.as_ptr() as *const i8, param_name.len());
+        }
+        build_body(context, function, |context| body(context, function));
+    }
+    (function, function_type)
+}
+
+// declares a function of the C library on first use
+unsafe fn libc_function(context: &mut LLVMGeneratorContext, name: &str, return_type: LLVMTypeRef,
+                        params: &[LLVMTypeRef], variadic: bool) -> (LLVMValueRef, LLVMTypeRef) {
+    let mut params = params.to_vec();
+    let function_type = LLVMFunctionType(return_type, params.as_mut_ptr(), params.len() as u32, variadic as i32);
+    let c_name = CString::new(name).unwrap();
+    let mut function = LLVMGetNamedFunction(context.module, c_name.as_ptr());
+    if function.is_null() {
+        function = LLVMAddFunction(context.module, c_name.as_ptr(), function_type);
+    }
+    (function, function_type)
+}
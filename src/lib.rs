@@ -4,16 +4,29 @@ pub mod parse {
     pub mod parser;
     pub mod lexer;
     pub mod token;
+    pub mod resolver;
 }
 
 pub mod syntax {
     pub mod ast;
+    pub mod types;
     pub mod vocabulary;
+    pub mod visit;
+    pub mod pretty;
+}
+
+pub mod analysis {
+    pub mod semantic;
+    pub mod infer;
+    pub mod type_check;
 }
 
 pub mod codegen {
     pub mod ir_generator;
     pub mod llvm_generator;
+    pub mod runtime;
+    pub mod closure;
+    pub mod jit;
     pub mod llvm_generation_alt;
 }
 
@@ -1,18 +1,244 @@
-use std::io::{self, Write};
-use std::ffi::{CStr};
+use std::io::{self, Read, Write};
+use std::ffi::{c_void, CStr};
+use std::fs::File;
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
 use llvm_sys::core::LLVMPrintValueToString;
 
 use kaleidoscope::parse::parser::*;
+use kaleidoscope::parse::resolver::{Module, Resolver};
+use kaleidoscope::analysis::semantic::SemanticAnalyzer;
+use kaleidoscope::analysis::type_check::TypeChecker;
+use kaleidoscope::syntax::ast::{ExprAst, FuncAst, GenericAst, ImportAst, SpannedAst};
+use kaleidoscope::syntax::pretty::format_source;
+use kaleidoscope::syntax::types::{Type, DEFAULT_TYPE};
 use kaleidoscope::codegen::llvm_generator::*;
 use kaleidoscope::codegen::ir_generator::IRGenerator;
+use kaleidoscope::codegen::jit::Jit;
 
 const QUIT_CMD : &str = "quit";
+const STDIN_ARG : &str = "-";
+const EMIT_AST_CMD : &str = "emit-ast";
+const FORMAT_FLAG : &str = "--format";
+const FMT_CMD : &str = "fmt";
+const CHECK_FLAG : &str = "--check";
+const INCLUDE_FLAG : &str = "-I";
+const NO_PRELUDE_FLAG : &str = "--no-prelude";
+
+pub enum AstFormat {
+    Json,
+    // postcard encoding of the same items, for tools that do not need to read it
+    Binary
+}
+
+impl AstFormat {
+    fn from_arg(arg: &str) -> Option<AstFormat> {
+        match arg {
+            "json" => Some(AstFormat::Json),
+            "binary" => Some(AstFormat::Binary),
+            _ => None
+        }
+    }
+}
+
+// the options of a compilation or a REPL session, given before or after its path
+pub struct Options {
+    // searched in order for the imported files
+    include_path: Vec<PathBuf>,
+    prelude: bool
+}
 
 pub struct Driver {}
 
+/*
+    Runs the checks of every top-level item before code generation, keeping the declarations
+    of the previous items. Returns the item with its conversions made explicit, or prints the
+    errors of the first failing stage with the byte span of the item.
+*/
+struct Checker {
+    analyzer: SemanticAnalyzer,
+    type_checker: TypeChecker,
+    // the imported file whose items are checked, named in their errors
+    file: Option<PathBuf>,
+    // the top-level expressions checked so far, which number their functions
+    expressions: usize
+}
+
+impl Checker {
+    // new() and not default(), the checks start out knowing the builtin functions
+    fn new() -> Checker {
+        Checker { analyzer: SemanticAnalyzer::new(), type_checker: TypeChecker::new(), file: None, expressions: 0 }
+    }
+
+    fn check(&mut self, item: &SpannedAst) -> Option<GenericAst> {
+        self.check_as(item, &item.ast)
+    }
+
+    // a top-level expression is checked as a function, which the REPL runs
+    fn check_top_level(&mut self, item: &SpannedAst) -> Option<GenericAst> {
+        match &item.ast {
+            GenericAst::ExprAst(expr) => self.check_expression(item, expr),
+            _ => self.check(item)
+        }
+    }
+
+    /*
+        A top-level expression is the body of a function without parameters, expression.1 for the
+        first one, whose return type is inferred from it. It is named with a '.' like the functions
+        of the runtime, so it cannot clash with a def. Names are still resolved in the expression
+        itself, so that errors do not mention the function.
+    */
+    fn check_expression(&mut self, item: &SpannedAst, expr: &ExprAst) -> Option<GenericAst> {
+        self.expressions += 1;
+        let proto = FuncAst::Prototype { name: format!("expression.{}", self.expressions), args: Vec::new(), return_type: None };
+        let function = GenericAst::FuncAst(FuncAst::Function { proto: Box::new(proto), body: Box::new(expr.clone()) });
+        self.check_as(item, &function)
+    }
+
+    /*
+        The checks keep what an item declares even when it is broken, but a broken item is never
+        generated: calling it afterwards would pass the checks and fail in codegen. The declarations
+        are put back as they were before the item when it has errors.
+    */
+    fn check_as(&mut self, item: &SpannedAst, ast: &GenericAst) -> Option<GenericAst> {
+        let declared = (self.analyzer.clone(), self.type_checker.clone());
+        let checked = self.analyzer.analyze(&item.ast)
+            .map_err(|errors| errors.iter().map(|err| err.to_string()).collect::<Vec<_>>())
+            .and_then(|_| self.type_checker.check_with_spans(ast, &item.spans)
+                .map_err(|errors| errors.iter().map(|err| err.to_string()).collect()));
+        match checked {
+            Ok(checked) => Some(checked),
+            Err(errors) => {
+                (self.analyzer, self.type_checker) = declared;
+                let file = match &self.file {
+                    Some(file) => format!(" of {}", file.display()),
+                    None => String::new()
+                };
+                for err in errors {
+                    eprintln!("{} (item at bytes {}..{}{})", err, item.span.start, item.span.end, file);
+                }
+                None
+            }
+        }
+    }
+}
+
+extern "C" {
+    // the runtime prints with printf, whose buffer is not the one of Rust's stdout
+    fn fflush(stream: *mut c_void) -> i32;
+}
+
+/*
+    Runs a top-level expression of the REPL, checked as a function, under the JIT and prints its
+    value. Every run compiles the whole module again, while the globals keep their values.
+*/
+unsafe fn evaluate(jit: &mut Jit, context: &mut LLVMGeneratorContext, function: &GenericAst) {
+    let (name, return_type) = match function {
+        GenericAst::FuncAst(FuncAst::Function { proto, .. }) => match &**proto {
+            FuncAst::Prototype { name, return_type, .. } => (name, return_type.unwrap_or(DEFAULT_TYPE)),
+            FuncAst::Function { .. } => panic!("Expected Prototype Ast!")
+        },
+        _ => return
+    };
+    // returned in a way that depends on their layout, only run for their effects
+    let wrapper = match return_type {
+        Type::Array | Type::Struct(_) | Type::Function(_) => context.build_discarding_wrapper(name),
+        _ => None
+    };
+    if let Err(err) = jit.compile(context) {
+        eprintln!("Could not compile {}: {}", name, err);
+        return;
+    }
+    let address = jit.function_address(wrapper.as_deref().unwrap_or(name)).unwrap();
+    let value = match return_type {
+        Type::I64 => std::mem::transmute::<u64, extern "C" fn() -> i64>(address)().to_string(),
+        Type::F64 => std::mem::transmute::<u64, extern "C" fn() -> f64>(address)().to_string(),
+        // only the lowest bit of an i1 is defined
+        Type::Bool => (std::mem::transmute::<u64, extern "C" fn() -> u8>(address)() & 1 == 1).to_string(),
+        Type::Str => {
+            let value = std::mem::transmute::<u64, extern "C" fn() -> *const c_char>(address)();
+            format!("{:?}", CStr::from_ptr(value).to_str().unwrap())
+        },
+        Type::Array | Type::Struct(_) | Type::Function(_) => {
+            std::mem::transmute::<u64, extern "C" fn()>(address)();
+            format!("a value of type {}", return_type)
+        }
+    };
+    fflush(std::ptr::null_mut());
+    println!("Evaluated to {}", value);
+}
+
+/*
+    Compiles the file of an import item, from a file of directory, into a module of its own that
+    is linked into the module being generated. The files it imports are compiled the same way when
+    their import is reached. Returns false when the file or one it imports has errors, which are
+    printed; a file imported before is not compiled again.
+*/
+fn import(context: &mut LLVMGeneratorContext, checker: &mut Checker, resolver: &mut Resolver,
+          import_ast: &ImportAst, directory: &Path) -> bool {
+    let compiled = match resolver.resolve(&import_ast.path, directory) {
+        Ok(Some(module)) => compile_module(context, checker, resolver, &module),
+        Ok(None) => return true,
+        Err(err) => {
+            eprintln!("{}", err);
+            return false;
+        }
+    };
+    resolver.finish();
+    compiled
+}
+
+// compiles the prelude, which is linked like an import that every source starts with
+fn load_prelude(context: &mut LLVMGeneratorContext, checker: &mut Checker, resolver: &mut Resolver) -> bool {
+    match resolver.load_prelude() {
+        Ok(module) => compile_module(context, checker, resolver, &module),
+        Err(err) => {
+            eprintln!("{}", err);
+            false
+        }
+    }
+}
+
+fn compile_module(context: &mut LLVMGeneratorContext, checker: &mut Checker, resolver: &mut Resolver, module: &Module) -> bool {
+    let module_directory = module.path.parent().unwrap_or(Path::new(".")).to_path_buf();
+    let importing_file = checker.file.replace(module.path.clone());
+    let mut has_errors = false;
+    let linked = context.link_module(&module.namespace, |context| {
+        for item in &module.items {
+            if let GenericAst::ImportAst(nested) = &item.ast {
+                has_errors |= !import(context, checker, resolver, nested, &module_directory);
+                continue;
+            }
+            let mut item = item.clone();
+            resolver.use_prelude(&mut item.ast);
+            match checker.check(&item) {
+                // like a compiled source, the items after an error are only checked
+                Some(checked) if !has_errors => unsafe {
+                    let _ = checked.generate(context);
+                },
+                Some(_) => {},
+                None => has_errors = true
+            }
+        }
+    });
+    checker.file = importing_file;
+
+    if let Err(err) = linked {
+        eprintln!("{}", err);
+        has_errors = true;
+    }
+    !has_errors
+}
+
 impl Driver {
-    pub fn run() {
+    pub fn run(options: Options) {
         let mut llvm_generator_context = LLVMGeneratorContext::new();
+        let mut checker = Checker::new();
+        let mut resolver = Resolver::new(options.include_path);
+        if options.prelude {
+            load_prelude(&mut llvm_generator_context, &mut checker, &mut resolver);
+        }
+        let mut jit = Jit::new();
         loop {
             print!("ready>> ");
             io::stdout().flush().unwrap(); // flushes the buffer
@@ -25,17 +251,256 @@ impl Driver {
             }
 
             let mut parser = Parser::new(&prompt);
-            let ast = parser.build_next_ast().unwrap();
-            // println!("{}", ast);
+            while let Some(item) = parser.next_spanned() {
+                let mut item = match item {
+                    Ok(item) => item,
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        continue;
+                    }
+                };
+                // println!("{}", ast);
+                resolver.use_prelude(&mut item.ast);
+                let checked = match &item.ast {
+                    // relative to the directory the REPL runs in
+                    GenericAst::ImportAst(import_ast) => {
+                        import(&mut llvm_generator_context, &mut checker, &mut resolver, import_ast, Path::new("."));
+                        continue;
+                    },
+                    _ => checker.check_top_level(&item)
+                };
+                let ast = match checked {
+                    Some(checked) => checked,
+                    None => continue
+                };
 
-            unsafe {
-                let llvm_value_ref = ast.generate(&mut llvm_generator_context);
-                println!("{}", CStr::from_ptr(LLVMPrintValueToString(llvm_value_ref)).to_str().unwrap());
+                unsafe {
+                    let llvm_value_ref = ast.generate(&mut llvm_generator_context);
+                    println!("{}", CStr::from_ptr(LLVMPrintValueToString(llvm_value_ref)).to_str().unwrap());
+                    if let GenericAst::ExprAst(_) = item.ast {
+                        evaluate(&mut jit, &mut llvm_generator_context, &ast);
+                    }
+                }
             }
         }
     }
+
+    /*
+        Compiles a whole source while it is being read, without loading it into memory first.
+        The source is read from path, or from stdin without one, which imports relative to the
+        current directory. The module is named after the file, its top-level expressions are left
+        as functions for the program linked with it to call. Returns false when the source has
+        errors, then nothing is printed.
+    */
+    pub fn compile<R: Read>(reader: R, path: Option<&Path>, options: Options) -> bool {
+        let (mut llvm_generator_context, mut resolver, directory) = match path {
+            Some(path) => (
+                LLVMGeneratorContext::with_module_name(&path.file_stem().unwrap_or_default().to_string_lossy()),
+                Resolver::with_root(options.include_path, path),
+                path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."))
+            ),
+            None => (LLVMGeneratorContext::new(), Resolver::new(options.include_path), Path::new("."))
+        };
+        let mut checker = Checker::new();
+        let mut has_errors = options.prelude && !load_prelude(&mut llvm_generator_context, &mut checker, &mut resolver);
+        let mut parser = Parser::from_reader(reader);
+        while let Some(item) = parser.next_spanned() {
+            match item {
+                Ok(SpannedAst { ast: GenericAst::ImportAst(import_ast), .. }) => {
+                    has_errors |= !import(&mut llvm_generator_context, &mut checker, &mut resolver, &import_ast, directory);
+                },
+                Ok(mut item) => {
+                    resolver.use_prelude(&mut item.ast);
+                    match checker.check_top_level(&item) {
+                        // keep checking to report every error, but stop generating code after the first one
+                        Some(checked) if !has_errors => unsafe {
+                            let _ = checked.generate(&mut llvm_generator_context);
+                        },
+                        Some(_) => {},
+                        None => has_errors = true
+                    }
+                },
+                Err(err) => {
+                    eprintln!("{}", err);
+                    has_errors = true;
+                }
+            }
+        }
+
+        if has_errors {
+            return false;
+        }
+        if let Err(err) = llvm_generator_context.verify() {
+            eprintln!("{}", err);
+            return false;
+        }
+        println!("{}", llvm_generator_context.get_module_as_string());
+        true
+    }
+
+    // prints every top-level item with its span, nothing is printed when the source has errors
+    pub fn emit_ast<R: Read>(reader: R, format: AstFormat) -> bool {
+        let mut parser = Parser::from_reader(reader);
+        let mut items: Vec<SpannedAst> = Vec::new();
+        let mut has_errors = false;
+        while let Some(item) = parser.next_spanned() {
+            match item {
+                Ok(item) => items.push(item),
+                Err(err) => {
+                    eprintln!("{}", err);
+                    has_errors = true;
+                }
+            }
+        }
+
+        if has_errors {
+            return false;
+        }
+        match format {
+            AstFormat::Json => println!("{}", serde_json::to_string_pretty(&items).unwrap()),
+            AstFormat::Binary => {
+                let bytes = postcard::to_allocvec(&items).unwrap();
+                io::stdout().write_all(&bytes).unwrap();
+            }
+        }
+        true
+    }
+}
+
+/*
+    fmt [--check] <path|->...
+    Rewrites the files in place, or prints the formatted source for '-'. With --check nothing is
+    written, the files that are not formatted are listed instead. Exits with 1 when a file has
+    parse errors or, in check mode, is not formatted.
+*/
+fn fmt(args: &[String]) {
+    let check = args.first().is_some_and(|arg| arg == CHECK_FLAG);
+    let paths = if check { &args[1..] } else { args };
+    if paths.is_empty() {
+        eprintln!("Usage: {} [{}] <path|{}>...", FMT_CMD, CHECK_FLAG, STDIN_ARG);
+        std::process::exit(1);
+    }
+    // stdin can only be read once, the second '-' would format an empty source
+    if paths.iter().filter(|path| *path == STDIN_ARG).count() > 1 {
+        eprintln!("{} can only format stdin ({}) once.", FMT_CMD, STDIN_ARG);
+        std::process::exit(1);
+    }
+
+    let mut failed = false;
+    for path in paths {
+        let mut src = String::new();
+        let read = if path == STDIN_ARG {
+            io::stdin().read_to_string(&mut src)
+        } else {
+            File::open(path).and_then(|mut file| file.read_to_string(&mut src))
+        };
+        if let Err(err) = read {
+            eprintln!("Could not read {}: {}", path, err);
+            failed = true;
+            continue;
+        }
+
+        let formatted = match format_source(&src) {
+            Ok(formatted) => formatted,
+            Err(errors) => {
+                for err in errors {
+                    eprintln!("{}: {}", path, err);
+                }
+                failed = true;
+                continue;
+            }
+        };
+
+        if check {
+            if formatted != src {
+                println!("Not formatted: {}", path);
+                failed = true;
+            }
+        } else if path == STDIN_ARG {
+            print!("{}", formatted);
+        } else if formatted != src {
+            if let Err(err) = std::fs::write(path, formatted) {
+                eprintln!("Could not write {}: {}", path, err);
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+// returns false when the source cannot be opened or run fails on it
+fn with_source<F: FnOnce(Box<dyn Read>) -> bool>(path: &str, run: F) -> bool {
+    if path == STDIN_ARG {
+        return run(Box::new(io::stdin().lock()));
+    }
+    match File::open(path) {
+        Ok(file) => run(Box::new(file)),
+        Err(err) => {
+            eprintln!("Could not open {}: {}", path, err);
+            false
+        }
+    }
+}
+
+// emit-ast [--format json|binary] <path|->
+fn emit_ast(args: &[String]) -> bool {
+    let (format, path) = match args {
+        [flag, format, path] if flag == FORMAT_FLAG => match AstFormat::from_arg(format) {
+            Some(format) => (format, path),
+            None => {
+                eprintln!("Unknown AST format {}, expected json or binary", format);
+                return false;
+            }
+        },
+        [path] => (AstFormat::Json, path),
+        _ => {
+            eprintln!("Usage: {} [{} json|binary] <path|{}>", EMIT_AST_CMD, FORMAT_FLAG, STDIN_ARG);
+            return false;
+        }
+    };
+    with_source(path, |reader| Driver::emit_ast(reader, format))
+}
+
+/*
+    -I <dir> adds a directory to the include path, which is searched in order for the imported files.
+    --no-prelude compiles without the prelude, its functions have to be declared by the source.
+*/
+fn split_options(args: Vec<String>) -> (Options, Vec<String>) {
+    let mut options = Options { include_path: Vec::new(), prelude: true };
+    let mut rest = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == NO_PRELUDE_FLAG {
+            options.prelude = false;
+        } else if arg != INCLUDE_FLAG {
+            rest.push(arg);
+        } else if let Some(dir) = args.next() {
+            options.include_path.push(PathBuf::from(dir));
+        }
+    }
+    (options, rest)
 }
 
 fn main() {
-    Driver::run();
-}
\ No newline at end of file
+    let (options, args) = split_options(std::env::args().skip(1).collect());
+    // a command exits with 1 when its source has errors
+    let succeeded = match args.first() {
+        Some(cmd) if cmd == EMIT_AST_CMD => emit_ast(&args[1..]),
+        Some(cmd) if cmd == FMT_CMD => {
+            fmt(&args[1..]);
+            true
+        },
+        Some(path) if path == STDIN_ARG => with_source(path, |reader| Driver::compile(reader, None, options)),
+        Some(path) => with_source(path, |reader| Driver::compile(reader, Some(Path::new(path)), options)),
+        None => {
+            Driver::run(options);
+            true
+        }
+    };
+    if !succeeded {
+        std::process::exit(1);
+    }
+}
@@ -1,89 +1,318 @@
+use core::fmt;
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fmt::Formatter;
+use std::io::{BufRead, BufReader, Read};
+
+use unicode_xid::UnicodeXID;
+
 use crate::parse::token::*;
 use crate::syntax::vocabulary::*;
 
+#[derive(PartialEq, Debug, Clone)]
+pub enum LexError {
+    InvalidNumber { literal: String, reason: String },
+    NumberOutOfRange { literal: String, reason: String },
+    UnexpectedCharacter(char),
+    UnterminatedString,
+    InvalidEscape(char),
+    Io(String)
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LexError::InvalidNumber { literal, reason } => write!(f, "Invalid numeric literal '{}': {}", literal, reason),
+            LexError::NumberOutOfRange { literal, reason } => write!(f, "Numeric literal '{}' out of range: {}", literal, reason),
+            LexError::UnexpectedCharacter(c) => write!(f, "Unexpected character '{}' (U+{:04X})", c, *c as u32),
+            LexError::UnterminatedString => write!(f, "Unterminated string literal, strings end on the same line"),
+            LexError::InvalidEscape(c) => write!(f, "Invalid escape '\\{}' in string literal, expected \\n, \\t, \\r, \\0, \\\\ or \\\"", c),
+            LexError::Io(err) => write!(f, "Failed to read source: {}", err)
+        }
+    }
+}
+
+/*
+    The lexer works on byte offsets into the source. Every read_* helper returns the number of
+    bytes it consumed, so slicing the source with them always lands on a char boundary.
+
+    The source is either borrowed in full or streamed from a reader. A streamed source is read
+    line by line, which is enough since no token spans a line break: before lexing a token the
+    buffer is refilled until a line break follows the token. Consumed text is dropped from the
+    buffer as it grows, spans stay relative to the start of the whole source.
+*/
 pub struct Lexer<'a> {
-    data: &'a str,
-    token_cache: Token,
+    buffer: Cow<'a, str>,
+    buffer_offset: usize,
+    position: usize,
+    reader: Option<Box<dyn BufRead + 'a>>,
+    lookahead: VecDeque<SpannedToken>,
+    // end of the last popped token, so the parser can tell where an item ends
+    popped_end: usize,
+    // '{' minus '}' popped so far, so the parser can skip a broken item up to the end of its blocks
+    brace_depth: isize
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(src: &str) -> Lexer {
+        Lexer::with_source(Cow::Borrowed(src), None)
+    }
+
+    pub fn from_reader<R: Read + 'a>(reader: R) -> Lexer<'a> {
+        Lexer::with_source(Cow::Owned(String::new()), Some(Box::new(BufReader::new(reader))))
+    }
+
+    fn with_source(buffer: Cow<'a, str>, reader: Option<Box<dyn BufRead + 'a>>) -> Lexer<'a> {
         let mut lexer = Lexer {
-            data: src,
-            token_cache: Token::TokEof
+            buffer,
+            buffer_offset: 0,
+            position: 0,
+            reader,
+            lookahead: VecDeque::new(),
+            popped_end: 0,
+            brace_depth: 0
         };
         lexer.init();
         lexer
     }
 
     pub fn peek(&self) -> &Token {
-        &self.token_cache
+        &self.lookahead[0].token
+    }
+
+    // peek_nth(0) is the same as peek()
+    pub fn peek_nth(&mut self, n: usize) -> &Token {
+        while self.lookahead.len() <= n {
+            let next = self.parse_spanned_token();
+            self.lookahead.push_back(next);
+        }
+        &self.lookahead[n].token
     }
 
     pub fn pop(&mut self) -> Token {
-        let popped = std::mem::take(&mut self.token_cache);
-        (self.token_cache, self.data) = Self::parse_and_slide(self.data);
+        self.pop_with_trivia().token
+    }
+
+    pub fn peek_with_trivia(&self) -> &SpannedToken {
+        &self.lookahead[0]
+    }
+
+    pub fn pop_with_trivia(&mut self) -> SpannedToken {
+        if self.lookahead.len() == 1 {
+            let next = self.parse_spanned_token();
+            self.lookahead.push_back(next);
+        }
+        let popped = self.lookahead.pop_front().unwrap();
+        self.popped_end = popped.span.end;
+        match popped.token {
+            Token::TokSymbol('{') => self.brace_depth += 1,
+            Token::TokSymbol('}') => self.brace_depth -= 1,
+            _ => {}
+        }
         popped
     }
 
+    pub fn popped_end(&self) -> usize {
+        self.popped_end
+    }
+
+    pub fn brace_depth(&self) -> isize {
+        self.brace_depth
+    }
+
     fn init(&mut self) {
-        (self.token_cache, self.data) = Self::parse_and_slide(self.data)
+        let first = self.parse_spanned_token();
+        self.lookahead.push_back(first);
     }
 
-    fn parse_and_slide(data: &str) -> (Token, &str) {
-        let (token, read_count) = Self::parse_token(data);
-        (token, &data[read_count..])
+    fn parse_spanned_token(&mut self) -> SpannedToken {
+        let offset = self.buffer_offset + self.position;
+        if let Err(err) = self.fill_buffer() {
+            self.reader = None; // do not read past a failure
+            return SpannedToken { token: Token::TokError(err), span: Span { start: offset, end: offset }, ..Default::default() };
+        }
+
+        let offset = self.buffer_offset + self.position;
+        let data = &self.buffer[self.position..];
+        let (leading, leading_count) = Self::read_trivia(data, true);
+        let (token, token_count) = Self::parse_token(&data[leading_count..]);
+
+        let read_count = leading_count + token_count;
+        let (trailing, trailing_count) = if token != Token::TokEof {
+            Self::read_trivia(&data[read_count..], false)
+        } else {
+            (Vec::new(), 0)
+        };
+
+        let span = Span { start: offset + leading_count, end: offset + read_count };
+        self.position += read_count + trailing_count;
+        SpannedToken { leading, token, trailing, span }
     }
 
-    fn parse_token(data: &str) -> (Token, usize) {
-        let trim_count = Self::trim_start(data);
+    fn fill_buffer(&mut self) -> Result<(), LexError> {
+        let reader = match self.reader.as_mut() {
+            Some(reader) => reader,
+            None => return Ok(())
+        };
 
-        let trimmed_data = &data[trim_count..];
+        // drop consumed text once it makes up half of the buffer, which keeps compaction linear overall
+        if self.position > 0 && self.position * 2 >= self.buffer.len() {
+            self.buffer.to_mut().drain(..self.position);
+            self.buffer_offset += self.position;
+            self.position = 0;
+        }
 
-        if trimmed_data.len() ==  0 {
-            return (Token::TokEof, trim_count);
+        while !Self::holds_complete_token(&self.buffer[self.position..]) {
+            let read_count = reader.read_line(self.buffer.to_mut()).map_err(|err| LexError::Io(err.to_string()))?;
+            if read_count == 0 {
+                self.reader = None;
+                break;
+            }
         }
+        Ok(())
+    }
 
-        let first_char = trimmed_data.chars().nth(0).unwrap();
-        let (token, token_count) = match first_char {
-            'a'..='z' | 'A'..='Z' => {
-                let (token_str, token_count) = Self::read_token_str(trimmed_data, false);
+    fn holds_complete_token(data: &str) -> bool {
+        let (_, leading_count) = Self::read_trivia(data, true);
+        data[leading_count..].contains(is_line_break)
+    }
+
+    fn parse_token(data: &str) -> (Token, usize) {
+        if data.len() ==  0 {
+            return (Token::TokEof, 0);
+        }
+
+        let first_char = data.chars().nth(0).unwrap();
+        match first_char {
+            '0'..='9' => Self::read_number(data),
+            '.' if data[1..].starts_with(|c: char| c.is_ascii_digit()) => Self::read_number(data),
+            '"' => Self::read_string(data),
+            c if is_identifier_start(c) => {
+                let (token_str, token_count) = Self::read_identifier(data);
                 (Token::from(token_str), token_count)
             }
-            '0'..='9' => {
-                let (token_value, token_count) = Self::read_token_str(trimmed_data, false);
-                (Token::from(token_value.parse::<f64>().unwrap()), token_count)
-            }
-            '#' => {
-                let (comment_str, token_count) = Self::read_token_str(trimmed_data, true);
-                (Token::from(comment_str), token_count)
-            }
-            _ => {
-                let (token_char, token_count) = Self::read_primary_token(trimmed_data);
-                (Token::from(token_char), token_count)
-            }
-        };
-        (token, (trim_count + token_count))
+            c if is_symbol_char(c) => Self::read_primary_token(data),
+            c => (Token::TokError(LexError::UnexpectedCharacter(c)), c.len_utf8())
+        }
     }
 
-    fn trim_start(data: &str) -> usize {
-        Self::read_while(data, |c| { c.is_whitespace() })
+    /*
+        Reads whitespace and comments. Leading trivia spans lines, trailing trivia stops
+        before the first line break so that the next line belongs to the next token.
+    */
+    fn read_trivia(data: &str, span_lines: bool) -> (Vec<Trivia>, usize) {
+        let mut trivia = Vec::new();
+        let mut read_count = 0;
+        loop {
+            let remaining = &data[read_count..];
+            let count = match remaining.chars().nth(0) {
+                Some('#') => {
+                    let count = Self::read_while(remaining, |c| { !is_line_break(c) });
+                    trivia.push(Trivia::Comment(remaining[..count].to_string()));
+                    count
+                }
+                Some(c) if c.is_whitespace() => {
+                    let count = Self::read_while(remaining, |c| {
+                        c.is_whitespace() && (span_lines || !is_line_break(c))
+                    });
+                    if count == 0 {
+                        break;
+                    }
+                    trivia.push(Trivia::Whitespace(remaining[..count].to_string()));
+                    count
+                }
+                _ => break
+            };
+            read_count += count;
+        }
+        (trivia, read_count)
     }
 
-    fn read_token_str(data: &str, consume_space_char: bool) -> (&str, usize) {
-        let read_count = if !consume_space_char {
-            Self::read_while(data, |c| { !(c.is_whitespace() || is_symbol_char(c)) })
-        } else {
-            Self::read_while(data, |c| { !((c == '\r') || (c == '\n') || is_symbol_char(c)) })
-        };
+    // a name can be qualified with the namespaces of the files it was imported from, like math::sqrt
+    fn read_identifier(data: &str) -> (&str, usize) {
+        let mut read_count = Self::read_while(data, is_xid_continue);
+        while data[read_count..].starts_with(NAMESPACE_SEPARATOR)
+            && data[read_count + NAMESPACE_SEPARATOR.len()..].starts_with(is_identifier_start) {
+            read_count += NAMESPACE_SEPARATOR.len();
+            read_count += Self::read_while(&data[read_count..], is_xid_continue);
+        }
         (&data[..read_count], read_count)
     }
 
-    fn read_primary_token(data: &str) -> (char, usize) {
+    /*
+        Everything that could belong to a numeric literal is read first and validated afterwards,
+        so that "1.2.3" or "3abc" are reported as a single bad literal instead of being split into
+        several valid tokens. A sign is only part of the literal directly after a decimal exponent.
+    */
+    fn read_number(data: &str) -> (Token, usize) {
+        let is_radix_literal = data.starts_with("0x") || data.starts_with("0X")
+            || data.starts_with("0b") || data.starts_with("0B");
+
+        let mut read_count = 0;
+        let mut prev_char = '\0';
+        for c in data.chars() {
+            let is_exponent_sign = (c == '+' || c == '-') && (prev_char == 'e' || prev_char == 'E') && !is_radix_literal;
+            if !(is_xid_continue(c) || c == '.' || is_exponent_sign) {
+                break;
+            }
+            prev_char = c;
+            read_count += c.len_utf8();
+        }
+
+        let literal = &data[..read_count];
+        match parse_number_literal(literal) {
+            Ok(value) => (Token::from(value), read_count),
+            Err(err) => (Token::TokError(err), read_count)
+        }
+    }
+
+    /*
+        Reads a string literal up to its closing quote, which has to be on the same line.
+        An invalid escape is reported for the whole literal, so that lexing continues after it.
+    */
+    fn read_string(data: &str) -> (Token, usize) {
+        let mut value = String::new();
+        let mut error = None;
+        let mut chars = data.char_indices().skip(1);
+        while let Some((offset, c)) = chars.next() {
+            match c {
+                '"' => {
+                    let token = match error {
+                        Some(err) => Token::TokError(err),
+                        None => Token::TokString(value)
+                    };
+                    return (token, offset + 1);
+                },
+                '\\' => match chars.next() {
+                    Some((_, escaped)) => match unescape(escaped) {
+                        Some(unescaped) => value.push(unescaped),
+                        None => {
+                            error.get_or_insert(LexError::InvalidEscape(escaped));
+                        }
+                    },
+                    None => break
+                },
+                c if is_line_break(c) => return (Token::TokError(LexError::UnterminatedString), offset),
+                c => value.push(c)
+            }
+        }
+        (Token::TokError(LexError::UnterminatedString), data.len())
+    }
+
+    fn read_primary_token(data: &str) -> (Token, usize) {
         let primary_tok_char = data.chars().nth(0).unwrap();
-        (primary_tok_char, 1)
+        if SYMBOL_NON_OP_CHARS.contains(&primary_tok_char) {
+            return (Token::from(primary_tok_char), primary_tok_char.len_utf8());
+        }
+
+        match OPERATORS.iter().find(|op| data.starts_with(**op)) {
+            Some(op) => (Token::TokOperator(op.to_string()), op.len()),
+            // a single '&' or '|'
+            None => (Token::TokError(LexError::UnexpectedCharacter(primary_tok_char)), primary_tok_char.len_utf8())
+        }
     }
 
+    // returns the number of bytes (not chars) matching the predicate
     fn read_while<F>(data: &str, pred: F) -> usize
         where F: Fn(char) -> bool {
         let mut read_count = 0;
@@ -91,8 +320,146 @@ impl<'a> Lexer<'a> {
             if !pred(elem) {
                 break;
             }
-            read_count += 1;
+            read_count += elem.len_utf8();
         }
         read_count
     }
-}
\ No newline at end of file
+}
+
+/*
+    Iterates over the tokens up to (excluding) the end of input. Lexing errors are yielded
+    in place of the offending token and iteration continues after them.
+*/
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Result<SpannedToken, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if *self.peek() == Token::TokEof {
+            return None;
+        }
+
+        let spanned_token = self.pop_with_trivia();
+        match spanned_token.token {
+            Token::TokError(err) => Some(Err(err)),
+            _ => Some(Ok(spanned_token))
+        }
+    }
+}
+
+fn is_line_break(c: char) -> bool {
+    c == '\r' || c == '\n'
+}
+
+// the character an escape sequence in a string literal stands for, the inverse of escape_string
+fn unescape(c: char) -> Option<char> {
+    match c {
+        'n' => Some('\n'),
+        't' => Some('\t'),
+        'r' => Some('\r'),
+        '0' => Some('\0'),
+        '\\' | '"' => Some(c),
+        _ => None
+    }
+}
+
+// writes a string back as a literal that reads to the same value
+pub fn escape_string(value: &str) -> String {
+    let mut literal = String::from('"');
+    for c in value.chars() {
+        match c {
+            '\n' => literal.push_str("\\n"),
+            '\t' => literal.push_str("\\t"),
+            '\r' => literal.push_str("\\r"),
+            '\0' => literal.push_str("\\0"),
+            '\\' | '"' => {
+                literal.push('\\');
+                literal.push(c);
+            },
+            c => literal.push(c)
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+fn is_identifier_start(c: char) -> bool {
+    c == '_' || UnicodeXID::is_xid_start(c)
+}
+
+fn is_xid_continue(c: char) -> bool {
+    UnicodeXID::is_xid_continue(c)
+}
+
+/*
+    Numeric literal forms:
+        decimal     42, 1_000_000, 1.5, .5, 1e-3, 2.5E+10
+        hexadecimal 0x1F
+        binary      0b1010
+    Underscores may only appear between two digits.
+*/
+pub fn parse_number_literal(literal: &str) -> Result<f64, LexError> {
+    let invalid = |reason: String| LexError::InvalidNumber { literal: literal.to_string(), reason };
+    let out_of_range = |reason: &str| LexError::NumberOutOfRange { literal: literal.to_string(), reason: reason.to_string() };
+
+    let (radix, digits) = match literal.get(..2) {
+        Some("0x") | Some("0X") => (16, &literal[2..]),
+        Some("0b") | Some("0B") => (2, &literal[2..]),
+        _ => (10, literal)
+    };
+
+    if radix != 10 {
+        check_digits(digits, radix, "digits after the radix prefix").map_err(invalid)?;
+        let cleaned = digits.replace('_', "");
+        return u64::from_str_radix(&cleaned, radix)
+            .map(|value| value as f64)
+            .map_err(|_| out_of_range("value does not fit in 64 bits"));
+    }
+
+    let (mantissa, exponent) = match digits.find(['e', 'E']) {
+        Some(idx) => (&digits[..idx], Some(&digits[idx + 1..])),
+        None => (digits, None)
+    };
+    let (integral, fraction) = match mantissa.find('.') {
+        Some(idx) => (&mantissa[..idx], Some(&mantissa[idx + 1..])),
+        None => (mantissa, None)
+    };
+
+    match fraction {
+        Some(fraction) => {
+            if fraction.contains('.') {
+                return Err(invalid("more than one decimal point".to_string()));
+            }
+            if !integral.is_empty() {
+                check_digits(integral, radix, "integral digits").map_err(invalid)?;
+            }
+            check_digits(fraction, radix, "digits after the decimal point").map_err(invalid)?;
+        },
+        None => check_digits(integral, radix, "digits").map_err(invalid)?
+    }
+    if let Some(exponent) = exponent {
+        let exponent_digits = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+        check_digits(exponent_digits, radix, "exponent digits").map_err(invalid)?;
+    }
+
+    let value = literal.replace('_', "").parse::<f64>().map_err(|err| invalid(err.to_string()))?;
+    if value.is_infinite() {
+        return Err(out_of_range("value is too large for a 64-bit float"));
+    }
+    if value == 0.0 && mantissa.contains(['1', '2', '3', '4', '5', '6', '7', '8', '9']) {
+        return Err(out_of_range("value is too small for a 64-bit float"));
+    }
+    Ok(value)
+}
+
+fn check_digits(digits: &str, radix: u32, expected: &str) -> Result<(), String> {
+    if digits.is_empty() {
+        return Err(format!("expected {}", expected));
+    }
+    if let Some(c) = digits.chars().find(|c| !(c.is_digit(radix) || *c == '_')) {
+        return Err(format!("unexpected character '{}' in base {} literal", c, radix));
+    }
+    if digits.starts_with('_') || digits.ends_with('_') || digits.contains("__") {
+        return Err("'_' must separate two digits".to_string());
+    }
+    Ok(())
+}
@@ -1,7 +1,10 @@
 use core::fmt;
 use std::fmt::Formatter;
+use std::io::Read;
 
 use crate::syntax::ast::*;
+use crate::syntax::types::{FunctionType, StructName, Type};
+use crate::syntax::vocabulary::*;
 use crate::parse::lexer::*;
 use crate::parse::token::*;
 
@@ -18,24 +21,133 @@ impl fmt::Display for ParseError {
     }
 }
 
+// every top-level item of a source, together with the errors of the items that failed to parse
+#[derive(Debug)]
+pub struct Program {
+    pub items: Vec<GenericAst>,
+    pub errors: Vec<ParseError>
+}
+
 pub struct Parser<'a> {
-    lexer: Lexer<'a>
+    lexer: Lexer<'a>,
+    // the spans of the expressions of the item being parsed, see SpannedAst
+    spans: Vec<Span>
 }
 
 impl<'a> Parser<'a> {
     pub fn new(src: &str) -> Parser {
         Parser {
-            lexer: Lexer::new(src)
+            lexer: Lexer::new(src),
+            spans: Vec::new()
         }
     }
 
+    pub fn from_reader<R: Read + 'a>(reader: R) -> Parser<'a> {
+        Parser {
+            lexer: Lexer::from_reader(reader),
+            spans: Vec::new()
+        }
+    }
+
+    // true once only empty statements (';') are left
+    pub fn is_eof(&mut self) -> bool {
+        self.skip_empty_items();
+        *self.lexer.peek() == Token::TokEof
+    }
+
+    pub fn parse_program(&mut self) -> Program {
+        let mut program = Program { items: Vec::new(), errors: Vec::new() };
+        for item in self {
+            match item {
+                Ok(ast) => program.items.push(ast),
+                Err(err) => program.errors.push(err)
+            }
+        }
+        program
+    }
+
+    /*
+        Parses a single top-level item. Prefer iterating over the parser, which tells the end of
+        input (None) apart from errors and skips broken items; this returns an "EOF" error instead.
+    */
     pub fn build_next_ast(&mut self) -> Result<GenericAst, ParseError> {
-            return match self.peek_lexer() {
+        self.build_next_spanned_ast().map(|item| item.ast)
+    }
+
+    pub fn build_next_spanned_ast(&mut self) -> Result<SpannedAst, ParseError> {
+            self.skip_empty_items();
+            self.spans.clear();
+            let start = self.peek_start();
+            let ast = match self.peek_lexer() {
                 Token::TokEof => Err(ParseError("EOF".to_string())),
                 Token::TokDef => Ok(GenericAst::FuncAst(self.parse_function_definition()?)),
                 Token::TokExtern => Ok(GenericAst::FuncAst(self.parse_extern_call_expression()?)),
+                Token::TokStruct => Ok(GenericAst::StructAst(self.parse_struct_definition()?)),
+                Token::TokConst | Token::TokGlobal => Ok(GenericAst::GlobalAst(self.parse_global_definition()?)),
+                Token::TokImport => Ok(GenericAst::ImportAst(self.parse_import()?)),
                 _default => Ok(GenericAst::ExprAst(self.parse_abstract_expression()?))
+            }?;
+            let span = Span { start, end: self.lexer.popped_end() };
+            self.parse_item_terminator()?;
+            Ok(SpannedAst { span, ast, spans: std::mem::take(&mut self.spans) })
+    }
+
+    // same as next(), but keeps the span of every item
+    pub fn next_spanned(&mut self) -> Option<Result<SpannedAst, ParseError>> {
+        if self.is_eof() {
+            return None;
+        }
+
+        let item_start = self.lexer.peek_with_trivia().span.start;
+        let item_depth = self.lexer.brace_depth();
+        let item = self.build_next_spanned_ast();
+        if item.is_err() {
+            self.recover(item_start, item_depth);
+        }
+        Some(item)
+    }
+
+    fn skip_empty_items(&mut self) {
+        while Token::TokSymbol(';') == *self.peek_lexer() {
+            self.pop_lexer(); // pop ;
+        }
+    }
+
+    // a top-level item ends with an optional ';', but has to be followed by a line break otherwise
+    fn parse_item_terminator(&mut self) -> Result<(), ParseError> {
+        let next = self.lexer.peek_with_trivia();
+        match next.token {
+            Token::TokSymbol(';') => {
+                self.pop_lexer(); // pop ;
+                Ok(())
+            },
+            Token::TokEof => Ok(()),
+            _ if next.starts_line() => Ok(()),
+            _ => Err(ParseError(format!("Expected ';' or a line break after top-level item, found {} at byte {}.", next.token, next.span.start)))
+        }
+    }
+
+    /*
+        Skips a broken item up to and including the next ';' outside of its blocks, or up to the
+        next definition. The ';' inside an unfinished { a; b } sequence the block, stopping there
+        would report the rest of the block as more broken items.
+    */
+    fn recover(&mut self, item_start: usize, item_depth: isize) {
+        loop {
+            let next = self.lexer.peek_with_trivia();
+            match next.token {
+                Token::TokEof => break,
+                Token::TokSymbol(';') if self.lexer.brace_depth() <= item_depth => {
+                    self.pop_lexer(); // pop ;
+                    break;
+                },
+                Token::TokDef | Token::TokExtern | Token::TokStruct | Token::TokConst | Token::TokGlobal | Token::TokImport
+                    if next.span.start != item_start => break,
+                _ => {
+                    self.pop_lexer();
+                }
             }
+        }
     }
 
     fn parse_function_definition(&mut self) -> Result<FuncAst, ParseError> {
@@ -49,115 +161,423 @@ impl<'a> Parser<'a> {
         self.parse_prototype()
     }
 
+    // struct Name { field, field: type, ... }
+    fn parse_struct_definition(&mut self) -> Result<StructAst, ParseError> {
+        self.lexer.pop(); // pop struct
+        let name = match self.pop_lexer() {
+            Token::TokIdentifier(name) => name,
+            token => return Err(ParseError(format!("Expected the name of the struct, found {}.", token)))
+        };
+        if Type::from_name(&name).is_some() {
+            return Err(ParseError(format!("Cannot declare struct {}, it is the name of a builtin type.", name)));
+        }
+
+        let opener = self.lexer.pop_with_trivia();
+        if opener.token != Token::TokSymbol('{') {
+            return Err(ParseError(format!("Expected the fields of struct {} to begin with '{{'.", name)));
+        }
+        let fields = self.parse_params()?;
+        self.expect_closing(opener.span, '}', &format!("struct {}", name))?;
+        Ok(StructAst { name, fields })
+    }
+
+    // const name = value or global name = value, the name can have a type like a parameter
+    fn parse_global_definition(&mut self) -> Result<GlobalAst, ParseError> {
+        let constant = self.pop_lexer() == Token::TokConst; // pop const or global
+        let keyword = if constant { "const" } else { "global" };
+        if let Token::TokIdentifier(_) = self.peek_lexer() {} else {
+            return Err(ParseError(format!("Expected the name of the {}, found {}.", keyword, self.peek_lexer())));
+        }
+        let Param { name, ty } = self.parse_param()?;
+
+        match self.pop_lexer() {
+            Token::TokOperator(op) if op == "=" => {},
+            token => return Err(ParseError(format!("Expected '=' and the value of {} {}, found {}.", keyword, name, token)))
+        }
+        let value = self.parse_abstract_expression()?;
+        Ok(GlobalAst { name, ty, value, constant })
+    }
+
+    // import "path"
+    fn parse_import(&mut self) -> Result<ImportAst, ParseError> {
+        self.lexer.pop(); // pop import
+        match self.pop_lexer() {
+            Token::TokString(path) => Ok(ImportAst { path }),
+            token => Err(ParseError(format!("Expected the path of the imported file as a string, found {}.", token)))
+        }
+    }
+
     fn parse_abstract_expression(&mut self) -> Result<ExprAst, ParseError> {
-        let lhs = self.parse_single_expression_unit()?;
-        self.parse_op_and_rhs(lhs, 0)
+        self.parse_binary_expression(0)
     }
 
+    // name(arg, arg: type, ...) with an optional '-> type' for the return value
     fn parse_prototype(&mut self) -> Result<FuncAst, ParseError> {
         if let Token::TokIdentifier(fn_ident) = self.lexer.pop() {
-            let mut args = Vec::new();
-
-            if self.lexer.pop() != Token::TokSymbol('(') {
+            let opener = self.lexer.pop_with_trivia();
+            if opener.token != Token::TokSymbol('(') {
                 return Err(ParseError("Expected prototype AST to begin with '('.".to_string()));
             }
 
-            while let Token::TokIdentifier(_) = self.peek_lexer() {
-                if let Token::TokIdentifier(arg_ident) = self.lexer.pop() {
-                    args.push(arg_ident);
-                    if let Token::TokSymbol(',') = self.peek_lexer() {
-                        self.pop_lexer(); // pop the comma
-                    } else {
-                        break;
-                    }
-                }
-            }
+            let args = self.parse_params()?;
+            self.expect_closing(opener.span, ')', &format!("prototype of {}", fn_ident))?;
 
-            if self.lexer.pop() != Token::TokSymbol(')') {
-                return Err(ParseError("Expected prototype AST to end with ')'.".to_string()));
-            }
-            Ok(FuncAst::Prototype { name: fn_ident.to_string(), args })
+            let return_type = if Token::TokOperator("->".to_string()) == *self.peek_lexer() {
+                self.pop_lexer(); // pop ->
+                Some(self.parse_type()?)
+            } else {
+                None
+            };
+            Ok(FuncAst::Prototype { name: fn_ident.to_string(), args, return_type })
         } else {
             return Err(ParseError("Attempted to parse non-prototype AST as prototype.".to_string()));
         }
     }
 
-    fn parse_op_and_rhs(&mut self, mut lhs: ExprAst, min_precedence: i8) -> Result<ExprAst, ParseError> {
-            while self.peek_lexer().is_tok_symbol() { // next operator
-                let precedence = get_token_precedence(&self.peek_lexer());
-                if precedence >= min_precedence {
-                    if let Token::TokSymbol(op) = self.lexer.pop() {
-                        let mut rhs = self.parse_single_expression_unit()?;
-                        while self.peek_lexer().is_tok_symbol() {
-                            let peek_precedence = get_token_precedence(&self.peek_lexer());
-                            if peek_precedence > precedence {
-                                rhs = self.parse_op_and_rhs(rhs, precedence+1)?;
-                            } else {
-                                break;
-                            }
-                            // equal condition ?
-                            }
-                        lhs = ExprAst::BinaryExpr { op, lhs: Box::new(lhs), rhs: Box::new(rhs) };
-                    }
-                } else {
-                    break;
+    // comma-separated parameters or fields up to the closing symbol, a trailing comma is allowed
+    fn parse_params(&mut self) -> Result<Vec<Param>, ParseError> {
+        let mut params = Vec::new();
+        while let Token::TokIdentifier(_) = self.peek_lexer() {
+            params.push(self.parse_param()?);
+            if let Token::TokSymbol(',') = self.peek_lexer() {
+                self.pop_lexer(); // pop the comma
+            } else {
+                break;
+            }
+        }
+        Ok(params)
+    }
+
+    // a parameter or a field, x or x: type
+    fn parse_param(&mut self) -> Result<Param, ParseError> {
+        let name = match self.pop_lexer() {
+            Token::TokIdentifier(name) => name,
+            token => return Err(ParseError(format!("Expected a name, found {}.", token)))
+        };
+        let ty = if Token::TokSymbol(':') == *self.peek_lexer() {
+            self.pop_lexer(); // pop :
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+        Ok(Param { name, ty })
+    }
+
+    /*
+        A builtin type name, [f64] for an array, fn(types) -> type for a function or the name of a
+        struct. Struct names are only known once the items before are analyzed, unknown ones are
+        left to the SemanticAnalyzer.
+    */
+    fn parse_type(&mut self) -> Result<Type, ParseError> {
+        match self.pop_lexer() {
+            Token::TokIdentifier(name) if name == "fn" && Token::TokSymbol('(') == *self.peek_lexer() => self.parse_function_type(),
+            Token::TokIdentifier(name) => Ok(Type::from_name(&name).unwrap_or_else(|| Type::Struct(StructName::new(&name)))),
+            Token::TokSymbol('[') => {
+                let element_type = self.parse_type()?;
+                if element_type != Type::F64 {
+                    return Err(ParseError(format!("Arrays can only hold f64, found [{}].", element_type)));
                 }
+                match self.pop_lexer() {
+                    Token::TokSymbol(']') => Ok(Type::Array),
+                    token => Err(ParseError(format!("Expected ']' to close the array type, found {}.", token)))
+                }
+            },
+            token => Err(ParseError(format!("Expected a type, found {}.", token)))
+        }
+    }
+
+    // the return type is required, so that fn(f64) -> f64 -> body reads the same in every position
+    fn parse_function_type(&mut self) -> Result<Type, ParseError> {
+        let opener = self.lexer.pop_with_trivia(); // pop (
+        let mut params = Vec::new();
+        while Token::TokSymbol(')') != *self.peek_lexer() {
+            params.push(self.parse_type()?);
+            if Token::TokSymbol(',') == *self.peek_lexer() {
+                self.pop_lexer(); // pop the comma
+            } else {
+                break;
+            }
+        }
+        self.expect_closing(opener.span, ')', "function type")?;
+        match self.pop_lexer() {
+            Token::TokOperator(op) if op == "->" => {},
+            token => return Err(ParseError(format!("Expected '->' and the return type of the function type, found {}.", token)))
+        }
+        let return_type = self.parse_type()?;
+        Ok(Type::Function(FunctionType::new(params, return_type)))
+    }
+
+    /*
+        Pratt parsing: consumes binary operators binding at least as tight as min_precedence.
+            - left associative: the rhs only takes tighter operators, a - b - c is (a - b) - c
+            - right associative: the rhs takes operators of equal precedence, a ^ b ^ c is a ^ (b ^ c)
+            - non associative: two operators of the same precedence in a row are rejected, a < b < c
+    */
+    fn parse_binary_expression(&mut self, min_precedence: i8) -> Result<ExprAst, ParseError> {
+        let start = self.peek_start();
+        let mut lhs = self.parse_unary_expression()?;
+        let mut non_assoc_precedence = None;
+
+        while let Token::TokOperator(op) = self.peek_lexer() {
+            let op = op.clone();
+            let (precedence, associativity) = match get_binary_op(&op) {
+                Some(binary_op) => binary_op,
+                None => break
+            };
+            if precedence < min_precedence {
+                break;
+            }
+            if non_assoc_precedence == Some(precedence) {
+                return Err(ParseError(format!("Operator '{}' is non-associative, use parentheses to group the comparison.", op)));
+            }
+            if op == "=" && !is_assignable(&lhs) {
+                return Err(ParseError(format!("Attempted to assign to non-variable expression {}.", lhs)));
+            }
+
+            self.pop_lexer(); // pop the operator
+            let rhs_min_precedence = match associativity {
+                Associativity::Left | Associativity::NonAssoc => precedence + 1,
+                Associativity::Right => precedence
+            };
+            let rhs = self.parse_binary_expression(rhs_min_precedence)?;
+            lhs = self.spanned(start, ExprAst::BinaryExpr { op, lhs: Box::new(lhs), rhs: Box::new(rhs) });
+
+            non_assoc_precedence = if associativity == Associativity::NonAssoc { Some(precedence) } else { None };
         }
         Ok(lhs)
     }
 
+    /*
+        Prefix operators bind tighter than every binary operator except '^': -x * y is (-x) * y
+        while -x ^ 2 is -(x ^ 2). They nest to the right, so --x and !-x are accepted.
+    */
+    fn parse_unary_expression(&mut self) -> Result<ExprAst, ParseError> {
+        if let Token::TokOperator(op) = self.peek_lexer() {
+            if is_unary_op(op) {
+                let op = op.clone();
+                let start = self.peek_start();
+                self.pop_lexer(); // pop the operator
+                let operand = self.parse_binary_expression(UNARY_OP_PRECEDENCE)?;
+                return Ok(self.spanned(start, ExprAst::UnaryExpr { op, operand: Box::new(operand) }));
+            }
+        }
+        self.parse_postfix_expression()
+    }
+
+    // indexing and field access bind tighter than any operator: -a[i] is -(a[i]), p.q.x is (p.q).x
+    fn parse_postfix_expression(&mut self) -> Result<ExprAst, ParseError> {
+        let start = self.peek_start();
+        let mut expr = self.parse_single_expression_unit()?;
+        loop {
+            match self.peek_lexer() {
+                Token::TokSymbol('[') => {
+                    let opener = self.lexer.pop_with_trivia(); // pop [
+                    let index = self.parse_abstract_expression()?;
+                    self.expect_closing(opener.span, ']', "index")?;
+                    expr = self.spanned(start, ExprAst::IndexExpr { array: Box::new(expr), index: Box::new(index) });
+                },
+                Token::TokSymbol('.') => {
+                    self.pop_lexer(); // pop .
+                    let field = match self.pop_lexer() {
+                        Token::TokIdentifier(field) => field,
+                        token => return Err(ParseError(format!("Expected a field name after '.', found {}.", token)))
+                    };
+                    expr = self.spanned(start, ExprAst::FieldExpr { object: Box::new(expr), field });
+                },
+                _ => return Ok(expr)
+            }
+        }
+    }
+
     fn parse_single_expression_unit(&mut self) -> Result<ExprAst, ParseError> {
         match self.peek_lexer() {
             Token::TokNumber(_val) => self.parse_number_expression(),
+            Token::TokString(_val) => self.parse_string_expression(),
             Token::TokIdentifier(_val) => self.parse_variable_or_call_expression(),
             Token::TokSymbol('(') => self.parse_enclosed_expression(),
+            Token::TokSymbol('{') => self.parse_block_expression(),
+            Token::TokSymbol('[') => self.parse_array_expression(),
+            Token::TokSymbol('\\') => self.parse_lambda_expression(),
+            Token::TokError(err) => {
+                let message = err.to_string();
+                Err(ParseError(format!("{} at byte {}", message, self.lexer.peek_with_trivia().span.start)))
+            },
             _ => Err(ParseError("Attempted to parse non-primary AST as primary.".to_string()))
         }
     }
 
     fn parse_number_expression(&mut self) -> Result<ExprAst, ParseError> {
+        let start = self.peek_start();
         if let Token::TokNumber(val) = self.pop_lexer() {
-            return Ok(ExprAst::NumberExpr { number: val });
+            return Ok(self.spanned(start, ExprAst::NumberExpr { number: val }));
         }
         Err(ParseError("Attempted to parse non-number EXPR as number.".to_string()))
     }
 
+    fn parse_string_expression(&mut self) -> Result<ExprAst, ParseError> {
+        let start = self.peek_start();
+        if let Token::TokString(value) = self.pop_lexer() {
+            return Ok(self.spanned(start, ExprAst::StringExpr { value }));
+        }
+        Err(ParseError("Attempted to parse non-string EXPR as string.".to_string()))
+    }
+
     fn parse_variable_or_call_expression(&mut self) -> Result<ExprAst, ParseError> {
+        let start = self.peek_start();
         if let Token::TokIdentifier(identifier) = self.pop_lexer() {
             if Token::TokSymbol('(') != *self.peek_lexer() {
-                return Ok(ExprAst::VariableExpr { name: identifier });
+                return Ok(self.spanned(start, ExprAst::VariableExpr { name: identifier }));
             }
 
-            self.pop_lexer(); // pop '('
+            let opener = self.lexer.pop_with_trivia(); // pop '('
 
             let mut args = Vec::new();
             if Token::TokSymbol(')')  != *self.peek_lexer() {
                 loop {
                     args.push(self.parse_abstract_expression()?);
 
-                    if Token::TokSymbol(')') == *self.peek_lexer(){
-                        self.pop_lexer(); // pop ')'
-                        break;
-                    }
-
                     if Token::TokSymbol(',') == *self.peek_lexer() {
                         self.pop_lexer(); // pop the comma
                     } else {
-                        return Err(ParseError("Attempted to parse badly formatted function call (expected ',').".to_string()));
+                        break;
                     }
                 }
             }
-            Ok(ExprAst::CallExpr {callee: identifier.to_string(), args })
+            self.expect_closing(opener.span, ')', &format!("call to {}", identifier))?;
+            if let Some(ty) = Type::from_name(&identifier) {
+                return Self::build_cast(ty, args).map(|cast| self.spanned(start, cast));
+            }
+            Ok(self.spanned(start, ExprAst::CallExpr {callee: identifier.to_string(), args }))
         } else {
             return Err(ParseError("Attempted to incorrectly parse EXPR as variable or call expression.".to_string()));
         }
     }
 
     fn parse_enclosed_expression(&mut self) -> Result<ExprAst, ParseError> {
-        self.pop_lexer(); // pop (
-        let res = self.parse_abstract_expression();
-        self.pop_lexer(); // pop )
-        return res;
+        let opener = self.lexer.pop_with_trivia(); // pop (
+        let res = self.parse_abstract_expression()?;
+        self.expect_closing(opener.span, ')', "parenthesis")?;
+        Ok(res)
+    }
+
+    /*
+        \x -> body, or \(x: i64, y): f64 -> body with typed parameters and a return type. The body
+        extends as far as possible like the rhs of '=': \x -> x + 1 is \x -> (x + 1).
+    */
+    fn parse_lambda_expression(&mut self) -> Result<ExprAst, ParseError> {
+        let start = self.peek_start();
+        self.pop_lexer(); // pop \
+        let (params, return_type) = match self.peek_lexer() {
+            Token::TokIdentifier(name) => {
+                let param = Param::from(name.as_str());
+                self.pop_lexer(); // pop the name
+                (vec![param], None)
+            },
+            Token::TokSymbol('(') => {
+                let opener = self.lexer.pop_with_trivia(); // pop (
+                let params = self.parse_params()?;
+                self.expect_closing(opener.span, ')', "lambda parameters")?;
+                let return_type = if Token::TokSymbol(':') == *self.peek_lexer() {
+                    self.pop_lexer(); // pop :
+                    Some(self.parse_type()?)
+                } else {
+                    None
+                };
+                (params, return_type)
+            },
+            token => return Err(ParseError(format!("Expected the parameters of the lambda, found {}.", token)))
+        };
+        match self.pop_lexer() {
+            Token::TokOperator(op) if op == "->" => {},
+            token => return Err(ParseError(format!("Expected '->' before the body of the lambda, found {}.", token)))
+        }
+        let body = self.parse_abstract_expression()?;
+        Ok(self.spanned(start, ExprAst::LambdaExpr { params, return_type, body: Box::new(body) }))
+    }
+
+    /*
+        Parses { e1; e2; ... } where ';' sequences the expressions. A trailing ';' before
+        the closing brace is allowed, an empty block is not since it has no value.
+    */
+    fn parse_block_expression(&mut self) -> Result<ExprAst, ParseError> {
+        let opener = self.lexer.pop_with_trivia(); // pop {
+
+        let mut exprs = Vec::new();
+        while Token::TokSymbol('}') != *self.peek_lexer() {
+            exprs.push(self.parse_abstract_expression()?);
+
+            if Token::TokSymbol(';') == *self.peek_lexer() {
+                self.pop_lexer(); // pop ;
+            } else {
+                break;
+            }
+        }
+
+        self.expect_closing(opener.span, '}', "block")?;
+        Self::build_block(exprs).map(|block| self.spanned(opener.span.start, block))
+    }
+
+    // [e1, e2, ...], an empty array [] is allowed and can be grown with push
+    fn parse_array_expression(&mut self) -> Result<ExprAst, ParseError> {
+        let opener = self.lexer.pop_with_trivia(); // pop [
+
+        let mut elements = Vec::new();
+        while Token::TokSymbol(']') != *self.peek_lexer() {
+            elements.push(self.parse_abstract_expression()?);
+
+            if Token::TokSymbol(',') == *self.peek_lexer() {
+                self.pop_lexer(); // pop the comma
+            } else {
+                break;
+            }
+        }
+
+        self.expect_closing(opener.span, ']', "array")?;
+        Ok(self.spanned(opener.span.start, ExprAst::ArrayExpr { elements }))
+    }
+
+    fn build_block(exprs: Vec<ExprAst>) -> Result<ExprAst, ParseError> {
+        if exprs.is_empty() {
+            return Err(ParseError("Attempted to parse empty block, a block needs at least one expression.".to_string()));
+        }
+        Ok(ExprAst::BlockExpr { exprs })
+    }
+
+    // a call to a type name converts its single argument to that type
+    fn build_cast(ty: Type, mut args: Vec<ExprAst>) -> Result<ExprAst, ParseError> {
+        if args.len() != 1 {
+            return Err(ParseError(format!("Conversion to {} takes exactly one argument, found {}.", ty, args.len())));
+        }
+        Ok(ExprAst::CastExpr { ty, operand: Box::new(args.remove(0)) })
+    }
+
+    /*
+        Pops the delimiter closing the opener at opener_span. When it is missing, the error points
+        at the opener, which is usually more helpful than the token found instead.
+    */
+    fn expect_closing(&mut self, opener_span: Span, closing: char, construct: &str) -> Result<(), ParseError> {
+        let next = self.lexer.peek_with_trivia();
+        if next.token == Token::TokSymbol(closing) {
+            self.pop_lexer(); // pop the closing delimiter
+            return Ok(());
+        }
+
+        let found = match &next.token {
+            Token::TokEof => "end of input".to_string(),
+            token => format!("{} at byte {}", token, next.span.start)
+        };
+        Err(ParseError(format!("Unclosed {} opened at byte {}: expected '{}', found {}.",
+                               construct, opener_span.start, closing, found)))
+    }
+
+    fn peek_start(&self) -> usize {
+        self.lexer.peek_with_trivia().span.start
+    }
+
+    // records the span of an expression from start up to its last token, once its children are parsed
+    fn spanned(&mut self, start: usize, expr: ExprAst) -> ExprAst {
+        self.spans.push(Span { start, end: self.lexer.popped_end() });
+        expr
     }
 
     fn pop_lexer(&mut self) -> Token {
@@ -168,3 +588,23 @@ impl<'a> Parser<'a> {
         self.lexer.peek()
     }
 }
+
+/*
+    A variable, an element of an array or a field of something assignable: a struct is a value, so
+    p.x = v stores a new value to p, while f().x = v would change nothing.
+*/
+fn is_assignable(expr: &ExprAst) -> bool {
+    match expr {
+        ExprAst::VariableExpr { .. } | ExprAst::IndexExpr { .. } => true,
+        ExprAst::FieldExpr { object, .. } => matches!(**object, ExprAst::VariableExpr { .. } | ExprAst::FieldExpr { .. }) && is_assignable(object),
+        _ => false
+    }
+}
+
+impl<'a> Iterator for Parser<'a> {
+    type Item = Result<GenericAst, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_spanned().map(|item| item.map(|spanned| spanned.ast))
+    }
+}
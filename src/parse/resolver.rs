@@ -0,0 +1,317 @@
+use core::fmt;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Formatter;
+use std::path::{Path, PathBuf};
+
+use crate::parse::lexer::Lexer;
+use crate::parse::parser::Parser;
+use crate::parse::token::Token;
+use crate::syntax::ast::*;
+use crate::syntax::types::{FunctionType, StructName, Type};
+use crate::syntax::visit::*;
+use crate::syntax::vocabulary::NAMESPACE_SEPARATOR;
+
+// the source of the prelude, built into the compiler
+pub const PRELUDE: &str = include_str!("../../prelude/prelude.kal");
+pub const PRELUDE_NAMESPACE: &str = "prelude";
+// the helpers of the prelude start with it, they are only reached through the namespace
+const PRELUDE_PRIVATE_PREFIX: char = '_';
+// the prelude has no file, its errors name it like this
+const PRELUDE_PATH: &str = "<prelude>";
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum ImportError {
+    NotFound { path: String, searched: Vec<PathBuf> },
+    // the files importing each other, starting and ending with the same one
+    Cycle { files: Vec<PathBuf> },
+    Unreadable { path: PathBuf, reason: String },
+    Parse { path: PathBuf, errors: Vec<String> },
+    // the name of the file is not an identifier, so it cannot be used as a namespace
+    InvalidNamespace { path: PathBuf },
+    NamespaceClash { namespace: String, first: PathBuf, second: PathBuf },
+    TopLevelExpression { path: PathBuf }
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::NotFound { path, searched } => {
+                let searched: Vec<String> = searched.iter().map(|dir| dir.display().to_string()).collect();
+                write!(f, "Cannot find imported file {}, searched in: {}.", path, searched.join(", "))
+            },
+            ImportError::Cycle { files } => {
+                let files: Vec<String> = files.iter().map(|file| file.display().to_string()).collect();
+                write!(f, "Import cycle: {}.", files.join(" imports "))
+            },
+            ImportError::Unreadable { path, reason } =>
+                write!(f, "Cannot read imported file {}: {}.", path.display(), reason),
+            ImportError::Parse { path, errors } =>
+                write!(f, "Cannot parse imported file {}: {}", path.display(), errors.join(" ")),
+            ImportError::InvalidNamespace { path } =>
+                write!(f, "Cannot import {}, the name of the file without its extension has to be an identifier to be used as namespace.", path.display()),
+            ImportError::NamespaceClash { namespace, first, second } =>
+                write!(f, "Cannot import {}, namespace {} is already used by {}.", second.display(), namespace, first.display()),
+            ImportError::TopLevelExpression { path } =>
+                write!(f, "Imported file {} has a top-level expression, it can only declare functions, structs, consts and globals.", path.display())
+        }
+    }
+}
+
+// an imported file, its declarations renamed into its namespace
+pub struct Module {
+    pub namespace: String,
+    // canonical, the imports of the module are relative to its directory
+    pub path: PathBuf,
+    pub items: Vec<SpannedAst>
+}
+
+/*
+    Finds the file of an import and loads it as a module:
+        - the path is looked up relative to the directory of the importing file first, then to
+          every directory of the include path in order
+        - a file is only loaded the first time it is imported, from any file
+        - a file importing itself, directly or through the files it imports, is a cycle
+    The namespace of a module is the name of its file without the extension. Its functions,
+    structs, consts and globals are renamed into it, math.kal declares math::sqrt, and so are the
+    uses of them in the module. Externs keep their names, they are the C functions every module
+    shares.
+*/
+pub struct Resolver {
+    include_path: Vec<PathBuf>,
+    // the file of every namespace
+    namespaces: HashMap<String, PathBuf>,
+    imported: HashSet<PathBuf>,
+    // the files being compiled, the root first, an import of one of them is a cycle
+    importing: Vec<PathBuf>,
+    // the names of the prelude that no source declared
+    prelude: HashSet<String>
+}
+
+impl Resolver {
+    pub fn new(include_path: Vec<PathBuf>) -> Resolver {
+        Resolver { include_path, namespaces: HashMap::new(), imported: HashSet::new(), importing: Vec::new(), prelude: HashSet::new() }
+    }
+
+    // the file the compilation starts from, which has no namespace
+    pub fn with_root(include_path: Vec<PathBuf>, root: &Path) -> Resolver {
+        let mut resolver = Resolver::new(include_path);
+        if let Ok(root) = root.canonicalize() {
+            resolver.imported.insert(root.clone());
+            resolver.importing.push(root);
+        }
+        resolver
+    }
+
+    /*
+        Loads the file imported as path by a file of directory, None when it was imported before.
+        Once the module is compiled, finish has to be called before the next import of the file
+        that imported it, which is not a cycle.
+    */
+    pub fn resolve(&mut self, path: &str, directory: &Path) -> Result<Option<Module>, ImportError> {
+        let searched: Vec<PathBuf> = std::iter::once(directory.to_path_buf()).chain(self.include_path.iter().cloned()).collect();
+        let file = searched.iter()
+            .map(|dir| dir.join(path))
+            .find(|file| file.is_file())
+            .ok_or_else(|| ImportError::NotFound { path: path.to_string(), searched: searched.clone() })?;
+        let file = file.canonicalize()
+            .map_err(|err| ImportError::Unreadable { path: file.clone(), reason: err.to_string() })?;
+
+        if let Some(position) = self.importing.iter().position(|importing| *importing == file) {
+            let mut files = self.importing[position..].to_vec();
+            files.push(file);
+            return Err(ImportError::Cycle { files });
+        }
+        if self.imported.contains(&file) {
+            return Ok(None);
+        }
+
+        let namespace = namespace_of(&file).ok_or_else(|| ImportError::InvalidNamespace { path: file.clone() })?;
+        if let Some(first) = self.namespaces.get(&namespace) {
+            return Err(ImportError::NamespaceClash { namespace, first: first.clone(), second: file });
+        }
+        let src = std::fs::read_to_string(&file)
+            .map_err(|err| ImportError::Unreadable { path: file.clone(), reason: err.to_string() })?;
+        let items = qualify(&namespace, parse(&src, &file)?);
+
+        self.namespaces.insert(namespace.clone(), file.clone());
+        self.imported.insert(file.clone());
+        self.importing.push(file.clone());
+        Ok(Some(Module { namespace, path: file, items }))
+    }
+
+    // the last module resolved is compiled
+    pub fn finish(&mut self) {
+        self.importing.pop();
+    }
+
+    /*
+        Loads the prelude as a module, whose declarations are then used without their namespace,
+        except for its helpers. It is not imported by any file, so finish is not called for it.
+    */
+    pub fn load_prelude(&mut self) -> Result<Module, ImportError> {
+        let path = PathBuf::from(PRELUDE_PATH);
+        if let Some(first) = self.namespaces.get(PRELUDE_NAMESPACE) {
+            return Err(ImportError::NamespaceClash { namespace: PRELUDE_NAMESPACE.to_string(), first: first.clone(), second: path });
+        }
+        let items = parse(PRELUDE, &path)?;
+        self.prelude = items.iter()
+            .filter_map(|item| declared_name(&item.ast))
+            .filter(|name| !name.starts_with(PRELUDE_PRIVATE_PREFIX))
+            .cloned()
+            .collect();
+        self.namespaces.insert(PRELUDE_NAMESPACE.to_string(), path.clone());
+        Ok(Module { namespace: PRELUDE_NAMESPACE.to_string(), path, items: qualify(PRELUDE_NAMESPACE, items) })
+    }
+
+    /*
+        Renames the uses of the prelude in an item of a source, abs(x) calls prelude::abs. A name
+        declared by a source, an extern included, is its own from then on, the prelude one is
+        only reached through its namespace.
+    */
+    pub fn use_prelude(&mut self, ast: &mut GenericAst) {
+        let declared = match &*ast {
+            GenericAst::FuncAst(FuncAst::Prototype { name, .. }) => Some(name),
+            ast => declared_name(ast)
+        };
+        if let Some(name) = declared {
+            self.prelude.remove(name);
+        }
+        if !self.prelude.is_empty() {
+            Qualifier { namespace: PRELUDE_NAMESPACE, declared: &self.prelude, scopes: Vec::new() }.visit_generic_ast_mut(ast);
+        }
+    }
+}
+
+// the name of the file without its extension, when it is an identifier
+fn namespace_of(file: &Path) -> Option<String> {
+    let stem = file.file_stem()?.to_str()?;
+    match Lexer::new(stem).pop() {
+        Token::TokIdentifier(name) if name == stem && !name.contains(NAMESPACE_SEPARATOR) => Some(name),
+        _ => None
+    }
+}
+
+fn parse(src: &str, file: &Path) -> Result<Vec<SpannedAst>, ImportError> {
+    let mut items = Vec::new();
+    let mut errors = Vec::new();
+    let mut parser = Parser::new(src);
+    while let Some(item) = parser.next_spanned() {
+        match item {
+            Ok(item) => items.push(item),
+            Err(err) => errors.push(err.to_string())
+        }
+    }
+    if !errors.is_empty() {
+        return Err(ImportError::Parse { path: file.to_path_buf(), errors });
+    }
+    if items.iter().any(|item| matches!(item.ast, GenericAst::ExprAst(_))) {
+        return Err(ImportError::TopLevelExpression { path: file.to_path_buf() });
+    }
+    Ok(items)
+}
+
+// the function, struct, const or global an item declares, externs are not in a namespace
+fn declared_name(ast: &GenericAst) -> Option<&String> {
+    match ast {
+        GenericAst::FuncAst(FuncAst::Function { proto, .. }) => match &**proto {
+            FuncAst::Prototype { name, .. } => Some(name),
+            FuncAst::Function { .. } => None
+        },
+        GenericAst::StructAst(struct_ast) => Some(&struct_ast.name),
+        GenericAst::GlobalAst(global) => Some(&global.name),
+        _ => None
+    }
+}
+
+// renames the declarations of a module into its namespace, together with their uses
+fn qualify(namespace: &str, mut items: Vec<SpannedAst>) -> Vec<SpannedAst> {
+    let declared = items.iter().filter_map(|item| declared_name(&item.ast)).cloned().collect();
+    let mut qualifier = Qualifier { namespace, declared: &declared, scopes: Vec::new() };
+    for item in &mut items {
+        qualifier.visit_generic_ast_mut(&mut item.ast);
+    }
+    items
+}
+
+struct Qualifier<'a> {
+    namespace: &'a str,
+    declared: &'a HashSet<String>,
+    // the parameters in scope, which hide the declarations of the same name
+    scopes: Vec<HashSet<String>>
+}
+
+impl<'a> Qualifier<'a> {
+    fn qualify_name(&self, name: &mut String) {
+        if self.declared.contains(name) && !self.scopes.iter().any(|scope| scope.contains(name)) {
+            *name = format!("{}{}{}", self.namespace, NAMESPACE_SEPARATOR, name);
+        }
+    }
+
+    // types are not hidden by parameters
+    fn qualify_type(&self, ty: Type) -> Type {
+        match ty {
+            Type::Struct(name) if self.declared.contains(name.as_str()) =>
+                Type::Struct(StructName::new(&format!("{}{}{}", self.namespace, NAMESPACE_SEPARATOR, name.as_str()))),
+            Type::Function(function_type) => Type::Function(FunctionType::new(
+                function_type.params().iter().map(|param| self.qualify_type(*param)).collect(),
+                self.qualify_type(function_type.return_type()))),
+            ty => ty
+        }
+    }
+
+    fn qualify_params(&self, params: &mut [Param]) {
+        for param in params {
+            param.ty = param.ty.map(|ty| self.qualify_type(ty));
+        }
+    }
+}
+
+impl<'a> VisitorMut for Qualifier<'a> {
+    fn visit_prototype_mut(&mut self, name: &mut String, args: &mut Vec<Param>, return_type: &mut Option<Type>) {
+        self.qualify_name(name);
+        self.qualify_params(args);
+        *return_type = return_type.map(|ty| self.qualify_type(ty));
+    }
+
+    fn visit_struct_mut(&mut self, struct_ast: &mut StructAst) {
+        self.qualify_name(&mut struct_ast.name);
+        self.qualify_params(&mut struct_ast.fields);
+    }
+
+    fn visit_global_mut(&mut self, global: &mut GlobalAst) {
+        self.qualify_name(&mut global.name);
+        global.ty = global.ty.map(|ty| self.qualify_type(ty));
+        walk_global_mut(self, global);
+    }
+
+    fn visit_function_mut(&mut self, proto: &mut FuncAst, body: &mut ExprAst) {
+        self.visit_func_mut(proto);
+        if let FuncAst::Prototype { args, .. } = proto {
+            self.scopes.push(args.iter().map(|arg| arg.name.clone()).collect());
+        }
+        self.visit_expr_mut(body);
+        self.scopes.pop();
+    }
+
+    fn visit_variable_expr_mut(&mut self, name: &mut String) {
+        self.qualify_name(name);
+    }
+
+    fn visit_call_expr_mut(&mut self, callee: &mut String, args: &mut Vec<ExprAst>) {
+        self.qualify_name(callee);
+        walk_call_expr_mut(self, args);
+    }
+
+    fn visit_cast_expr_mut(&mut self, ty: &mut Type, operand: &mut ExprAst) {
+        *ty = self.qualify_type(*ty);
+        walk_cast_expr_mut(self, operand);
+    }
+
+    fn visit_lambda_expr_mut(&mut self, params: &mut Vec<Param>, return_type: &mut Option<Type>, body: &mut ExprAst) {
+        self.qualify_params(params);
+        *return_type = return_type.map(|ty| self.qualify_type(ty));
+        self.scopes.push(params.iter().map(|param| param.name.clone()).collect());
+        self.visit_expr_mut(body);
+        self.scopes.pop();
+    }
+}
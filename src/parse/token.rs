@@ -1,9 +1,11 @@
 use std::fmt::Formatter;
+use serde::{Deserialize, Serialize};
+use crate::parse::lexer::LexError;
 use crate::syntax::vocabulary::get_op_precedence;
 
 pub fn get_token_precedence(tok: &Token) -> i8 {
-    if let Token::TokSymbol(ch) = tok {
-        return get_op_precedence(ch);
+    if let Token::TokOperator(op) = tok {
+        return get_op_precedence(op);
     }
     -1
 }
@@ -12,14 +14,96 @@ pub fn get_token_precedence(tok: &Token) -> i8 {
 #[derive(PartialEq, Debug, Clone)]
 pub enum Token {
     TokEof,
-    TokComment(String),
+    TokError(LexError),
 
     TokDef,
     TokExtern,
+    TokStruct,
+    TokConst,
+    TokGlobal,
+    TokImport,
 
     TokSymbol(char),
+    TokOperator(String),
     TokIdentifier(String),
-    TokNumber(f64)
+    TokNumber(f64),
+    // the value of a string literal, with its escapes resolved
+    TokString(String)
+}
+
+/*
+    Trivia is source text that carries no meaning for the grammar (whitespace and comments).
+    It is kept next to the tokens so that a formatter can reproduce the source and so that
+    comments directly above a definition can be read back as its documentation.
+*/
+#[derive(PartialEq, Debug, Clone)]
+pub enum Trivia {
+    Whitespace(String),
+    Comment(String)
+}
+
+impl Trivia {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Trivia::Whitespace(val) | Trivia::Comment(val) => val
+        }
+    }
+}
+
+// byte range [start, end) of a token in the source
+#[derive(PartialEq, Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize
+}
+
+/*
+    A token together with its byte span in the source and its surrounding trivia.
+        - leading: everything between the previous token's trailing trivia and this token
+        - trailing: whitespace and comments after this token up to (excluding) the end of the line
+*/
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct SpannedToken {
+    pub leading: Vec<Trivia>,
+    pub token: Token,
+    pub trailing: Vec<Trivia>,
+    pub span: Span
+}
+
+impl SpannedToken {
+    // true when a line break separates this token from the previous one
+    pub fn starts_line(&self) -> bool {
+        self.leading.iter().any(|trivia| {
+            matches!(trivia, Trivia::Whitespace(space) if space.contains(['\r', '\n']))
+        })
+    }
+
+    /*
+        Collects the block of comment lines directly above the token. A blank line between
+        two comments (or between a comment and the token) ends the block.
+    */
+    pub fn doc_comment(&self) -> Option<String> {
+        let mut lines = Vec::new();
+        for trivia in self.leading.iter().rev() {
+            match trivia {
+                Trivia::Comment(comment) => {
+                    let line = comment.trim_start_matches('#');
+                    lines.push(line.strip_prefix(' ').unwrap_or(line).trim_end());
+                },
+                Trivia::Whitespace(space) => {
+                    if space.matches('\n').count() > 1 {
+                        break;
+                    }
+                }
+            }
+        }
+
+        if lines.is_empty() {
+            return None;
+        }
+        lines.reverse();
+        Some(lines.join("\n"))
+    }
 }
 
 impl Default for Token {
@@ -32,12 +116,18 @@ impl std::fmt::Display for Token {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             Token::TokEof => write!(f, "<eof>"),
-            Token::TokComment(val) => write!(f, "<comment> {}", val),
+            Token::TokError(val) => write!(f, "<error> {}", val),
             Token::TokDef => write!(f, "<def>"),
             Token::TokExtern => write!(f, "<extern>"),
+            Token::TokStruct => write!(f, "<struct>"),
+            Token::TokConst => write!(f, "<const>"),
+            Token::TokGlobal => write!(f, "<global>"),
+            Token::TokImport => write!(f, "<import>"),
             Token::TokSymbol(val) => write!(f, "<primary> {}", val),
+            Token::TokOperator(val) => write!(f, "<operator> {}", val),
             Token::TokIdentifier(val) => write!(f, "<identifier> {}", val),
-            Token::TokNumber(val) => write!(f, "<number> {}", val)
+            Token::TokNumber(val) => write!(f, "<number> {}", val),
+            Token::TokString(val) => write!(f, "<string> {:?}", val)
         }
     }
 }
@@ -47,7 +137,10 @@ impl From<&str> for Token {
         match token_str {
             "def" => Token::TokDef,
             "extern" => Token::TokExtern,
-            comment if comment.starts_with("#") => Token::TokComment(comment.to_string()),
+            "struct" => Token::TokStruct,
+            "const" => Token::TokConst,
+            "global" => Token::TokGlobal,
+            "import" => Token::TokImport,
             non_empty if !non_empty.is_empty() => Token::TokIdentifier(non_empty.to_string()),
             _ => Token::TokEof
         }
@@ -1,4 +1,8 @@
-use crate::utils::display;
+use serde::{Deserialize, Serialize};
+
+use crate::parse::token::Span;
+use crate::syntax::pretty::{Layout, Printer};
+use crate::syntax::types::Type;
 
 /*
     Learning Note:
@@ -6,53 +10,144 @@ use crate::utils::display;
         PartialEq, for example, will loosely implement a field by field comparision.
 */
 #[repr(i8)]
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
 pub enum ExprAst {
     NumberExpr { number: f64 },
+    // a string literal, with its escapes resolved
+    StringExpr { value: String },
     VariableExpr { name: String },
-    BinaryExpr { op: char, lhs: Box<ExprAst>, rhs: Box<ExprAst> },
-    CallExpr { callee: String, args: Vec<ExprAst> }
+    UnaryExpr { op: String, operand: Box<ExprAst> },
+    BinaryExpr { op: String, lhs: Box<ExprAst>, rhs: Box<ExprAst> },
+    CallExpr { callee: String, args: Vec<ExprAst> },
+    // explicit conversion, written i64(x), f64(x), bool(x) or str(x)
+    CastExpr { ty: Type, operand: Box<ExprAst> },
+    // { e1; e2; e3 } evaluates every expression in order, its value is the last one
+    BlockExpr { exprs: Vec<ExprAst> },
+    // [e1, e2, e3] creates a new array holding the values of the expressions
+    ArrayExpr { elements: Vec<ExprAst> },
+    // array[index], the index is checked against the length of the array when it is evaluated
+    IndexExpr { array: Box<ExprAst>, index: Box<ExprAst> },
+    // object.field reads a field of a struct
+    FieldExpr { object: Box<ExprAst>, field: String },
+    // \x -> body or \(x: i64, y): f64 -> body, the variables it uses are captured when it is evaluated
+    LambdaExpr { params: Vec<Param>, return_type: Option<Type>, body: Box<ExprAst> }
 }
 
 #[repr(i8)]
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
 pub enum FuncAst {
-    Prototype { name: String, args: Vec<String> },
+    // missing types are resolved by the type checker
+    Prototype { name: String, args: Vec<Param>, return_type: Option<Type> },
     Function { proto: Box<FuncAst>, body: Box<ExprAst> }
 }
 
+// a parameter of a prototype, x or x: i64
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct Param {
+    pub name: String,
+    pub ty: Option<Type>
+}
+
+// an unannotated parameter
+impl From<&str> for Param {
+    fn from(name: &str) -> Self {
+        Param { name: name.to_string(), ty: None }
+    }
+}
+
+/*
+    struct Name { x, y: i64 } declares a struct type together with its constructor Name(x, y).
+    Fields are written like parameters, the ones without a type are f64.
+*/
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct StructAst {
+    pub name: String,
+    pub fields: Vec<Param>
+}
+
+/*
+    const pi = 3.14159 or global counter: i64 = 0 declares a variable every function declared after
+    it can use. The value is a constant expression, computed when the program is compiled; a const
+    cannot be assigned and its uses are replaced with its value.
+*/
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct GlobalAst {
+    pub name: String,
+    pub ty: Option<Type>,
+    pub value: ExprAst,
+    pub constant: bool
+}
+
+/*
+    import "math.kal" compiles another file once, its declarations are then referenced with the
+    name of the file as namespace, math::sqrt. The path is relative to the importing file or to
+    a directory of the include path.
+*/
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct ImportAst {
+    pub path: String
+}
+
 #[repr(i8)]
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
 pub enum GenericAst {
     ExprAst(ExprAst),
-    FuncAst(FuncAst)
+    FuncAst(FuncAst),
+    StructAst(StructAst),
+    GlobalAst(GlobalAst),
+    ImportAst(ImportAst)
+}
+
+/*
+    A top-level item together with the bytes of the source it was parsed from, from its first
+    token up to its last one (the ';' terminating it excluded).
+    Together with the derived serde implementations, this is what external tools consume:
+        {"span":{"start":0,"end":1},"ast":{"ExprAst":{"VariableExpr":{"name":"x"}}},"spans":[{"start":0,"end":1}]}
+
+    The spans of the expressions inside the item are kept aside, in the order the parser completes
+    them: the children of an expression before it, from left to right. This is the order of
+    walking the tree with a Visitor and taking each expression after its children, which is how
+    inference finds where its constraints come from.
+*/
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct SpannedAst {
+    pub span: Span,
+    pub ast: GenericAst,
+    pub spans: Vec<Span>
 }
 
 impl std::fmt::Display for ExprAst {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            ExprAst::NumberExpr { number } => write!(f, "{}", number),
-            ExprAst::VariableExpr { name } => write!(f, "{}", name),
-            ExprAst::BinaryExpr { op, lhs, rhs } => write!(f, "({} {} {})", lhs, op, rhs),
-            ExprAst::CallExpr { callee, args } => {
-                write!(f, "{}(", callee)?;
-                display::structured_slice_print(args, f)
-            }
-        }
+        Printer::new(f, Layout::Inline).print_expr(self)
     }
 }
 
 impl std::fmt::Display for FuncAst {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            FuncAst::Prototype { name, args } => {
-                write!(f, "def {}(", name)?;
-                display::structured_slice_print(args, f)
-            },
-            FuncAst::Function { proto, body } => {
-                write!(f, "{}\n", proto)?;
-                write!(f, "\t{}", body)
-            }
-        }
+        Printer::new(f, Layout::Inline).print_func(self)
+    }
+}
+
+impl std::fmt::Display for StructAst {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Printer::new(f, Layout::Inline).print_struct(self)
+    }
+}
+
+impl std::fmt::Display for GlobalAst {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Printer::new(f, Layout::Inline).print_global(self)
+    }
+}
+
+impl std::fmt::Display for ImportAst {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Printer::new(f, Layout::Inline).print_import(self)
     }
-}
\ No newline at end of file
+}
+
+impl std::fmt::Display for GenericAst {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Printer::new(f, Layout::Inline).print_generic_ast(self)
+    }
+}
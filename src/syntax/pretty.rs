@@ -0,0 +1,354 @@
+use std::fmt::{self, Write};
+
+use crate::parse::lexer::{escape_string, Lexer};
+use crate::parse::parser::{ParseError, Parser};
+use crate::parse::token::{Token, Trivia};
+use crate::syntax::ast::*;
+use crate::syntax::vocabulary::*;
+
+const INDENT: &str = "    ";
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum Layout {
+    // everything on one line, blocks print as { a; b }
+    Inline,
+    // every expression of a block on its own line, used by the formatter
+    Indented
+}
+
+/*
+    Prints the AST back as source that parses to the same tree, with as few parentheses as
+    the precedence and associativity of the operators allow.
+*/
+pub struct Printer<'w, W: Write> {
+    out: &'w mut W,
+    layout: Layout,
+    indent: usize
+}
+
+impl<'w, W: Write> Printer<'w, W> {
+    pub fn new(out: &'w mut W, layout: Layout) -> Printer<'w, W> {
+        Printer { out, layout, indent: 0 }
+    }
+
+    pub fn print_generic_ast(&mut self, ast: &GenericAst) -> fmt::Result {
+        match ast {
+            GenericAst::ExprAst(expr) => self.print_expr(expr),
+            GenericAst::FuncAst(func) => self.print_func(func),
+            GenericAst::StructAst(struct_ast) => self.print_struct(struct_ast),
+            GenericAst::GlobalAst(global) => self.print_global(global),
+            GenericAst::ImportAst(import) => self.print_import(import)
+        }
+    }
+
+    // import "math.kal"
+    pub fn print_import(&mut self, import: &ImportAst) -> fmt::Result {
+        write!(self.out, "import {}", escape_string(&import.path))
+    }
+
+    // const pi = 3.14159 or global counter: i64 = 0
+    pub fn print_global(&mut self, global: &GlobalAst) -> fmt::Result {
+        write!(self.out, "{} {}", if global.constant { "const" } else { "global" }, global.name)?;
+        if let Some(ty) = global.ty {
+            write!(self.out, ": {}", ty)?;
+        }
+        write!(self.out, " = ")?;
+        self.print_expr(&global.value)
+    }
+
+    // on one line in both layouts, struct Point { x, y: i64 }
+    pub fn print_struct(&mut self, struct_ast: &StructAst) -> fmt::Result {
+        write!(self.out, "struct {} {{", struct_ast.name)?;
+        if struct_ast.fields.is_empty() {
+            return write!(self.out, "}}");
+        }
+        write!(self.out, " ")?;
+        self.print_params(&struct_ast.fields)?;
+        write!(self.out, " }}")
+    }
+
+    // a prototype on its own is an extern, the prototype of a definition is printed by print_signature
+    pub fn print_func(&mut self, func: &FuncAst) -> fmt::Result {
+        match func {
+            FuncAst::Prototype { .. } => {
+                write!(self.out, "extern ")?;
+                self.print_signature(func)
+            },
+            FuncAst::Function { proto, body } => {
+                write!(self.out, "def ")?;
+                self.print_signature(proto)?;
+                write!(self.out, " ")?;
+                self.print_expr(body)
+            }
+        }
+    }
+
+    fn print_signature(&mut self, proto: &FuncAst) -> fmt::Result {
+        match proto {
+            FuncAst::Prototype { name, args, return_type } => {
+                write!(self.out, "{}(", name)?;
+                self.print_params(args)?;
+                write!(self.out, ")")?;
+                if let Some(return_type) = return_type {
+                    write!(self.out, " -> {}", return_type)?;
+                }
+                Ok(())
+            },
+            FuncAst::Function { .. } => panic!("Expected Prototype Ast!")
+        }
+    }
+
+    fn print_params(&mut self, params: &[Param]) -> fmt::Result {
+        for (i, param) in params.iter().enumerate() {
+            if i != 0 {
+                write!(self.out, ", ")?;
+            }
+            write!(self.out, "{}", param.name)?;
+            if let Some(ty) = param.ty {
+                write!(self.out, ": {}", ty)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn print_expr(&mut self, expr: &ExprAst) -> fmt::Result {
+        match expr {
+            ExprAst::NumberExpr { number } => write!(self.out, "{}", number),
+            ExprAst::StringExpr { value } => write!(self.out, "{}", escape_string(value)),
+            ExprAst::VariableExpr { name } => write!(self.out, "{}", name),
+            ExprAst::UnaryExpr { op, operand } => {
+                write!(self.out, "{}", op)?;
+                // the operand only takes the operators binding tighter than prefix operators
+                let needs_parens = is_lambda(operand) || matches!(binary_precedence(operand), Some(precedence) if precedence < UNARY_OP_PRECEDENCE);
+                self.print_operand(operand, needs_parens)
+            },
+            ExprAst::BinaryExpr { op, lhs, rhs } => {
+                let (precedence, associativity) = get_binary_op(op).unwrap();
+                let lhs_needs_parens = match &**lhs {
+                    ExprAst::UnaryExpr { .. } => precedence >= UNARY_OP_PRECEDENCE,
+                    ExprAst::LambdaExpr { .. } => true,
+                    lhs => matches!(binary_precedence(lhs), Some(lhs_precedence)
+                        if lhs_precedence < precedence || (lhs_precedence == precedence && associativity != Associativity::Left))
+                };
+                // the rhs of '=' is always last, a lambda anywhere else would take in what follows it
+                let rhs_needs_parens = (is_lambda(rhs) && op != "=") || matches!(binary_precedence(rhs), Some(rhs_precedence)
+                    if rhs_precedence < precedence || (rhs_precedence == precedence && associativity != Associativity::Right));
+
+                self.print_operand(lhs, lhs_needs_parens)?;
+                write!(self.out, " {} ", op)?;
+                self.print_operand(rhs, rhs_needs_parens)
+            },
+            ExprAst::CallExpr { callee, args } => {
+                write!(self.out, "{}(", callee)?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i != 0 {
+                        write!(self.out, ", ")?;
+                    }
+                    self.print_expr(arg)?;
+                }
+                write!(self.out, ")")
+            },
+            ExprAst::CastExpr { ty, operand } => {
+                write!(self.out, "{}(", ty)?;
+                self.print_expr(operand)?;
+                write!(self.out, ")")
+            },
+            ExprAst::BlockExpr { exprs } => self.print_block(exprs),
+            ExprAst::ArrayExpr { elements } => {
+                write!(self.out, "[")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i != 0 {
+                        write!(self.out, ", ")?;
+                    }
+                    self.print_expr(element)?;
+                }
+                write!(self.out, "]")
+            },
+            ExprAst::IndexExpr { array, index } => {
+                self.print_operand(array, is_postfix_operand_in_parens(array))?;
+                write!(self.out, "[")?;
+                self.print_expr(index)?;
+                write!(self.out, "]")
+            },
+            ExprAst::FieldExpr { object, field } => {
+                self.print_operand(object, is_postfix_operand_in_parens(object))?;
+                write!(self.out, ".{}", field)
+            },
+            ExprAst::LambdaExpr { params, return_type, body } => {
+                match (params.as_slice(), return_type) {
+                    ([Param { name, ty: None }], None) => write!(self.out, "\\{}", name)?,
+                    _ => {
+                        write!(self.out, "\\(")?;
+                        self.print_params(params)?;
+                        write!(self.out, ")")?;
+                        if let Some(ty) = return_type {
+                            write!(self.out, ": {}", ty)?;
+                        }
+                    }
+                }
+                write!(self.out, " -> ")?;
+                self.print_expr(body)
+            }
+        }
+    }
+
+    fn print_operand(&mut self, operand: &ExprAst, needs_parens: bool) -> fmt::Result {
+        if !needs_parens {
+            return self.print_expr(operand);
+        }
+        write!(self.out, "(")?;
+        self.print_expr(operand)?;
+        write!(self.out, ")")
+    }
+
+    fn print_block(&mut self, exprs: &[ExprAst]) -> fmt::Result {
+        if self.layout == Layout::Inline {
+            write!(self.out, "{{ ")?;
+            for (i, expr) in exprs.iter().enumerate() {
+                if i != 0 {
+                    write!(self.out, "; ")?;
+                }
+                self.print_expr(expr)?;
+            }
+            return write!(self.out, " }}");
+        }
+
+        writeln!(self.out, "{{")?;
+        self.indent += 1;
+        for (i, expr) in exprs.iter().enumerate() {
+            write!(self.out, "{}", INDENT.repeat(self.indent))?;
+            self.print_expr(expr)?;
+            writeln!(self.out, "{}", if i + 1 != exprs.len() { ";" } else { "" })?;
+        }
+        self.indent -= 1;
+        write!(self.out, "{}}}", INDENT.repeat(self.indent))
+    }
+}
+
+// indexing and field access bind tighter than every operator, their operand needs parentheses when it is one
+fn is_operation(expr: &ExprAst) -> bool {
+    matches!(expr, ExprAst::UnaryExpr { .. } | ExprAst::BinaryExpr { .. } | ExprAst::LambdaExpr { .. })
+}
+
+// the '.' after a number is lexed as part of it, so (1).x cannot be printed as 1.x
+fn is_postfix_operand_in_parens(expr: &ExprAst) -> bool {
+    is_operation(expr) || matches!(expr, ExprAst::NumberExpr { .. })
+}
+
+// the body of a lambda extends as far as possible, so it needs parentheses unless nothing follows it
+fn is_lambda(expr: &ExprAst) -> bool {
+    matches!(expr, ExprAst::LambdaExpr { .. })
+}
+
+// unary expressions are left out, they only need parentheses on the lhs of a binary operator
+fn binary_precedence(expr: &ExprAst) -> Option<i8> {
+    match expr {
+        ExprAst::BinaryExpr { op, .. } => get_binary_op(op).map(|(precedence, _)| precedence),
+        _ => None
+    }
+}
+
+pub fn pretty_print(ast: &GenericAst, layout: Layout) -> String {
+    let mut out = String::new();
+    Printer::new(&mut out, layout).print_generic_ast(ast).unwrap();
+    out
+}
+
+/*
+    Formats a whole source: one item per line, blocks indented, and at most one blank line
+    between items. Comments are kept:
+        - comments between items stay between them, a comment after an item stays on its line
+        - an item with comments inside is kept as written, since the AST has no place for them
+    Items are only terminated with ';' when the next item would otherwise continue them,
+    as in f followed by (1) or [1] on the next line.
+*/
+pub fn format_source(src: &str) -> Result<String, Vec<ParseError>> {
+    let mut parser = Parser::new(src);
+    let mut items = Vec::new();
+    let mut errors = Vec::new();
+    while let Some(item) = parser.next_spanned() {
+        match item {
+            Ok(item) => items.push(item),
+            Err(err) => errors.push(err)
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let comments = collect_comments(src);
+    let texts: Vec<String> = items.iter().map(|item| {
+        let has_inner_comments = comments.iter().any(|(offset, _)| item.span.start < *offset && *offset < item.span.end);
+        if has_inner_comments {
+            src[item.span.start..item.span.end].to_string()
+        } else {
+            pretty_print(&item.ast, Layout::Indented)
+        }
+    }).collect();
+
+    let mut out = String::new();
+    let mut emitted_end = None;
+    let mut comments = comments.into_iter().peekable();
+    for (i, item) in items.iter().enumerate() {
+        while let Some((offset, comment)) = comments.next_if(|(offset, _)| *offset < item.span.end) {
+            if offset < item.span.start {
+                out.push_str(separator(src, emitted_end, offset, true));
+                out.push_str(&comment);
+                emitted_end = Some(offset + comment.len());
+            }
+        }
+
+        out.push_str(separator(src, emitted_end, item.span.start, false));
+        out.push_str(&texts[i]);
+        if texts.get(i + 1).is_some_and(|next| next.starts_with(['(', '[', '-'])) {
+            out.push(';');
+        }
+        emitted_end = Some(item.span.end);
+    }
+    for (offset, comment) in comments {
+        out.push_str(separator(src, emitted_end, offset, true));
+        out.push_str(&comment);
+        emitted_end = Some(offset + comment.len());
+    }
+
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+// what goes between the last emitted piece of source and the next one, based on the original gap
+fn separator(src: &str, emitted_end: Option<usize>, next_start: usize, is_comment: bool) -> &'static str {
+    let emitted_end = match emitted_end {
+        Some(emitted_end) => emitted_end,
+        None => return ""
+    };
+    match src[emitted_end..next_start].matches('\n').count() {
+        0 if is_comment => " ",
+        0 | 1 => "\n",
+        _ => "\n\n"
+    }
+}
+
+// every comment of the source with its byte offset, in order
+fn collect_comments(src: &str) -> Vec<(usize, String)> {
+    let mut comments = Vec::new();
+    let mut lexer = Lexer::new(src);
+    loop {
+        let token = lexer.pop_with_trivia();
+        let leading_len: usize = token.leading.iter().map(|trivia| trivia.as_str().len()).sum();
+        for (start, trivia_list) in [(token.span.start - leading_len, &token.leading), (token.span.end, &token.trailing)] {
+            let mut offset = start;
+            for trivia in trivia_list {
+                if let Trivia::Comment(comment) = trivia {
+                    comments.push((offset, comment.clone()));
+                }
+                offset += trivia.as_str().len();
+            }
+        }
+        if token.token == Token::TokEof {
+            break;
+        }
+    }
+    comments
+}
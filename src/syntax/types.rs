@@ -0,0 +1,178 @@
+use std::collections::HashSet;
+use std::sync::{Mutex, OnceLock};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/*
+    Types of values. Unannotated parameters and return values are f64, which keeps untyped
+    Kaleidoscope code working as before.
+    Conversions:
+        - implicit, when no information is lost: bool -> i64 -> f64
+        - explicit with the conversion syntax i64(x), f64(x) and bool(x) otherwise
+        - str(x) formats any number or bool, a str never converts to anything else
+        - an array, a struct or a function never converts to or from anything
+*/
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash, Serialize, Deserialize)]
+pub enum Type {
+    I64,
+    F64,
+    Bool,
+    // a nul-terminated string of bytes, i8* in LLVM
+    Str,
+    // a growable array of f64 on the heap, written [f64], a pointer to the runtime's array in LLVM
+    Array,
+    // a struct declared with struct Name { ... }, an LLVM struct passed by value
+    Struct(StructName),
+    // a function value written fn(f64, i64) -> f64, a function pointer with the variables it captured in LLVM
+    Function(FunctionType)
+}
+
+/*
+    The name of a struct type. Names are interned into a table that lives as long as the program,
+    so that a Type stays Copy like the builtin types while its name still compares as a string.
+*/
+#[derive(PartialEq, Eq, Clone, Copy, Hash)]
+pub struct StructName(&'static str);
+
+impl StructName {
+    pub fn new(name: &str) -> StructName {
+        static NAMES: OnceLock<Mutex<HashSet<&'static str>>> = OnceLock::new();
+        let mut names = NAMES.get_or_init(Default::default).lock().unwrap();
+        if let Some(interned) = names.get(name) {
+            return StructName(interned);
+        }
+        let interned: &'static str = Box::leak(name.to_string().into_boxed_str());
+        names.insert(interned);
+        StructName(interned)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        self.0
+    }
+}
+
+// serialized as the plain name, which is interned again when it is read back
+impl Serialize for StructName {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for StructName {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(|name| StructName::new(&name))
+    }
+}
+
+impl std::fmt::Debug for StructName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.0)
+    }
+}
+
+/*
+    The parameter and return types of a function value. They are interned like struct names, so
+    that a Type stays Copy however deeply function types are nested.
+*/
+#[derive(PartialEq, Eq, Clone, Copy, Hash)]
+pub struct FunctionType(&'static FunctionTypeData);
+
+#[derive(PartialEq, Eq, Hash)]
+struct FunctionTypeData {
+    params: Vec<Type>,
+    return_type: Type,
+    // fn(f64, i64) -> f64, kept to give out names as &'static str like the other types
+    name: String
+}
+
+impl FunctionType {
+    pub fn new(params: Vec<Type>, return_type: Type) -> FunctionType {
+        static TYPES: OnceLock<Mutex<HashSet<&'static FunctionTypeData>>> = OnceLock::new();
+        let param_names: Vec<&str> = params.iter().map(|param| param.name()).collect();
+        let name = format!("fn({}) -> {}", param_names.join(", "), return_type);
+        let data = FunctionTypeData { params, return_type, name };
+
+        let mut types = TYPES.get_or_init(Default::default).lock().unwrap();
+        if let Some(interned) = types.get(&data) {
+            return FunctionType(interned);
+        }
+        let interned: &'static FunctionTypeData = Box::leak(Box::new(data));
+        types.insert(interned);
+        FunctionType(interned)
+    }
+
+    pub fn params(&self) -> &'static [Type] {
+        &self.0.params
+    }
+
+    pub fn return_type(&self) -> Type {
+        self.0.return_type
+    }
+
+    pub fn name(&self) -> &'static str {
+        &self.0.name
+    }
+}
+
+// serialized as (parameter types, return type)
+impl Serialize for FunctionType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (self.params(), self.return_type()).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for FunctionType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        <(Vec<Type>, Type)>::deserialize(deserializer).map(|(params, return_type)| FunctionType::new(params, return_type))
+    }
+}
+
+impl std::fmt::Debug for FunctionType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.0.name)
+    }
+}
+
+pub const DEFAULT_TYPE: Type = Type::F64;
+
+impl Type {
+    pub fn from_name(name: &str) -> Option<Type> {
+        match name {
+            "i64" => Some(Type::I64),
+            "f64" => Some(Type::F64),
+            "bool" => Some(Type::Bool),
+            "str" => Some(Type::Str),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Type::I64 => "i64",
+            Type::F64 => "f64",
+            Type::Bool => "bool",
+            Type::Str => "str",
+            Type::Array => "[f64]",
+            Type::Struct(name) => name.as_str(),
+            Type::Function(function_type) => function_type.name()
+        }
+    }
+
+    pub fn is_scalar(&self) -> bool {
+        matches!(self, Type::I64 | Type::F64 | Type::Bool)
+    }
+
+    pub fn converts_explicitly_to(&self, to: Type) -> bool {
+        *self == to || (self.is_scalar() && (to.is_scalar() || to == Type::Str))
+    }
+
+    pub fn converts_implicitly_to(&self, to: Type) -> bool {
+        *self == to || matches!((self, to), (Type::Bool, Type::I64) | (Type::Bool, Type::F64) | (Type::I64, Type::F64))
+    }
+}
+
+impl std::fmt::Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
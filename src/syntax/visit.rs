@@ -0,0 +1,438 @@
+use crate::syntax::ast::*;
+use crate::syntax::types::Type;
+
+/*
+    Traversals over the AST.
+        - Visitor: reads the tree
+        - VisitorMut: edits the tree in place
+        - Folder: consumes the tree and builds a new one
+    Every node kind has its own method. The default implementations walk into the children, so a
+    pass only overrides the methods for the nodes it cares about. An override that still wants to
+    reach the children calls the matching walk_* function (Visitor, VisitorMut) or folds them
+    itself (Folder).
+
+    Learning Note:
+        Enum variants are not types in Rust, so the variant methods receive the fields of the
+        variant instead of the node itself.
+*/
+pub trait Visitor {
+    fn visit_generic_ast(&mut self, ast: &GenericAst) {
+        walk_generic_ast(self, ast)
+    }
+
+    fn visit_func(&mut self, func: &FuncAst) {
+        walk_func(self, func)
+    }
+
+    fn visit_prototype(&mut self, _name: &str, _args: &[Param], _return_type: Option<Type>) {}
+
+    fn visit_struct(&mut self, _struct_ast: &StructAst) {}
+
+    fn visit_global(&mut self, global: &GlobalAst) {
+        walk_global(self, global)
+    }
+
+    fn visit_import(&mut self, _import: &ImportAst) {}
+
+    fn visit_function(&mut self, proto: &FuncAst, body: &ExprAst) {
+        walk_function(self, proto, body)
+    }
+
+    fn visit_expr(&mut self, expr: &ExprAst) {
+        walk_expr(self, expr)
+    }
+
+    fn visit_number_expr(&mut self, _number: f64) {}
+
+    fn visit_string_expr(&mut self, _value: &str) {}
+
+    fn visit_variable_expr(&mut self, _name: &str) {}
+
+    fn visit_unary_expr(&mut self, _op: &str, operand: &ExprAst) {
+        walk_unary_expr(self, operand)
+    }
+
+    fn visit_binary_expr(&mut self, _op: &str, lhs: &ExprAst, rhs: &ExprAst) {
+        walk_binary_expr(self, lhs, rhs)
+    }
+
+    fn visit_call_expr(&mut self, _callee: &str, args: &[ExprAst]) {
+        walk_call_expr(self, args)
+    }
+
+    fn visit_cast_expr(&mut self, _ty: Type, operand: &ExprAst) {
+        walk_cast_expr(self, operand)
+    }
+
+    fn visit_block_expr(&mut self, exprs: &[ExprAst]) {
+        walk_block_expr(self, exprs)
+    }
+
+    fn visit_array_expr(&mut self, elements: &[ExprAst]) {
+        walk_array_expr(self, elements)
+    }
+
+    fn visit_index_expr(&mut self, array: &ExprAst, index: &ExprAst) {
+        walk_index_expr(self, array, index)
+    }
+
+    fn visit_field_expr(&mut self, object: &ExprAst, _field: &str) {
+        walk_field_expr(self, object)
+    }
+
+    fn visit_lambda_expr(&mut self, _params: &[Param], _return_type: Option<Type>, body: &ExprAst) {
+        walk_lambda_expr(self, body)
+    }
+}
+
+pub fn walk_generic_ast<V: Visitor + ?Sized>(visitor: &mut V, ast: &GenericAst) {
+    match ast {
+        GenericAst::ExprAst(expr) => visitor.visit_expr(expr),
+        GenericAst::FuncAst(func) => visitor.visit_func(func),
+        GenericAst::StructAst(struct_ast) => visitor.visit_struct(struct_ast),
+        GenericAst::GlobalAst(global) => visitor.visit_global(global),
+        GenericAst::ImportAst(import) => visitor.visit_import(import)
+    }
+}
+
+pub fn walk_global<V: Visitor + ?Sized>(visitor: &mut V, global: &GlobalAst) {
+    visitor.visit_expr(&global.value);
+}
+
+pub fn walk_func<V: Visitor + ?Sized>(visitor: &mut V, func: &FuncAst) {
+    match func {
+        FuncAst::Prototype { name, args, return_type } => visitor.visit_prototype(name, args, *return_type),
+        FuncAst::Function { proto, body } => visitor.visit_function(proto, body)
+    }
+}
+
+pub fn walk_function<V: Visitor + ?Sized>(visitor: &mut V, proto: &FuncAst, body: &ExprAst) {
+    visitor.visit_func(proto);
+    visitor.visit_expr(body);
+}
+
+pub fn walk_expr<V: Visitor + ?Sized>(visitor: &mut V, expr: &ExprAst) {
+    match expr {
+        ExprAst::NumberExpr { number } => visitor.visit_number_expr(*number),
+        ExprAst::StringExpr { value } => visitor.visit_string_expr(value),
+        ExprAst::VariableExpr { name } => visitor.visit_variable_expr(name),
+        ExprAst::UnaryExpr { op, operand } => visitor.visit_unary_expr(op, operand),
+        ExprAst::BinaryExpr { op, lhs, rhs } => visitor.visit_binary_expr(op, lhs, rhs),
+        ExprAst::CallExpr { callee, args } => visitor.visit_call_expr(callee, args),
+        ExprAst::CastExpr { ty, operand } => visitor.visit_cast_expr(*ty, operand),
+        ExprAst::BlockExpr { exprs } => visitor.visit_block_expr(exprs),
+        ExprAst::ArrayExpr { elements } => visitor.visit_array_expr(elements),
+        ExprAst::IndexExpr { array, index } => visitor.visit_index_expr(array, index),
+        ExprAst::FieldExpr { object, field } => visitor.visit_field_expr(object, field),
+        ExprAst::LambdaExpr { params, return_type, body } => visitor.visit_lambda_expr(params, *return_type, body)
+    }
+}
+
+pub fn walk_unary_expr<V: Visitor + ?Sized>(visitor: &mut V, operand: &ExprAst) {
+    visitor.visit_expr(operand);
+}
+
+pub fn walk_binary_expr<V: Visitor + ?Sized>(visitor: &mut V, lhs: &ExprAst, rhs: &ExprAst) {
+    visitor.visit_expr(lhs);
+    visitor.visit_expr(rhs);
+}
+
+pub fn walk_call_expr<V: Visitor + ?Sized>(visitor: &mut V, args: &[ExprAst]) {
+    for arg in args {
+        visitor.visit_expr(arg);
+    }
+}
+
+pub fn walk_cast_expr<V: Visitor + ?Sized>(visitor: &mut V, operand: &ExprAst) {
+    visitor.visit_expr(operand);
+}
+
+pub fn walk_block_expr<V: Visitor + ?Sized>(visitor: &mut V, exprs: &[ExprAst]) {
+    for expr in exprs {
+        visitor.visit_expr(expr);
+    }
+}
+
+pub fn walk_array_expr<V: Visitor + ?Sized>(visitor: &mut V, elements: &[ExprAst]) {
+    for element in elements {
+        visitor.visit_expr(element);
+    }
+}
+
+pub fn walk_index_expr<V: Visitor + ?Sized>(visitor: &mut V, array: &ExprAst, index: &ExprAst) {
+    visitor.visit_expr(array);
+    visitor.visit_expr(index);
+}
+
+pub fn walk_field_expr<V: Visitor + ?Sized>(visitor: &mut V, object: &ExprAst) {
+    visitor.visit_expr(object);
+}
+
+pub fn walk_lambda_expr<V: Visitor + ?Sized>(visitor: &mut V, body: &ExprAst) {
+    visitor.visit_expr(body);
+}
+
+pub trait VisitorMut {
+    fn visit_generic_ast_mut(&mut self, ast: &mut GenericAst) {
+        walk_generic_ast_mut(self, ast)
+    }
+
+    fn visit_func_mut(&mut self, func: &mut FuncAst) {
+        walk_func_mut(self, func)
+    }
+
+    fn visit_prototype_mut(&mut self, _name: &mut String, _args: &mut Vec<Param>, _return_type: &mut Option<Type>) {}
+
+    fn visit_struct_mut(&mut self, _struct_ast: &mut StructAst) {}
+
+    fn visit_global_mut(&mut self, global: &mut GlobalAst) {
+        walk_global_mut(self, global)
+    }
+
+    fn visit_import_mut(&mut self, _import: &mut ImportAst) {}
+
+    fn visit_function_mut(&mut self, proto: &mut FuncAst, body: &mut ExprAst) {
+        walk_function_mut(self, proto, body)
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut ExprAst) {
+        walk_expr_mut(self, expr)
+    }
+
+    fn visit_number_expr_mut(&mut self, _number: &mut f64) {}
+
+    fn visit_string_expr_mut(&mut self, _value: &mut String) {}
+
+    fn visit_variable_expr_mut(&mut self, _name: &mut String) {}
+
+    fn visit_unary_expr_mut(&mut self, _op: &mut String, operand: &mut ExprAst) {
+        walk_unary_expr_mut(self, operand)
+    }
+
+    fn visit_binary_expr_mut(&mut self, _op: &mut String, lhs: &mut ExprAst, rhs: &mut ExprAst) {
+        walk_binary_expr_mut(self, lhs, rhs)
+    }
+
+    fn visit_call_expr_mut(&mut self, _callee: &mut String, args: &mut Vec<ExprAst>) {
+        walk_call_expr_mut(self, args)
+    }
+
+    fn visit_cast_expr_mut(&mut self, _ty: &mut Type, operand: &mut ExprAst) {
+        walk_cast_expr_mut(self, operand)
+    }
+
+    fn visit_block_expr_mut(&mut self, exprs: &mut Vec<ExprAst>) {
+        walk_block_expr_mut(self, exprs)
+    }
+
+    fn visit_array_expr_mut(&mut self, elements: &mut Vec<ExprAst>) {
+        walk_array_expr_mut(self, elements)
+    }
+
+    fn visit_index_expr_mut(&mut self, array: &mut ExprAst, index: &mut ExprAst) {
+        walk_index_expr_mut(self, array, index)
+    }
+
+    fn visit_field_expr_mut(&mut self, object: &mut ExprAst, _field: &mut String) {
+        walk_field_expr_mut(self, object)
+    }
+
+    fn visit_lambda_expr_mut(&mut self, _params: &mut Vec<Param>, _return_type: &mut Option<Type>, body: &mut ExprAst) {
+        walk_lambda_expr_mut(self, body)
+    }
+}
+
+pub fn walk_generic_ast_mut<V: VisitorMut + ?Sized>(visitor: &mut V, ast: &mut GenericAst) {
+    match ast {
+        GenericAst::ExprAst(expr) => visitor.visit_expr_mut(expr),
+        GenericAst::FuncAst(func) => visitor.visit_func_mut(func),
+        GenericAst::StructAst(struct_ast) => visitor.visit_struct_mut(struct_ast),
+        GenericAst::GlobalAst(global) => visitor.visit_global_mut(global),
+        GenericAst::ImportAst(import) => visitor.visit_import_mut(import)
+    }
+}
+
+pub fn walk_global_mut<V: VisitorMut + ?Sized>(visitor: &mut V, global: &mut GlobalAst) {
+    visitor.visit_expr_mut(&mut global.value);
+}
+
+pub fn walk_func_mut<V: VisitorMut + ?Sized>(visitor: &mut V, func: &mut FuncAst) {
+    match func {
+        FuncAst::Prototype { name, args, return_type } => visitor.visit_prototype_mut(name, args, return_type),
+        FuncAst::Function { proto, body } => visitor.visit_function_mut(proto, body)
+    }
+}
+
+pub fn walk_function_mut<V: VisitorMut + ?Sized>(visitor: &mut V, proto: &mut FuncAst, body: &mut ExprAst) {
+    visitor.visit_func_mut(proto);
+    visitor.visit_expr_mut(body);
+}
+
+pub fn walk_expr_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expr: &mut ExprAst) {
+    match expr {
+        ExprAst::NumberExpr { number } => visitor.visit_number_expr_mut(number),
+        ExprAst::StringExpr { value } => visitor.visit_string_expr_mut(value),
+        ExprAst::VariableExpr { name } => visitor.visit_variable_expr_mut(name),
+        ExprAst::UnaryExpr { op, operand } => visitor.visit_unary_expr_mut(op, operand),
+        ExprAst::BinaryExpr { op, lhs, rhs } => visitor.visit_binary_expr_mut(op, lhs, rhs),
+        ExprAst::CallExpr { callee, args } => visitor.visit_call_expr_mut(callee, args),
+        ExprAst::CastExpr { ty, operand } => visitor.visit_cast_expr_mut(ty, operand),
+        ExprAst::BlockExpr { exprs } => visitor.visit_block_expr_mut(exprs),
+        ExprAst::ArrayExpr { elements } => visitor.visit_array_expr_mut(elements),
+        ExprAst::IndexExpr { array, index } => visitor.visit_index_expr_mut(array, index),
+        ExprAst::FieldExpr { object, field } => visitor.visit_field_expr_mut(object, field),
+        ExprAst::LambdaExpr { params, return_type, body } => visitor.visit_lambda_expr_mut(params, return_type, body)
+    }
+}
+
+pub fn walk_unary_expr_mut<V: VisitorMut + ?Sized>(visitor: &mut V, operand: &mut ExprAst) {
+    visitor.visit_expr_mut(operand);
+}
+
+pub fn walk_binary_expr_mut<V: VisitorMut + ?Sized>(visitor: &mut V, lhs: &mut ExprAst, rhs: &mut ExprAst) {
+    visitor.visit_expr_mut(lhs);
+    visitor.visit_expr_mut(rhs);
+}
+
+pub fn walk_call_expr_mut<V: VisitorMut + ?Sized>(visitor: &mut V, args: &mut [ExprAst]) {
+    for arg in args {
+        visitor.visit_expr_mut(arg);
+    }
+}
+
+pub fn walk_cast_expr_mut<V: VisitorMut + ?Sized>(visitor: &mut V, operand: &mut ExprAst) {
+    visitor.visit_expr_mut(operand);
+}
+
+pub fn walk_block_expr_mut<V: VisitorMut + ?Sized>(visitor: &mut V, exprs: &mut [ExprAst]) {
+    for expr in exprs {
+        visitor.visit_expr_mut(expr);
+    }
+}
+
+pub fn walk_array_expr_mut<V: VisitorMut + ?Sized>(visitor: &mut V, elements: &mut [ExprAst]) {
+    for element in elements {
+        visitor.visit_expr_mut(element);
+    }
+}
+
+pub fn walk_index_expr_mut<V: VisitorMut + ?Sized>(visitor: &mut V, array: &mut ExprAst, index: &mut ExprAst) {
+    visitor.visit_expr_mut(array);
+    visitor.visit_expr_mut(index);
+}
+
+pub fn walk_field_expr_mut<V: VisitorMut + ?Sized>(visitor: &mut V, object: &mut ExprAst) {
+    visitor.visit_expr_mut(object);
+}
+
+pub fn walk_lambda_expr_mut<V: VisitorMut + ?Sized>(visitor: &mut V, body: &mut ExprAst) {
+    visitor.visit_expr_mut(body);
+}
+
+/*
+    The default fold methods rebuild the node from its folded children, so the identity Folder
+    returns an equal tree.
+*/
+pub trait Folder {
+    fn fold_generic_ast(&mut self, ast: GenericAst) -> GenericAst {
+        match ast {
+            GenericAst::ExprAst(expr) => GenericAst::ExprAst(self.fold_expr(expr)),
+            GenericAst::FuncAst(func) => GenericAst::FuncAst(self.fold_func(func)),
+            GenericAst::StructAst(struct_ast) => GenericAst::StructAst(self.fold_struct(struct_ast)),
+            GenericAst::GlobalAst(global) => GenericAst::GlobalAst(self.fold_global(global)),
+            GenericAst::ImportAst(import) => GenericAst::ImportAst(self.fold_import(import))
+        }
+    }
+
+    fn fold_import(&mut self, import: ImportAst) -> ImportAst {
+        import
+    }
+
+    fn fold_struct(&mut self, struct_ast: StructAst) -> StructAst {
+        struct_ast
+    }
+
+    fn fold_global(&mut self, global: GlobalAst) -> GlobalAst {
+        GlobalAst { value: self.fold_expr(global.value), ..global }
+    }
+
+    fn fold_func(&mut self, func: FuncAst) -> FuncAst {
+        match func {
+            FuncAst::Prototype { name, args, return_type } => self.fold_prototype(name, args, return_type),
+            FuncAst::Function { proto, body } => self.fold_function(*proto, *body)
+        }
+    }
+
+    fn fold_prototype(&mut self, name: String, args: Vec<Param>, return_type: Option<Type>) -> FuncAst {
+        FuncAst::Prototype { name, args, return_type }
+    }
+
+    fn fold_function(&mut self, proto: FuncAst, body: ExprAst) -> FuncAst {
+        FuncAst::Function { proto: Box::new(self.fold_func(proto)), body: Box::new(self.fold_expr(body)) }
+    }
+
+    fn fold_expr(&mut self, expr: ExprAst) -> ExprAst {
+        match expr {
+            ExprAst::NumberExpr { number } => self.fold_number_expr(number),
+            ExprAst::StringExpr { value } => self.fold_string_expr(value),
+            ExprAst::VariableExpr { name } => self.fold_variable_expr(name),
+            ExprAst::UnaryExpr { op, operand } => self.fold_unary_expr(op, *operand),
+            ExprAst::BinaryExpr { op, lhs, rhs } => self.fold_binary_expr(op, *lhs, *rhs),
+            ExprAst::CallExpr { callee, args } => self.fold_call_expr(callee, args),
+            ExprAst::CastExpr { ty, operand } => self.fold_cast_expr(ty, *operand),
+            ExprAst::BlockExpr { exprs } => self.fold_block_expr(exprs),
+            ExprAst::ArrayExpr { elements } => self.fold_array_expr(elements),
+            ExprAst::IndexExpr { array, index } => self.fold_index_expr(*array, *index),
+            ExprAst::FieldExpr { object, field } => self.fold_field_expr(*object, field),
+            ExprAst::LambdaExpr { params, return_type, body } => self.fold_lambda_expr(params, return_type, *body)
+        }
+    }
+
+    fn fold_number_expr(&mut self, number: f64) -> ExprAst {
+        ExprAst::NumberExpr { number }
+    }
+
+    fn fold_string_expr(&mut self, value: String) -> ExprAst {
+        ExprAst::StringExpr { value }
+    }
+
+    fn fold_variable_expr(&mut self, name: String) -> ExprAst {
+        ExprAst::VariableExpr { name }
+    }
+
+    fn fold_unary_expr(&mut self, op: String, operand: ExprAst) -> ExprAst {
+        ExprAst::UnaryExpr { op, operand: Box::new(self.fold_expr(operand)) }
+    }
+
+    fn fold_binary_expr(&mut self, op: String, lhs: ExprAst, rhs: ExprAst) -> ExprAst {
+        ExprAst::BinaryExpr { op, lhs: Box::new(self.fold_expr(lhs)), rhs: Box::new(self.fold_expr(rhs)) }
+    }
+
+    fn fold_call_expr(&mut self, callee: String, args: Vec<ExprAst>) -> ExprAst {
+        ExprAst::CallExpr { callee, args: args.into_iter().map(|arg| self.fold_expr(arg)).collect() }
+    }
+
+    fn fold_cast_expr(&mut self, ty: Type, operand: ExprAst) -> ExprAst {
+        ExprAst::CastExpr { ty, operand: Box::new(self.fold_expr(operand)) }
+    }
+
+    fn fold_block_expr(&mut self, exprs: Vec<ExprAst>) -> ExprAst {
+        ExprAst::BlockExpr { exprs: exprs.into_iter().map(|expr| self.fold_expr(expr)).collect() }
+    }
+
+    fn fold_array_expr(&mut self, elements: Vec<ExprAst>) -> ExprAst {
+        ExprAst::ArrayExpr { elements: elements.into_iter().map(|element| self.fold_expr(element)).collect() }
+    }
+
+    fn fold_index_expr(&mut self, array: ExprAst, index: ExprAst) -> ExprAst {
+        ExprAst::IndexExpr { array: Box::new(self.fold_expr(array)), index: Box::new(self.fold_expr(index)) }
+    }
+
+    fn fold_field_expr(&mut self, object: ExprAst, field: String) -> ExprAst {
+        ExprAst::FieldExpr { object: Box::new(self.fold_expr(object)), field }
+    }
+
+    fn fold_lambda_expr(&mut self, params: Vec<Param>, return_type: Option<Type>, body: ExprAst) -> ExprAst {
+        ExprAst::LambdaExpr { params, return_type, body: Box::new(self.fold_expr(body)) }
+    }
+}
@@ -1,13 +1,65 @@
-pub const SYMBOL_NON_OP_CHARS: &'static [char; 3] = &['(', ')', ','];
-pub const SYMBOL_OP_CHARS: &'static [char; 6] = &['+', '-', '*', '/', '>', '<'];
+use crate::syntax::types::Type;
+
+pub const SYMBOL_NON_OP_CHARS: &'static [char; 11] = &['(', ')', ',', '{', '}', '[', ']', ';', ':', '.', '\\'];
+pub const SYMBOL_OP_CHARS: &'static [char; 12] = &['+', '-', '*', '/', '%', '>', '<', '^', '=', '!', '&', '|'];
+// longest operators first, so that "<=" is not read as "<" followed by "="
+pub const OPERATORS: &[&str] = &["<=", ">=", "==", "!=", "&&", "||", "->", "+", "-", "*", "/", "%", "<", ">", "^", "=", "!"];
+// between the namespace of an imported file and a name declared in it, math::sqrt
+pub const NAMESPACE_SEPARATOR: &str = "::";
+pub const UNARY_OPS: &[&str] = &["-", "!"];
 pub fn is_symbol_char(c: char) -> bool {
     SYMBOL_NON_OP_CHARS.contains(&c) || SYMBOL_OP_CHARS.contains(&c)
 }
-pub fn get_op_precedence(op: &char) -> i8 {
-    match op {
-        '<' | '>' => 10,
-        '+' | '-' => 20,
-        '*' | '/' => 30,
-        _ => -1
-    }
-}
\ No newline at end of file
+pub fn is_unary_op(op: &str) -> bool {
+    UNARY_OPS.contains(&op)
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Associativity {
+    Left,
+    Right,
+    NonAssoc
+}
+
+// (operator, precedence, associativity), a higher precedence binds tighter
+pub const BINARY_OPS: &[(&str, i8, Associativity)] = &[
+    ("=", 2, Associativity::Right),
+    ("||", 4, Associativity::Left),
+    ("&&", 6, Associativity::Left),
+    ("==", 8, Associativity::NonAssoc),
+    ("!=", 8, Associativity::NonAssoc),
+    ("<", 10, Associativity::NonAssoc),
+    (">", 10, Associativity::NonAssoc),
+    ("<=", 10, Associativity::NonAssoc),
+    (">=", 10, Associativity::NonAssoc),
+    ("+", 20, Associativity::Left),
+    ("-", 20, Associativity::Left),
+    ("*", 30, Associativity::Left),
+    ("/", 30, Associativity::Left),
+    ("%", 30, Associativity::Left),
+    ("^", 50, Associativity::Right)
+];
+pub const UNARY_OP_PRECEDENCE: i8 = 40;
+
+pub fn get_binary_op(op: &str) -> Option<(i8, Associativity)> {
+    BINARY_OPS.iter()
+        .find(|(binary_op, _, _)| *binary_op == op)
+        .map(|(_, precedence, associativity)| (*precedence, *associativity))
+}
+pub fn get_op_precedence(op: &str) -> i8 {
+    get_binary_op(op).map_or(-1, |(precedence, _)| precedence)
+}
+
+// functions every program can call without declaring them: (name, parameter types, return type)
+pub const BUILTIN_FUNCTIONS: &[(&str, &[Type], Type)] = &[
+    // the number of elements of an array
+    ("len", &[Type::Array], Type::I64),
+    // appends an element to an array and returns its new length
+    ("push", &[Type::Array, Type::F64], Type::I64)
+];
+
+pub fn get_builtin_function(name: &str) -> Option<(&'static [Type], Type)> {
+    BUILTIN_FUNCTIONS.iter()
+        .find(|(builtin, _, _)| *builtin == name)
+        .map(|(_, params, return_type)| (*params, *return_type))
+}
@@ -0,0 +1,229 @@
+#[cfg(test)]
+mod tests {
+    use kaleidoscope::analysis::type_check::*;
+    use kaleidoscope::analysis::type_check::TypeError::*;
+    use kaleidoscope::parse::parser::*;
+    use kaleidoscope::parse::token::Span;
+    use kaleidoscope::syntax::types::Type;
+
+    // checks every item with the same checker, returning the last item printed or all the errors
+    fn infer_source(src: &str) -> Result<String, Vec<TypeError>> {
+        let mut parser = Parser::new(src);
+        let mut checker = TypeChecker::new();
+        let mut last = String::new();
+        let mut errors = Vec::new();
+        while let Some(item) = parser.next_spanned() {
+            let item = item.unwrap();
+            match checker.check_with_spans(&item.ast, &item.spans) {
+                Ok(item) => last = item.to_string(),
+                Err(item_errors) => errors.extend(item_errors)
+            }
+        }
+        if errors.is_empty() { Ok(last) } else { Err(errors) }
+    }
+
+    macro_rules! inference_test {
+        ($name:ident, $src:expr => $should_be:expr) => {
+            #[test]
+            fn $name() {
+                assert_eq!(infer_source($src).unwrap(), $should_be);
+            }
+        }
+    }
+
+    macro_rules! inference_error_test {
+        ($name:ident, $src:expr => $should_be:expr) => {
+            #[test]
+            fn $name() {
+                let got = infer_source($src).unwrap_err();
+                let should_be: Vec<TypeError> = $should_be;
+                assert_eq!(got, should_be);
+            }
+        }
+    }
+
+    inference_test!(
+        can_fall_back_to_f64_without_constraints,
+        "def f(x, y) x * y + 1" => "def f(x: f64, y: f64) -> f64 x * y + 1"
+    );
+
+    inference_test!(
+        can_infer_parameter_from_call,
+        "extern g(n: i64) -> i64\ndef f(x) g(x) + 1" => "def f(x: i64) -> i64 g(x) + i64(1)"
+    );
+
+    inference_test!(
+        can_infer_parameter_through_operations,
+        "extern g(n: i64)\ndef f(x) g(x * 2 + 1)" => "def f(x: i64) -> f64 g(x * i64(2) + i64(1))"
+    );
+
+    inference_test!(
+        can_infer_parameter_from_other_operand,
+        "def f(x, n: i64) x * n" => "def f(x: i64, n: i64) -> i64 x * n"
+    );
+
+    inference_test!(
+        can_infer_parameter_from_return_type,
+        "def f(x) -> i64 x + 1" => "def f(x: i64) -> i64 x + i64(1)"
+    );
+
+    inference_test!(
+        can_infer_bool_return_type,
+        "def less(a, b) a < b" => "def less(a: f64, b: f64) -> bool a < b"
+    );
+
+    inference_test!(
+        can_infer_bool_parameter,
+        "extern g(flag: bool)\ndef f(x) g(x)" => "def f(x: bool) -> f64 g(x)"
+    );
+
+    inference_test!(
+        can_infer_str_parameter,
+        "extern println(s: str)\ndef greet(name) println(name)" => "def greet(name: str) -> f64 println(name)"
+    );
+
+    inference_test!(
+        can_infer_str_return_type,
+        "def label(n: i64) str(n)" => "def label(n: i64) -> str str(n)"
+    );
+
+    inference_test!(
+        can_ignore_bools_in_arithmetic,
+        "def f(a: bool, n) a + n" => "def f(a: bool, n: f64) -> f64 f64(a) + n"
+    );
+
+    inference_test!(
+        can_infer_wider_result_of_mixed_operands,
+        "def f(x, n: i64) x * n + 0.5" => "def f(x: i64, n: i64) -> f64 f64(x * n) + 0.5"
+    );
+
+    inference_test!(
+        can_infer_through_recursive_calls,
+        "extern g(n: i64)\ndef count(n) { g(n); count(n - 1) }" => "def count(n: i64) -> f64 { g(n); count(n - i64(1)) }"
+    );
+
+    inference_test!(
+        can_keep_recursive_numeric_function_f64,
+        "def fact(n) n < 1 || n * fact(n - 1)" => "def fact(n: f64) -> f64 f64(n < 1 || bool(n * fact(n - 1)))"
+    );
+
+    inference_test!(
+        can_take_types_from_earlier_declaration,
+        "extern f(x: i64) -> i64\ndef f(x) x" => "def f(x: i64) -> i64 x"
+    );
+
+    inference_test!(
+        can_prefer_annotations,
+        "extern g(n: i64)\ndef f(x: f64) g(i64(x))" => "def f(x: f64) -> f64 g(i64(x))"
+    );
+
+    inference_test!(
+        can_infer_array_from_indexing,
+        "def get(a, i) a[i]" => "def get(a: [f64], i: i64) -> f64 a[i]"
+    );
+
+    inference_test!(
+        can_infer_array_from_builtin_call,
+        "def count(a) len(a)" => "def count(a: [f64]) -> i64 len(a)"
+    );
+
+    inference_test!(
+        can_infer_array_return_type,
+        "def wrap(x) [x, 0.5]" => "def wrap(x: f64) -> [f64] [x, 0.5]"
+    );
+
+    inference_error_test!(
+        can_reject_conflicting_requirements,
+        "extern a(n: i64)\nextern b(flag: bool)\ndef f(x) { a(x); b(x) }" => vec![
+            ConflictingInference {
+                function: "f".to_string(),
+                subject: "parameter x".to_string(),
+                first: Type::I64,
+                first_context: "argument 1 of a".to_string(),
+                first_span: Some(Span { start: 49, end: 53 }),
+                second: Type::Bool,
+                second_context: "argument 1 of b".to_string(),
+                second_span: Some(Span { start: 55, end: 59 })
+            }
+        ]
+    );
+
+    inference_error_test!(
+        can_reject_ambiguous_hints,
+        "def f(x, n: i64) x * n + x * 0.5" => vec![
+            AmbiguousInference {
+                function: "f".to_string(),
+                subject: "parameter x".to_string(),
+                first: Type::I64,
+                first_context: "operand of *".to_string(),
+                first_span: Some(Span { start: 17, end: 22 }),
+                second: Type::F64,
+                second_context: "operand of *".to_string(),
+                second_span: Some(Span { start: 25, end: 32 })
+            }
+        ]
+    );
+
+    inference_error_test!(
+        can_reject_array_used_as_number,
+        "extern g(x: f64)\ndef f(a) { g(a); a[0] }" => vec![
+            ConflictingInference {
+                function: "f".to_string(),
+                subject: "parameter a".to_string(),
+                first: Type::F64,
+                first_context: "argument 1 of g".to_string(),
+                first_span: Some(Span { start: 28, end: 32 }),
+                second: Type::Array,
+                second_context: "indexed value".to_string(),
+                second_span: Some(Span { start: 34, end: 38 })
+            }
+        ]
+    );
+
+    inference_test!(
+        can_infer_struct_from_field_access,
+        "struct Point { x, y: i64 }\ndef f(p) p.y" => "def f(p: Point) -> i64 p.y"
+    );
+
+    inference_test!(
+        can_infer_struct_return_type,
+        "struct Point { x, y }\ndef origin() Point(0, 0)" => "def origin() -> Point Point(0, 0)"
+    );
+
+    inference_test!(
+        can_leave_field_shared_by_structs_to_annotations,
+        "struct A { x }\nstruct B { x, y }\ndef f(a: A, b) { b.y; a.x }" => "def f(a: A, b: B) -> f64 { b.y; a.x }"
+    );
+
+    inference_test!(
+        can_infer_called_parameter_as_function,
+        "def twice(f, x) f(f(x))" => "def twice(f: fn(f64) -> f64, x: f64) -> f64 f(f(x))"
+    );
+
+    inference_test!(
+        can_infer_lambda_return_type,
+        "def adder(k: i64) \\(x: i64) -> x + k" => "def adder(k: i64) -> fn(i64) -> i64 \\(x: i64): i64 -> x + k"
+    );
+
+    inference_test!(
+        can_infer_captured_variable_from_lambda_body,
+        "extern apply(f: fn(i64) -> i64) -> i64\ndef f(k) apply(\\(x: i64) -> x * k)" => "def f(k: i64) -> i64 apply(\\(x: i64): i64 -> x * k)"
+    );
+
+    inference_test!(
+        can_infer_from_global_types,
+        "global count: i64 = 0\ndef f(x) count = x" => "def f(x: i64) -> i64 count = x"
+    );
+
+    // out of the range of i64, the literal is an f64 however it is used
+    inference_test!(
+        can_keep_huge_literal_f64,
+        "def f(n: i64) n + 1e19" => "def f(n: i64) -> f64 f64(n) + 10000000000000000000"
+    );
+
+    #[test]
+    fn can_describe_conflict() {
+        let got = infer_source("extern a(n: i64)\nextern b(flag: bool)\ndef f(x) { a(x); b(x) }").unwrap_err()[0].to_string();
+        assert_eq!(got, "Conflicting types for parameter x of f: i64 from argument 1 of a at bytes 49..53, bool from argument 1 of b at bytes 55..59. Annotate it to choose one.");
+    }
+}
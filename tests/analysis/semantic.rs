@@ -0,0 +1,203 @@
+#[cfg(test)]
+mod tests {
+    use kaleidoscope::analysis::semantic::*;
+    use kaleidoscope::analysis::semantic::SemanticError::*;
+    use kaleidoscope::parse::parser::*;
+
+    macro_rules! semantic_test {
+        ($name:ident, $src:expr => $should_be:expr) => {
+            #[test]
+            fn $name() {
+                let program = Parser::new($src).parse_program();
+                assert!(program.errors.is_empty(), "{:?}", program.errors);
+
+                let got = analyze_program(&program.items);
+                let should_be: Vec<SemanticError> = $should_be;
+                assert_eq!(got, should_be);
+            }
+        }
+    }
+
+    semantic_test!(
+        can_accept_valid_program,
+        r###"
+            extern sin(x)
+            def square(x) x * x
+            def f(x, y) { x = square(sin(x)); x + y }
+            def fact(n) n < 1 || n * fact(n - 1)
+            f(1, fact(3))
+        "###
+        => vec![]
+    );
+
+    semantic_test!(
+        can_reject_unknown_variable,
+        "def f(x) x + y" => vec![UnknownVariable { name: "y".to_string(), function: Some("f".to_string()) }]
+    );
+
+    semantic_test!(
+        can_reject_variable_in_top_level_expression,
+        "def f(x) x\nx + 1" => vec![UnknownVariable { name: "x".to_string(), function: None }]
+    );
+
+    semantic_test!(
+        can_reject_assignment_to_unknown_variable,
+        "def f(x) { y = x; y }" => vec![
+            UnknownVariable { name: "y".to_string(), function: Some("f".to_string()) },
+            UnknownVariable { name: "y".to_string(), function: Some("f".to_string()) }
+        ]
+    );
+
+    semantic_test!(
+        can_reject_call_before_declaration,
+        "def f(x) g(x)\ndef g(x) x" => vec![UnknownFunction { name: "g".to_string() }]
+    );
+
+    semantic_test!(
+        can_reject_wrong_arity,
+        "extern atan2(y, x)\natan2(1)\natan2(1, 2, 3)" => vec![
+            ArityMismatch { callee: "atan2".to_string(), expected: 2, found: 1 },
+            ArityMismatch { callee: "atan2".to_string(), expected: 2, found: 3 }
+        ]
+    );
+
+    semantic_test!(
+        can_reject_duplicate_parameters,
+        "def f(x, y, x, y) x" => vec![
+            DuplicateParameter { function: "f".to_string(), name: "x".to_string() },
+            DuplicateParameter { function: "f".to_string(), name: "y".to_string() }
+        ]
+    );
+
+    semantic_test!(
+        can_accept_definition_of_extern,
+        "extern f(x)\nf(1)\ndef f(x) x\nf(2)" => vec![]
+    );
+
+    semantic_test!(
+        can_reject_conflicting_declarations,
+        "extern f(x)\ndef f(x, y) x + y\nf(1)" => vec![ConflictingDeclaration { name: "f".to_string(), expected: 1, found: 2 }]
+    );
+
+    semantic_test!(
+        can_reject_redefinition,
+        "def f(x) x\ndef f(x) x + 1" => vec![Redefinition { name: "f".to_string() }]
+    );
+
+    semantic_test!(
+        can_collect_errors_inside_arguments,
+        "def f(x) f(y, g(z))" => vec![
+            ArityMismatch { callee: "f".to_string(), expected: 1, found: 2 },
+            UnknownVariable { name: "y".to_string(), function: Some("f".to_string()) },
+            UnknownFunction { name: "g".to_string() },
+            UnknownVariable { name: "z".to_string(), function: Some("f".to_string()) }
+        ]
+    );
+
+    semantic_test!(
+        can_resolve_builtin_functions,
+        "def f(x) push(x, len(x))\nlen(1, 2)" => vec![ArityMismatch { callee: "len".to_string(), expected: 1, found: 2 }]
+    );
+
+    semantic_test!(
+        can_reject_redeclaration_of_builtin,
+        "extern len(a)\ndef push(a, x) x" => vec![
+            BuiltinRedeclaration { name: "len".to_string() },
+            BuiltinRedeclaration { name: "push".to_string() }
+        ]
+    );
+
+    semantic_test!(
+        can_resolve_struct_constructor,
+        "struct Point { x, y }\ndef f(p: Point) -> Point Point(p.y, p.x)\nPoint(1)" => vec![
+            ArityMismatch { callee: "Point".to_string(), expected: 2, found: 1 }
+        ]
+    );
+
+    semantic_test!(
+        can_reject_duplicate_field,
+        "struct Point { x, y, x }" => vec![DuplicateField { ty: "Point".to_string(), name: "x".to_string() }]
+    );
+
+    semantic_test!(
+        can_reject_unknown_type,
+        "def f(x: int) x\nstruct List { next: List }\nextern g() -> Tree" => vec![
+            UnknownType { name: "int".to_string() },
+            UnknownType { name: "List".to_string() },
+            UnknownType { name: "Tree".to_string() }
+        ]
+    );
+
+    semantic_test!(
+        can_reject_struct_named_like_function,
+        "extern Point(x)\nstruct Point { x }\nstruct Line { a: Point }\ndef Line(a) a\nstruct len { n }" => vec![
+            Redefinition { name: "Point".to_string() },
+            UnknownType { name: "Point".to_string() },
+            Redefinition { name: "Line".to_string() },
+            BuiltinRedeclaration { name: "len".to_string() }
+        ]
+    );
+
+    semantic_test!(
+        can_resolve_functions_as_values_and_calls_of_variables,
+        "def square(x) x * x\ndef apply(f, x) f(x)\napply(square, 2)\napply(len, 2)" => vec![
+            UnknownVariable { name: "len".to_string(), function: None }
+        ]
+    );
+
+    semantic_test!(
+        can_resolve_lambda_parameters_and_captures,
+        "def f(k) \\x -> \\y -> x + y + k + z\n\\(a, a) -> a" => vec![
+            UnknownVariable { name: "z".to_string(), function: Some("f".to_string()) },
+            DuplicateParameter { function: "lambda".to_string(), name: "a".to_string() }
+        ]
+    );
+
+    semantic_test!(
+        can_resolve_consts_and_globals,
+        "const pi = 3.14159\nglobal counter = 0\ndef f(x) { counter = counter + x * pi; g(1) }\nglobal g = f\ndef h(pi) pi = 1" => vec![
+            UnknownFunction { name: "g".to_string() }
+        ]
+    );
+
+    semantic_test!(
+        can_reject_assignment_to_const,
+        "const pi = 3.14159\ndef f() pi = 3\npi = 3" => vec![
+            ConstantAssignment { name: "pi".to_string() },
+            ConstantAssignment { name: "pi".to_string() }
+        ]
+    );
+
+    semantic_test!(
+        can_reject_non_constant_values,
+        "global counter = 0\nconst a = -(1 + 2) * i64(1.5) == 3 || 1\nconst b = counter + 1\nconst c = str(1)\nglobal d = [1]\nconst e = e" => vec![
+            NonConstantValue { name: "a".to_string() },
+            NonConstantValue { name: "b".to_string() },
+            NonConstantValue { name: "c".to_string() },
+            NonConstantValue { name: "d".to_string() },
+            UnknownVariable { name: "e".to_string(), function: None }
+        ]
+    );
+
+    semantic_test!(
+        can_reject_global_redefinitions,
+        "def f(x) x\nglobal f = 1\nglobal len = 2\nconst g = 1\nglobal g = 2\ndef g() 1\nstruct g {}" => vec![
+            GlobalRedefinition { name: "f".to_string() },
+            BuiltinRedeclaration { name: "len".to_string() },
+            GlobalRedefinition { name: "g".to_string() },
+            GlobalRedefinition { name: "g".to_string() },
+            GlobalRedefinition { name: "g".to_string() }
+        ]
+    );
+
+    #[test]
+    fn can_keep_declarations_across_items() {
+        let mut analyzer = SemanticAnalyzer::new();
+        let mut parser = Parser::new("def f(x, x) x\nf(1, 2)\nf()");
+
+        assert!(analyzer.analyze(&parser.next().unwrap().unwrap()).is_err());
+        assert!(analyzer.analyze(&parser.next().unwrap().unwrap()).is_ok());
+        let errors = analyzer.analyze(&parser.next().unwrap().unwrap()).unwrap_err();
+        assert_eq!(errors[0].to_string(), "Function f expects 2 argument(s), found 0.");
+    }
+}
@@ -0,0 +1,343 @@
+#[cfg(test)]
+mod tests {
+    use kaleidoscope::analysis::type_check::*;
+    use kaleidoscope::analysis::type_check::TypeError::*;
+    use kaleidoscope::parse::parser::*;
+    use kaleidoscope::syntax::types::{FunctionType, StructName, Type};
+
+    // checks every item with the same checker, returning the printed elaborated items or the errors
+    fn check_source(src: &str) -> Result<Vec<String>, Vec<TypeError>> {
+        let program = Parser::new(src).parse_program();
+        assert!(program.errors.is_empty(), "{:?}", program.errors);
+
+        let mut checker = TypeChecker::new();
+        let mut checked = Vec::new();
+        let mut errors = Vec::new();
+        for item in &program.items {
+            match checker.check(item) {
+                Ok(item) => checked.push(item.to_string()),
+                Err(item_errors) => errors.extend(item_errors)
+            }
+        }
+        if errors.is_empty() { Ok(checked) } else { Err(errors) }
+    }
+
+    macro_rules! elaboration_test {
+        ($name:ident, $src:expr => $should_be:expr) => {
+            #[test]
+            fn $name() {
+                let got = check_source($src).unwrap();
+                let should_be: Vec<&str> = $should_be;
+                assert_eq!(got, should_be);
+            }
+        }
+    }
+
+    macro_rules! type_error_test {
+        ($name:ident, $src:expr => $should_be:expr) => {
+            #[test]
+            fn $name() {
+                let got = check_source($src).unwrap_err();
+                let should_be: Vec<TypeError> = $should_be;
+                assert_eq!(got, should_be);
+            }
+        }
+    }
+
+    elaboration_test!(
+        can_fill_in_default_types,
+        "extern sin(x)\ndef f(x, y: i64) x" => vec!["extern sin(x: f64) -> f64", "def f(x: f64, y: i64) -> f64 x"]
+    );
+
+    elaboration_test!(
+        can_adapt_integral_literals_to_i64,
+        "def f(n: i64) -> i64 n * 2 + 1" => vec!["def f(n: i64) -> i64 n * i64(2) + i64(1)"]
+    );
+
+    elaboration_test!(
+        can_keep_literals_f64_without_i64_context,
+        "2 / 4" => vec!["2 / 4"]
+    );
+
+    elaboration_test!(
+        can_keep_fractional_literals_f64,
+        "def f(n: i64) -> f64 n * 0.5" => vec!["def f(n: i64) -> f64 f64(n) * 0.5"]
+    );
+
+    elaboration_test!(
+        can_widen_mixed_arithmetic,
+        "def f(n: i64, x: f64) n + x" => vec!["def f(n: i64, x: f64) -> f64 f64(n) + x"]
+    );
+
+    elaboration_test!(
+        can_compute_power_on_f64,
+        "def f(n: i64) n ^ 2" => vec!["def f(n: i64) -> f64 f64(n) ^ 2"]
+    );
+
+    elaboration_test!(
+        can_produce_bool_from_comparisons,
+        "def f(a: i64, b: i64) -> bool a < b && a != 0" => vec!["def f(a: i64, b: i64) -> bool a < b && a != i64(0)"]
+    );
+
+    elaboration_test!(
+        can_take_truth_value_of_numbers,
+        "def f(x) -> bool !x || x" => vec!["def f(x: f64) -> bool !bool(x) || bool(x)"]
+    );
+
+    elaboration_test!(
+        can_compare_bools_without_conversion,
+        "def f(a: bool, b: bool) -> bool a == b" => vec!["def f(a: bool, b: bool) -> bool a == b"]
+    );
+
+    elaboration_test!(
+        can_count_bools_as_i64,
+        "def f(a: bool, n: i64) -> i64 a + n" => vec!["def f(a: bool, n: i64) -> i64 i64(a) + n"]
+    );
+
+    elaboration_test!(
+        can_convert_arguments_to_parameter_types,
+        "extern g(x: f64, n: i64)\ndef f(n: i64) g(n, 3)" => vec![
+            "extern g(x: f64, n: i64) -> f64",
+            "def f(n: i64) -> f64 g(f64(n), i64(3))"
+        ]
+    );
+
+    elaboration_test!(
+        can_drop_conversion_to_same_type,
+        "def f(n: i64) -> i64 i64(n)" => vec!["def f(n: i64) -> i64 n"]
+    );
+
+    elaboration_test!(
+        can_convert_explicitly,
+        "def f(x: f64) -> i64 i64(x) / 2" => vec!["def f(x: f64) -> i64 i64(x) / i64(2)"]
+    );
+
+    elaboration_test!(
+        can_type_string_literals,
+        "extern println(s: str)\ndef f(n: i64) println(\"n\")" => vec!["extern println(s: str) -> f64", "def f(n: i64) -> f64 println(\"n\")"]
+    );
+
+    elaboration_test!(
+        can_format_with_conversion_to_str,
+        "extern println(s: str)\ndef f(n: i64, b: bool) { println(str(n)); println(str(b)) }" => vec![
+            "extern println(s: str) -> f64",
+            "def f(n: i64, b: bool) -> f64 { println(str(n)); println(str(b)) }"
+        ]
+    );
+
+    type_error_test!(
+        can_reject_implicit_conversion_to_str,
+        "extern println(s: str)\ndef f(x: f64) println(x)" => vec![
+            ImplicitConversion { from: Type::F64, to: Type::Str, context: "argument 1 of println".to_string() }
+        ]
+    );
+
+    type_error_test!(
+        can_reject_conversion_from_str,
+        "def f(s: str) -> i64 i64(s)" => vec![
+            InvalidConversion { from: Type::Str, to: Type::I64, context: "i64(...)".to_string() }
+        ]
+    );
+
+    type_error_test!(
+        can_reject_str_argument_for_number,
+        "extern g(x: f64)\ng(\"1\")" => vec![
+            InvalidConversion { from: Type::Str, to: Type::F64, context: "argument 1 of g".to_string() }
+        ]
+    );
+
+    type_error_test!(
+        can_reject_operators_on_str,
+        "def f(s: str) { s + 1; s == s; !s; -s }" => vec![
+            InvalidOperand { op: "+".to_string(), ty: Type::Str },
+            InvalidOperand { op: "==".to_string(), ty: Type::Str },
+            InvalidOperand { op: "!".to_string(), ty: Type::Str },
+            InvalidOperand { op: "-".to_string(), ty: Type::Str }
+        ]
+    );
+
+    elaboration_test!(
+        can_index_arrays_with_i64,
+        "def f(a: [f64], i: i64) { a[i] = 1; a[0] + len(a) }" => vec![
+            "def f(a: [f64], i: i64) -> f64 { a[i] = 1; a[i64(0)] + f64(len(a)) }"
+        ]
+    );
+
+    elaboration_test!(
+        can_convert_elements_to_f64,
+        "def f(n: i64, b: bool) -> [f64] [1, n, b]" => vec!["def f(n: i64, b: bool) -> [f64] [1, f64(n), f64(b)]"]
+    );
+
+    elaboration_test!(
+        can_call_builtin_functions,
+        "def f(a: [f64]) -> i64 push(a, len(a))" => vec!["def f(a: [f64]) -> i64 push(a, f64(len(a)))"]
+    );
+
+    type_error_test!(
+        can_reject_indexing_non_array,
+        "def f(x: f64) x[0]" => vec![InvalidOperand { op: "[]".to_string(), ty: Type::F64 }]
+    );
+
+    type_error_test!(
+        can_reject_f64_index,
+        "def f(a: [f64], x: f64) a[x]" => vec![
+            ImplicitConversion { from: Type::F64, to: Type::I64, context: "array index".to_string() }
+        ]
+    );
+
+    type_error_test!(
+        can_reject_operators_on_arrays,
+        "def f(a: [f64]) { a + 1; !a }" => vec![
+            InvalidOperand { op: "+".to_string(), ty: Type::Array },
+            InvalidOperand { op: "!".to_string(), ty: Type::Array }
+        ]
+    );
+
+    type_error_test!(
+        can_reject_str_element,
+        "[1, \"2\"]" => vec![
+            InvalidConversion { from: Type::Str, to: Type::F64, context: "element 2 of array".to_string() }
+        ]
+    );
+
+    type_error_test!(
+        can_reject_conversion_of_array,
+        "def f(a: [f64]) -> str str(a)" => vec![
+            InvalidConversion { from: Type::Array, to: Type::Str, context: "str(...)".to_string() }
+        ]
+    );
+
+    type_error_test!(
+        can_reject_implicit_narrowing_of_return_value,
+        "def f(x: f64) -> i64 x" => vec![
+            ImplicitConversion { from: Type::F64, to: Type::I64, context: "return value of f".to_string() }
+        ]
+    );
+
+    type_error_test!(
+        can_reject_implicit_narrowing_of_argument,
+        "extern g(n: i64)\ng(0.5)" => vec![
+            ImplicitConversion { from: Type::F64, to: Type::I64, context: "argument 1 of g".to_string() }
+        ]
+    );
+
+    type_error_test!(
+        can_reject_implicit_conversion_to_bool,
+        "def f(n: i64) -> bool n" => vec![
+            ImplicitConversion { from: Type::I64, to: Type::Bool, context: "return value of f".to_string() }
+        ]
+    );
+
+    type_error_test!(
+        can_reject_narrowing_assignment,
+        "def f(n: i64, x: f64) { n = x; n }" => vec![
+            ImplicitConversion { from: Type::F64, to: Type::I64, context: "assignment to n".to_string() }
+        ]
+    );
+
+    type_error_test!(
+        can_reject_conflicting_signature,
+        "extern f(x: i64)\ndef f(x: f64) x" => vec![
+            ConflictingSignature {
+                name: "f".to_string(),
+                expected: Signature { params: vec![Type::I64], return_type: Type::F64 },
+                found: Signature { params: vec![Type::F64], return_type: Type::F64 }
+            }
+        ]
+    );
+
+    elaboration_test!(
+        can_check_struct_constructor_and_fields,
+        "struct Point { x, n: i64 }\ndef f(p: Point) -> Point Point(p.n, 1)\nf(Point(1, 2)).x" => vec![
+            "struct Point { x: f64, n: i64 }",
+            "def f(p: Point) -> Point Point(f64(p.n), i64(1))",
+            "f(Point(1, i64(2))).x"
+        ]
+    );
+
+    elaboration_test!(
+        can_assign_to_nested_field,
+        "struct A { n: i64 }\nstruct B { a: A, b: bool }\ndef f(x: B) { x.a.n = x.b; x }" => vec![
+            "struct A { n: i64 }",
+            "struct B { a: A, b: bool }",
+            "def f(x: B) -> B { x.a.n = i64(x.b); x }"
+        ]
+    );
+
+    type_error_test!(
+        can_reject_unknown_field,
+        "struct Point { x, y }\ndef f(p: Point, x: f64) p.z + x.y" => vec![
+            UnknownField { ty: Type::Struct(StructName::new("Point")), field: "z".to_string() },
+            UnknownField { ty: Type::F64, field: "y".to_string() }
+        ]
+    );
+
+    type_error_test!(
+        can_reject_mismatched_structs,
+        "struct A { x }\nstruct B { x }\ndef f(a: A) -> B a\ndef g(a: A) a + 1" => vec![
+            InvalidConversion { from: Type::Struct(StructName::new("A")), to: Type::Struct(StructName::new("B")), context: "return value of f".to_string() },
+            InvalidOperand { op: "+".to_string(), ty: Type::Struct(StructName::new("A")) }
+        ]
+    );
+
+    elaboration_test!(
+        can_take_lambda_types_from_expected_function_type,
+        "def apply(f: fn(i64) -> i64, n: i64) -> i64 f(n)\napply(\\x -> x * 2, 3)\n\\x -> 1" => vec![
+            "def apply(f: fn(i64) -> i64, n: i64) -> i64 f(n)",
+            "apply(\\(x: i64): i64 -> x * i64(2), i64(3))",
+            "\\(x: f64): f64 -> 1"
+        ]
+    );
+
+    elaboration_test!(
+        can_use_function_as_value,
+        "def half(x: f64) -> f64 x / 2\ndef apply(f: fn(f64) -> f64) f(1)\napply(half)" => vec![
+            "def half(x: f64) -> f64 x / 2",
+            "def apply(f: fn(f64) -> f64) -> f64 f(1)",
+            "apply(half)"
+        ]
+    );
+
+    type_error_test!(
+        can_reject_calls_of_non_function_values,
+        "def f(g: fn(f64) -> f64, x: i64) { g(1, 2); x(1) }" => vec![
+            ArityMismatch { callee: "g".to_string(), ty: Type::Function(FunctionType::new(vec![Type::F64], Type::F64)), found: 2 },
+            NotCallable { name: "x".to_string(), ty: Type::I64 }
+        ]
+    );
+
+    type_error_test!(
+        can_reject_mismatched_function_types,
+        "def apply(f: fn(f64) -> f64) f(1)\ndef g(x: i64) -> i64 x\napply(g)" => vec![
+            InvalidConversion {
+                from: Type::Function(FunctionType::new(vec![Type::I64], Type::I64)),
+                to: Type::Function(FunctionType::new(vec![Type::F64], Type::F64)),
+                context: "argument 1 of apply".to_string()
+            }
+        ]
+    );
+
+    elaboration_test!(
+        can_check_consts_and_globals,
+        "const n: i64 = 2\nglobal scale = n\nglobal count: i64 = 1\ndef f(x: i64) -> i64 count = count + x * n\nscale * n" => vec![
+            "const n: i64 = i64(2)",
+            "global scale: i64 = n",
+            "global count: i64 = i64(1)",
+            "def f(x: i64) -> i64 count = count + x * n",
+            "scale * n"
+        ]
+    );
+
+    type_error_test!(
+        can_reject_global_value_of_other_type,
+        "global s: str = 1" => vec![
+            ImplicitConversion { from: Type::F64, to: Type::Str, context: "value of s".to_string() }
+        ]
+    );
+
+    #[test]
+    fn can_describe_implicit_conversion() {
+        let got = check_source("def f(x: f64) -> i64 x").unwrap_err()[0].to_string();
+        assert_eq!(got, "Cannot implicitly convert f64 to i64 in return value of f, use i64(...) to convert explicitly.");
+    }
+}
@@ -0,0 +1,229 @@
+#[cfg(test)]
+mod tests {
+    use kaleidoscope::analysis::type_check::TypeChecker;
+    use kaleidoscope::codegen::ir_generator::IRGenerator;
+    use kaleidoscope::codegen::jit::Jit;
+    use kaleidoscope::codegen::llvm_generator::LLVMGeneratorContext;
+    use kaleidoscope::parse::parser::*;
+    use kaleidoscope::parse::resolver::Resolver;
+
+    fn generate(context: &mut LLVMGeneratorContext, checker: &mut TypeChecker, src: &str) {
+        let mut parser = Parser::new(src);
+        while let Some(item) = parser.next_spanned() {
+            unsafe {
+                let _ = checker.check(&item.unwrap().ast).unwrap().generate(context);
+            }
+        }
+    }
+
+    // the functions of the source, compiled by the JIT
+    fn compile(src: &str) -> (LLVMGeneratorContext, Jit) {
+        let mut context = LLVMGeneratorContext::new();
+        generate(&mut context, &mut TypeChecker::new(), src);
+        let mut jit = Jit::new();
+        jit.compile(&context).unwrap();
+        (context, jit)
+    }
+
+    unsafe fn call(jit: &Jit, name: &str) -> i64 {
+        let function: extern "C" fn() -> i64 = std::mem::transmute(jit.function_address(name).unwrap());
+        function()
+    }
+
+    // runs a function taking and returning an i64
+    fn run(src: &str, name: &str, arg: i64) -> i64 {
+        let (_context, jit) = compile(src);
+        unsafe {
+            let function: extern "C" fn(i64) -> i64 = std::mem::transmute(jit.function_address(name).unwrap());
+            function(arg)
+        }
+    }
+
+    // like run, with the prelude linked before the source, whose uses of it are renamed
+    fn run_with_prelude(src: &str, name: &str, arg: i64) -> i64 {
+        let mut context = LLVMGeneratorContext::new();
+        let mut checker = TypeChecker::new();
+        let mut resolver = Resolver::new(Vec::new());
+        let prelude = resolver.load_prelude().unwrap();
+        context.link_module(&prelude.namespace, |context| unsafe {
+            for item in &prelude.items {
+                let _ = checker.check(&item.ast).unwrap().generate(context);
+            }
+        }).unwrap();
+        let mut parser = Parser::new(src);
+        while let Some(item) = parser.next_spanned() {
+            let mut ast = item.unwrap().ast;
+            resolver.use_prelude(&mut ast);
+            unsafe {
+                let _ = checker.check(&ast).unwrap().generate(&mut context);
+            }
+        }
+        let mut jit = Jit::new();
+        jit.compile(&context).unwrap();
+        unsafe {
+            let function: extern "C" fn(i64) -> i64 = std::mem::transmute(jit.function_address(name).unwrap());
+            function(arg)
+        }
+    }
+
+    macro_rules! jit_test {
+        ($name:ident, $src:expr, $function:expr, $arg:expr => $expected:expr) => {
+            #[test]
+            fn $name() {
+                assert_eq!(run($src, $function, $arg), $expected);
+            }
+        }
+    }
+
+    macro_rules! prelude_test {
+        ($name:ident, $src:expr, $arg:expr => $expected:expr) => {
+            #[test]
+            fn $name() {
+                assert_eq!(run_with_prelude($src, "run", $arg), $expected);
+            }
+        }
+    }
+
+    jit_test!(
+        can_run_function,
+        "def square(x: i64) -> i64 x * x", "square", 12 => 144
+    );
+
+    // test threads have a stack of 2 MiB, far less than 10 million frames
+    jit_test!(
+        can_run_self_tail_recursion_in_constant_stack,
+        "def count(n: i64) -> bool n == 0 || count(n - 1)\ndef run(n: i64) -> i64 i64(count(n))",
+        "run", 10_000_000 => 1
+    );
+
+    jit_test!(
+        can_run_mutual_tail_recursion_in_constant_stack,
+        "extern odd(n: i64) -> bool\ndef even(n: i64) -> bool n == 0 || { n = n - 1; odd(n) }\n\
+         def odd(n: i64) -> bool n != 0 && even(n - 1)\ndef run(n: i64) -> i64 i64(even(n))",
+        "run", 10_000_001 => 0
+    );
+
+    // the arguments of a self tail call are all computed before the parameters are overwritten
+    jit_test!(
+        can_run_self_tail_recursion_passing_parameters_to_each_other,
+        "global result: i64 = 0\ndef fib(n: i64, a: i64, b: i64) -> bool (n == 0 && (result = a) == a) || fib(n - 1, b, a + b)\n\
+         def run(n: i64) -> i64 { fib(n, 0, 1); result }",
+        "run", 50 => 12_586_269_025
+    );
+
+    jit_test!(
+        can_run_tail_call_of_function_value,
+        "def apply(f: fn(i64) -> i64, n: i64) -> i64 f(n)\ndef run(k: i64) -> i64 apply(\\(x: i64) -> x + k, 1)",
+        "run", 41 => 42
+    );
+
+    jit_test!(
+        can_call_function_values_of_consts_and_captures,
+        "def double(x: i64) -> i64 x * 2\nconst twice: fn(i64) -> i64 = double\n\
+         def apply(f: fn(i64) -> i64, n: i64) -> i64 f(n)\n\
+         def compose(f: fn(i64) -> i64, n: i64) -> i64 apply(\\(x: i64) -> f(twice(x)), n)\n\
+         def run(k: i64) -> i64 compose(\\(x: i64) -> x + k, 10)",
+        "run", 1 => 21
+    );
+
+    jit_test!(
+        can_run_function_with_constants_and_globals,
+        "const scale: i64 = 2 * 3\nglobal total: i64 = scale + 1\ndef add(n: i64) -> i64 { total = total + n * scale; total }",
+        "add", 5 => 37
+    );
+
+    #[test]
+    fn can_keep_globals_between_compilations() {
+        let mut context = LLVMGeneratorContext::new();
+        let mut checker = TypeChecker::new();
+        let mut jit = Jit::new();
+        generate(&mut context, &mut checker, "global counter: i64 = 40\ndef bump() -> i64 counter = counter + 1");
+        jit.compile(&context).unwrap();
+        unsafe {
+            assert_eq!(call(&jit, "bump"), 41);
+        }
+
+        generate(&mut context, &mut checker, "global step: i64 = 10\ndef jump() -> i64 counter = counter + step");
+        jit.compile(&context).unwrap();
+        unsafe {
+            assert_eq!(call(&jit, "bump"), 42);
+            assert_eq!(call(&jit, "jump"), 52);
+        }
+        jit.compile(&context).unwrap();
+        unsafe {
+            assert_eq!(call(&jit, "jump"), 62);
+        }
+    }
+
+    #[test]
+    fn can_report_unknown_function() {
+        let (_context, jit) = compile("def f(x) x");
+        assert_eq!(jit.function_address("g"), None);
+    }
+
+    #[test]
+    fn can_run_function_of_linked_module() {
+        let mut context = LLVMGeneratorContext::new();
+        let mut checker = TypeChecker::new();
+        context.link_module("counter", |context| {
+            generate(context, &mut checker, "global counter::value: i64 = 40
+def counter::next() -> i64 counter::value = counter::value + 1");
+        }).unwrap();
+        generate(&mut context, &mut checker, "def run() -> i64 { counter::next(); counter::next() }");
+        let mut jit = Jit::new();
+        jit.compile(&context).unwrap();
+        unsafe {
+            assert_eq!(call(&jit, "run"), 42);
+            assert_eq!(call(&jit, "counter::next"), 43);
+        }
+    }
+
+    prelude_test!(
+        can_run_prelude_abs_min_max_and_clamp,
+        "def run(n: i64) -> i64 i64(abs(f64(n)) + min(1, 2) + max(1, 2) * 10 + clamp(f64(n), -3, 3) * 100)",
+        -5 => -274
+    );
+
+    prelude_test!(
+        can_run_prelude_pow,
+        "def run(n: i64) -> i64 i64(pow(2, n) + pow(2, -n) * 1024 + pow(-3, 3))",
+        10 => 1024 + 1 - 27
+    );
+
+    // Park-Miller from a state of 8: 8 * 48271 / 2147483647 is 0.000179823
+    prelude_test!(
+        can_run_prelude_random_from_seed,
+        "def run(seed: i64) -> i64 { random_seed(seed); i64(random() * 1000000) }",
+        7 => 179
+    );
+
+    prelude_test!(
+        can_run_prelude_extern_of_c_math_library,
+        "def run(n: i64) -> i64 i64(sqrt(f64(n)) + exp(0) + log(1) + cos(0) + sin(0))",
+        81 => 11
+    );
+
+    prelude_test!(
+        can_run_declaration_hiding_prelude_one,
+        "def abs(x) x * 2\ndef run(n: i64) -> i64 i64(abs(f64(n)) + prelude::abs(f64(n)) * 10)",
+        -2 => 16
+    );
+
+    // a struct of three f64 is returned through a hidden pointer, which only the wrapper passes
+    #[test]
+    fn can_run_function_returning_struct_through_wrapper() {
+        let mut context = LLVMGeneratorContext::new();
+        generate(&mut context, &mut TypeChecker::new(), "global made: i64 = 0\nstruct P { x, y, z }\n\
+            def make() -> P { made = made + 1; P(1, 2, 3) }\ndef count() -> i64 made");
+        let wrapper = context.build_discarding_wrapper("make").unwrap();
+        assert_eq!(context.build_discarding_wrapper("unknown"), None);
+        let mut jit = Jit::new();
+        jit.compile(&context).unwrap();
+        unsafe {
+            let run: extern "C" fn() = std::mem::transmute(jit.function_address(&wrapper).unwrap());
+            run();
+            run();
+            assert_eq!(call(&jit, "count"), 2);
+        }
+    }
+}
@@ -7,9 +7,26 @@ mod tests {
     use kaleidoscope::parse::parser::*;
     use kaleidoscope::codegen::llvm_generator::*;
     use kaleidoscope::syntax::ast::GenericAst;
+    use kaleidoscope::analysis::type_check::TypeChecker;
 
+    // codegen expects the conversions made explicit by the type checker
     fn parse_source_to_ast(src: &str) -> GenericAst {
-        Parser::new(src).build_next_ast().unwrap()
+        check_source(&mut TypeChecker::new(), src)
+    }
+
+    fn check_source(checker: &mut TypeChecker, src: &str) -> GenericAst {
+        checker.check(&Parser::new(src).build_next_ast().unwrap()).unwrap()
+    }
+
+    fn generate_module(srcs: &[&str]) -> String {
+        let mut llvm_context = create_code_generator();
+        let mut checker = TypeChecker::new();
+        for src in srcs {
+            unsafe {
+                let _ = check_source(&mut checker, src).generate(&mut llvm_context);
+            }
+        }
+        llvm_context.get_module_as_string()
     }
 
     fn create_code_generator() -> LLVMGeneratorContext {
@@ -67,25 +84,260 @@ mod tests {
         "###
     );
 
+    llvm_ir_generation_module_test!(
+        generate_function_with_unary_operators,
+        r###"
+            def foo(a, b) -a * !b
+        "###
+    );
+
+    llvm_ir_generation_module_test!(
+        generate_function_with_power_and_assignment,
+        r###"
+            def foo(a, b) a = b ^ 2 + a
+        "###
+    );
+
+    llvm_ir_generation_module_test!(
+        generate_function_with_comparisons,
+        r###"
+            def foo(a, b) (a <= b) + (a >= b) + (a == b) + (a != b) + a % b
+        "###
+    );
+
+    llvm_ir_generation_module_test!(
+        generate_function_with_short_circuit_operators,
+        r###"
+            def foo(a, b, c) a && b || c
+        "###
+    );
+
     #[test]
-    fn generate_multi_function_ir() {
-        let mut llvm_context = create_code_generator();
-        let ast_foo = parse_source_to_ast(
+    fn generate_function_with_block_body() {
+        let module = generate_module(&[
+            "extern print(x)",
             r###"
-                def foo(a, b) a*b
+                def foo(a) {
+                    print(a);
+                    a = a * 2;
+                    a + 1
+                }
             "###
-        );
+        ]);
+        println!("Generated LLVM IR Module: {}", module);
+    }
 
-        let ast_bar = parse_source_to_ast(
-            r###"
-                def bar(a, b, c) c*foo(a, b)
-            "###
-        );
+    #[test]
+    fn generate_multi_function_ir() {
+        let module = generate_module(&[
+            "def foo(a, b) a*b",
+            "def bar(a, b, c) c*foo(a, b)"
+        ]);
+        println!("Generated LLVM IR Module: {}", module);
+    }
+
+    macro_rules! llvm_ir_contains_test {
+        ($name:ident, [$($src:expr),+], [$($expected:expr),+]) => {
+            #[test]
+            fn $name() {
+                let module = generate_module(&[$($src),+]);
+                $(
+                    assert!(module.contains($expected), "expected {:?} in:\n{}", $expected, module);
+                )+
+            }
+        }
+    }
+
+    llvm_ir_contains_test!(
+        generate_typed_integer_function,
+        ["def div(a: i64, b: i64) -> i64 a / b % 3"],
+        ["define i64 @div(i64 %a, i64 %b)", "sdiv i64", "srem i64 %", "ret i64"]
+    );
+
+    llvm_ir_contains_test!(
+        generate_untyped_function_as_double,
+        ["def half(x) x / 2"],
+        ["define double @half(double %x)", "fdiv double"]
+    );
+
+    llvm_ir_contains_test!(
+        generate_bool_comparison,
+        ["def less(a: i64, b: f64) -> bool a < b"],
+        ["define i1 @less(i64 %a, double %b)", "sitofp i64", "fcmp olt double", "ret i1"]
+    );
+
+    llvm_ir_contains_test!(
+        generate_explicit_conversions,
+        ["def trunc(x: f64) -> i64 i64(x)", "def truthy(x: i64) -> bool bool(x)"],
+        ["fptosi double", "icmp ne i64"]
+    );
 
+    llvm_ir_contains_test!(
+        generate_call_with_converted_arguments,
+        ["extern sqrt(x: f64) -> f64", "def root(n: i64) -> f64 sqrt(n)"],
+        ["declare double @sqrt(double)", "sitofp i64 %"]
+    );
+
+    llvm_ir_contains_test!(
+        generate_string_constants,
+        ["extern puts(s: str)", r#"def f() { puts("hi\n"); puts("hi\n") }"#],
+        ["@.str = private unnamed_addr constant [4 x i8] c\"hi\\0A\\00\"", "declare double @puts(i8*)"]
+    );
+
+    llvm_ir_contains_test!(
+        generate_print_runtime_functions,
+        ["extern print(s: str) -> i64", "extern println(s: str)"],
+        ["define linkonce_odr i64 @print(i8* %s)", "define linkonce_odr double @println(i8* %s)", "declare i32 @printf(i8*, ...)", "ret i64 0"]
+    );
+
+    llvm_ir_contains_test!(
+        generate_user_print_as_declaration,
+        ["extern print(x)"],
+        ["declare double @print(double)"]
+    );
+
+    llvm_ir_contains_test!(
+        generate_conversions_to_str,
+        ["def f(n: i64, x: f64, b: bool) -> str { str(n); str(x); str(b) }"],
+        ["call i8* @malloc(i64 32)", "c\"%lld\\00\"", "c\"%g\\00\"", "select i1 %b"]
+    );
+
+    llvm_ir_contains_test!(
+        generate_inferred_integer_function,
+        ["extern next(n: i64) -> i64", "def twice(n) next(next(n)) * 2"],
+        ["define i64 @twice(i64 %n)", "mul i64"]
+    );
+
+    llvm_ir_contains_test!(
+        generate_array_literal,
+        ["def f(x: f64) -> [f64] [x, 2]"],
+        ["%array = type { i64, i64, double* }", "define %array* @f(double %x)", "call %array* @array.new(i64 2)", "store double %x"]
+    );
+
+    llvm_ir_contains_test!(
+        generate_checked_indexing,
+        ["def f(a: [f64], i: i64) { a[i] = a[i + 1]; len(a) }"],
+        ["call double* @array.at(%array* %a", "icmp ult i64 %index", "call void @exit(i32 1)", "unreachable"]
+    );
+
+    llvm_ir_contains_test!(
+        generate_push_with_growth,
+        ["def f(a: [f64]) -> i64 push(a, 1)"],
+        ["call i64 @array.push(%array* %a2, double 1.000000e+00)", "call i8* @realloc(i8*"]
+    );
+
+    llvm_ir_contains_test!(
+        generate_struct_constructor,
+        ["struct Point { x, n: i64 }", "Point(1.5, 2)"],
+        ["%struct.Point = type { double, i64 }", "define %struct.Point @Point(double %x, i64 %n)",
+         "insertvalue %struct.Point undef, double %x, 0", "ret %struct.Point"]
+    );
+
+    llvm_ir_contains_test!(
+        generate_field_access_and_assignment,
+        ["struct Point { x, y }", "def f(p: Point) { p.y = p.x; p }"],
+        ["define %struct.Point @f(%struct.Point %p)", "extractvalue %struct.Point", "insertvalue %struct.Point",
+         "store %struct.Point"]
+    );
+
+    llvm_ir_contains_test!(
+        generate_lambda_with_captured_variable,
+        ["def adder(k: i64) \\(x: i64) -> x + k"],
+        ["define { i64 (i8*, i64)*, i8* } @adder(i64 %k)", "call i8* @malloc(i64 ptrtoint ({ i64 }* getelementptr ({ i64 }, { i64 }* null, i32 1) to i64))",
+         "define i64 @adder.lambda(i8* %environment, i64 %x)", "getelementptr inbounds { i64 }, { i64 }* %environmenttmp, i32 0, i32 0"]
+    );
+
+    llvm_ir_contains_test!(
+        generate_call_of_function_value,
+        ["def square(x) x * x", "def apply(f: fn(f64) -> f64) f(2)", "apply(square)"],
+        ["extractvalue { double (i8*, double)*, i8* } %f2, 0", "call double %functiontmp(i8* %environmenttmp, double 2.000000e+00)",
+         "define linkonce_odr double @square.closure(i8* %environment, double %x)", "@square.closure, i8* null }"]
+    );
+
+    // a self tail call is a jump, which runs in constant stack without optimizations
+    #[test]
+    fn generate_self_tail_call() {
+        let module = generate_module(&["def count(n: i64) -> bool n == 0 || count(n - 1)"]);
+
+        for expected in ["entry:\n  %n1 = alloca i64, align 8\n  store i64 %n, i64* %n1, align 4\n  br label %tailrecurse",
+                         "store i64 %subtmp, i64* %n1, align 4\n  br label %tailrecurse", "phi i1 [ true, %tailrecurse ]"] {
+            assert!(module.contains(expected), "expected {:?} in:\n{}", expected, module);
+        }
+        assert!(!module.contains("call i1 @count"), "{}", module);
+    }
+
+    llvm_ir_contains_test!(
+        generate_tail_call_in_block,
+        ["extern g(x) -> f64", "def f(x) { g(x); g(x + 1) }"],
+        ["%calltmp = call double @g(double %x2)", "%calltmp4 = tail call double @g(double %addtmp)\n  ret double %calltmp4"]
+    );
+
+    llvm_ir_contains_test!(
+        generate_call_with_converted_result_without_tail,
+        ["def f(x: i64) -> i64 x", "def g(x: i64) -> f64 f(x)"],
+        ["%calltmp = call i64 @f(i64 %x2)"]
+    );
+
+    llvm_ir_contains_test!(
+        generate_folded_constants,
+        ["const pi = 3.14159", "const tau = 2 * pi", "def area(r) pi * r * r"],
+        ["@pi = constant double 3.141590e+00", "@tau = constant double 6.283180e+00", "%multmp = fmul double 3.141590e+00, %r2"]
+    );
+
+    llvm_ir_contains_test!(
+        generate_global_variable,
+        ["global counter: i64 = 1 + 1", "def bump(n: i64) -> i64 counter = counter + n"],
+        ["@counter = global i64 2", "%counter = load i64, i64* @counter", "store i64 %addtmp, i64* @counter"]
+    );
+
+    llvm_ir_contains_test!(
+        generate_global_function_value,
+        ["def square(x) x * x", "global f = square", "def apply() f(3)"],
+        ["@f = global { double (i8*, double)*, i8* } { double (i8*, double)* @square.closure, i8* null }",
+         "%f = load { double (i8*, double)*, i8* }, { double (i8*, double)*, i8* }* @f"]
+    );
+
+    #[test]
+    fn generate_linked_module() {
+        let mut llvm_context = LLVMGeneratorContext::with_module_name("main");
+        let mut checker = TypeChecker::new();
+        let mut generate = |llvm_context: &mut LLVMGeneratorContext, src: &str| unsafe {
+            let _ = check_source(&mut checker, src).generate(llvm_context);
+        };
+        generate(&mut llvm_context, "extern println(s: str)");
+        llvm_context.link_module("math", |llvm_context| {
+            for src in ["extern println(s: str)", "global math::count: i64 = 1", r#"const math::name = "math""#,
+                        "def math::show(x) println(str(x))"] {
+                generate(llvm_context, src);
+            }
+        }).unwrap();
+        generate(&mut llvm_context, "def f() { math::count = math::count + 1; println(math::name); math::show(f64(math::count)) }");
+
+        let module = llvm_context.get_module_as_string();
+        // the string of the const was linked with the module, its uses are folded to the linked string
+        for expected in ["; ModuleID = 'main'", "@\"math::count\" = global i64 1", "define double @\"math::show\"(double %x)",
+                         "store i64 %addtmp, i64* @\"math::count\"", "define linkonce_odr double @println(i8* %s)",
+                         "@\"math::name\" = constant i8* getelementptr inbounds ([5 x i8], [5 x i8]* @.str.1",
+                         "call double @println(i8* getelementptr inbounds ([5 x i8], [5 x i8]* @.str.1"] {
+            assert!(module.contains(expected), "expected {:?} in:\n{}", expected, module);
+        }
+        assert_eq!(module.matches("define linkonce_odr double @println").count(), 1, "println defined twice in:\n{}", module);
+    }
+
+    #[test]
+    fn generate_call_to_extern_declared_by_linked_module() {
+        let mut llvm_context = LLVMGeneratorContext::new();
+        let mut checker = TypeChecker::new();
+        llvm_context.link_module("math", |llvm_context| unsafe {
+            let _ = check_source(&mut checker, "extern sqrt(x)").generate(llvm_context);
+        }).unwrap();
         unsafe {
-            let _ = ast_foo.generate(&mut llvm_context);
-            let _ = ast_bar.generate(&mut llvm_context);
-            println!("Generated LLVM IR Module: {}", llvm_context.get_module_as_string());
+            let _ = check_source(&mut checker, "def f() sqrt(2)").generate(&mut llvm_context);
+        }
+
+        let module = llvm_context.get_module_as_string();
+        for expected in ["declare double @sqrt(double)", "call double @sqrt(double 2.000000e+00)"] {
+            assert!(module.contains(expected), "expected {:?} in:\n{}", expected, module);
         }
     }
-}
\ No newline at end of file
+}
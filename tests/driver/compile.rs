@@ -0,0 +1,111 @@
+#[cfg(test)]
+mod tests {
+    use std::ffi::{CStr, CString};
+    use std::io::Write;
+    use std::process::{Command, Output, Stdio};
+
+    use llvm_sys::analysis::{LLVMVerifierFailureAction, LLVMVerifyModule};
+    use llvm_sys::core::*;
+    use llvm_sys::ir_reader::LLVMParseIRInContext;
+
+    // runs the compiler over a source given on stdin
+    fn run(args: &[&str], src: &str) -> Output {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_kaleidoscope"))
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(src.as_bytes()).unwrap();
+        child.wait_with_output().unwrap()
+    }
+
+    // parses the printed module again and verifies it, returning the names of the functions it defines
+    fn verify_ir(ir: &str) -> Vec<String> {
+        unsafe {
+            let context = LLVMContextCreate();
+            let name = CString::new("compiled").unwrap();
+            let buffer = LLVMCreateMemoryBufferWithMemoryRangeCopy(ir.as_ptr() as *const i8, ir.len(), name.as_ptr());
+            let mut module = std::ptr::null_mut();
+            let mut message = std::ptr::null_mut();
+            assert_eq!(LLVMParseIRInContext(context, buffer, &mut module, &mut message), 0,
+                       "{}\n{}", CStr::from_ptr(message).to_string_lossy(), ir);
+            assert_eq!(LLVMVerifyModule(module, LLVMVerifierFailureAction::LLVMReturnStatusAction, &mut message), 0,
+                       "{}\n{}", CStr::from_ptr(message).to_string_lossy(), ir);
+            LLVMDisposeMessage(message);
+
+            let mut defined = Vec::new();
+            let mut function = LLVMGetFirstFunction(module);
+            while !function.is_null() {
+                if LLVMIsDeclaration(function) == 0 {
+                    let mut length = 0;
+                    defined.push(CStr::from_ptr(LLVMGetValueName2(function, &mut length)).to_str().unwrap().to_string());
+                }
+                function = LLVMGetNextFunction(function);
+            }
+            LLVMDisposeModule(module);
+            LLVMContextDispose(context);
+            defined
+        }
+    }
+
+    macro_rules! compile_test {
+        ($name:ident, $src:expr => $defined:expr) => {
+            #[test]
+            fn $name() {
+                let output = run(&["--no-prelude", "-"], $src);
+                assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+                let defined: Vec<&str> = $defined;
+                assert_eq!(verify_ir(&String::from_utf8(output.stdout).unwrap()), defined);
+            }
+        }
+    }
+
+    macro_rules! compile_error_test {
+        ($name:ident, $args:expr, $src:expr) => {
+            #[test]
+            fn $name() {
+                let output = run(&$args, $src);
+                assert_eq!(output.status.code(), Some(1));
+                assert!(output.stdout.is_empty(), "{}", String::from_utf8_lossy(&output.stdout));
+                assert!(!output.stderr.is_empty());
+            }
+        }
+    }
+
+    compile_test!(
+        can_compile_top_level_call_after_function,
+        "def f(x) x + 1\nf(2)\nf(3)" => vec!["f", "expression.1", "expression.2"]
+    );
+
+    compile_test!(
+        can_compile_top_level_call_of_extern,
+        "extern g(x)\ng(1)" => vec!["expression.1"]
+    );
+
+    compile_test!(
+        can_compile_top_level_short_circuit,
+        "1 < 2 && 3 < 4" => vec!["expression.1"]
+    );
+
+    #[test]
+    fn can_compile_with_prelude() {
+        let output = run(&["-"], "def main() -> i64 { show(abs(-2)); 0 }");
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let defined = verify_ir(&String::from_utf8(output.stdout).unwrap());
+        for function in ["prelude::abs", "prelude::show", "main"] {
+            assert!(defined.iter().any(|name| name == function), "{} not in {:?}", function, defined);
+        }
+    }
+
+    compile_error_test!(can_fail_on_parse_error, ["--no-prelude", "-"], "def f(x");
+
+    compile_error_test!(can_fail_on_check_error, ["--no-prelude", "-"], "def f(x) y\ndef g() 1");
+
+    compile_error_test!(can_fail_on_missing_source, ["--no-prelude", "missing.kal"], "");
+
+    compile_error_test!(can_fail_emit_ast_on_parse_error, ["emit-ast", "-"], "def f(x");
+
+    compile_error_test!(can_fail_fmt_on_repeated_stdin, ["fmt", "-", "-"], "1 + 2");
+}
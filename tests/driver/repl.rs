@@ -0,0 +1,36 @@
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::process::{Command, Output, Stdio};
+
+    // runs the REPL over lines given on stdin, which have to end with quit
+    fn run_repl(lines: &str) -> Output {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_kaleidoscope"))
+            .arg("--no-prelude")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(lines.as_bytes()).unwrap();
+        child.wait_with_output().unwrap()
+    }
+
+    #[test]
+    fn can_forget_def_that_failed_checks() {
+        let output = run_repl("def f(x) y\nf(1)\nquit\n");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        assert!(output.status.success(), "{}", stderr);
+        assert!(stderr.contains("Unknown variable 'y' in function f."), "{}", stderr);
+        assert!(stderr.contains("Unknown function 'f'"), "{}", stderr);
+    }
+
+    #[test]
+    fn can_define_again_def_that_failed_checks() {
+        let output = run_repl("def f(x: i64) -> i64 y\ndef f(x: i64) -> i64 x + 1\nf(2)\nquit\n");
+
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        assert!(String::from_utf8_lossy(&output.stdout).lines().any(|line| line.ends_with("3")));
+    }
+}
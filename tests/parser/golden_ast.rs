@@ -0,0 +1,88 @@
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use kaleidoscope::parse::parser::*;
+    use kaleidoscope::syntax::ast::SpannedAst;
+
+    /*
+        Golden AST tests: tests/golden/<name>.ks is parsed and compared with the items stored in
+        tests/golden/<name>.json, the same JSON `kaleidoscope emit-ast` prints.
+        Run the tests with UPDATE_GOLDEN=1 to (re)write the JSON files from the current parser,
+        then review the diff before committing it.
+    */
+    macro_rules! golden_ast_test {
+        ($name:ident, $file:expr) => {
+            #[test]
+            fn $name() {
+                check_golden($file);
+            }
+        }
+    }
+
+    fn golden_path(file: &str, extension: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(format!("{}.{}", file, extension))
+    }
+
+    fn parse_spanned(src: &str) -> Vec<SpannedAst> {
+        let mut parser = Parser::new(src);
+        let mut items = Vec::new();
+        while let Some(item) = parser.next_spanned() {
+            items.push(item.unwrap());
+        }
+        items
+    }
+
+    fn check_golden(file: &str) {
+        let src = fs::read_to_string(golden_path(file, "ks")).unwrap();
+        let got = parse_spanned(&src);
+
+        let json_path = golden_path(file, "json");
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            fs::write(&json_path, serde_json::to_string_pretty(&got).unwrap() + "\n").unwrap();
+            return;
+        }
+        let should_be: Vec<SpannedAst> = serde_json::from_str(&fs::read_to_string(&json_path).unwrap()).unwrap();
+        assert_eq!(got, should_be);
+    }
+
+    golden_ast_test!(can_parse_precedence_changing_arithmetic_expression, "precedence_changing_arithmetic_expression");
+    golden_ast_test!(can_parse_parenthesis_containing_arithmetic_expression, "parenthesis_containing_arithmetic_expression");
+    golden_ast_test!(can_parse_compound_b2b_statements, "compound_b2b_statements");
+    golden_ast_test!(can_parse_block_function_body, "block_function_body");
+
+    #[test]
+    fn can_span_items_without_terminators_and_trivia() {
+        let items = parse_spanned("  x + 1; # comment\n;; def f(a) a\n");
+
+        assert_eq!(&"  x + 1; # comment\n;; def f(a) a\n"[items[0].span.start..items[0].span.end], "x + 1");
+        assert_eq!(items[1].span.start, 22);
+        assert_eq!(items[1].span.end, 32);
+    }
+
+    #[test]
+    fn can_span_expressions_after_their_children() {
+        let src = "def f(a) -g(a[0], (a.x))";
+        let items = parse_spanned(src);
+
+        let spanned: Vec<&str> = items[0].spans.iter().map(|span| &src[span.start..span.end]).collect();
+        assert_eq!(spanned, vec!["a", "0", "a[0]", "a", "a.x", "g(a[0], (a.x))", "-g(a[0], (a.x))"]);
+    }
+
+    #[test]
+    fn can_round_trip_json() {
+        let items = parse_spanned("extern sin(x)\ndef f(x) { x = -x; sin(x) ^ 2 }\nf(1) && !f(2)");
+        let json = serde_json::to_string(&items).unwrap();
+
+        assert_eq!(serde_json::from_str::<Vec<SpannedAst>>(&json).unwrap(), items);
+    }
+
+    #[test]
+    fn can_round_trip_binary() {
+        let items = parse_spanned("extern sin(x)\ndef f(x) { x = -x; sin(x) ^ 2 }\nf(1) && !f(2)");
+        let bytes = postcard::to_allocvec(&items).unwrap();
+
+        assert_eq!(postcard::from_bytes::<Vec<SpannedAst>>(&bytes).unwrap(), items);
+    }
+}
@@ -1,8 +1,25 @@
 #[cfg(test)]
 mod tests {
+    use std::io::Read;
+
     use kaleidoscope::parse::lexer::*;
     use kaleidoscope::parse::token::*;
 
+    // hands out the source a few bytes at a time, splitting lines and multi-byte chars
+    struct ChunkedReader<'a> {
+        data: &'a [u8],
+        chunk_size: usize
+    }
+
+    impl<'a> Read for ChunkedReader<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let count = self.chunk_size.min(buf.len()).min(self.data.len());
+            buf[..count].copy_from_slice(&self.data[..count]);
+            self.data = &self.data[count..];
+            Ok(count)
+        }
+    }
+
     macro_rules! single_tokenization_test {
         ($name:ident, $src:expr => $should_be:expr) => {
             #[test]
@@ -19,18 +36,204 @@ mod tests {
     single_tokenization_test!(can_tokenize_empty, "" => Token::TokEof);
     single_tokenization_test!(can_tokenize_open_parenthesis, " ( " => Token::TokSymbol('('));
     single_tokenization_test!(can_tokenize_close_parenthesis, " ) " => Token::TokSymbol(')'));
-    single_tokenization_test!(can_tokenize_op_add, " + " => Token::TokSymbol('+'));
-    single_tokenization_test!(can_tokenize_op_sub, " - " => Token::TokSymbol('-'));
-    single_tokenization_test!(can_tokenize_op_mul, " * " => Token::TokSymbol('*'));
-    single_tokenization_test!(can_tokenize_op_div, " / " => Token::TokSymbol('/'));
-    single_tokenization_test!(can_tokenize_op_le, " < " => Token::TokSymbol('<'));
-    single_tokenization_test!(can_tokenize_op_ge, " > " => Token::TokSymbol('>'));
+    single_tokenization_test!(can_tokenize_open_bracket, " [ " => Token::TokSymbol('['));
+    single_tokenization_test!(can_tokenize_close_bracket, " ] " => Token::TokSymbol(']'));
+    single_tokenization_test!(can_tokenize_dot, " . " => Token::TokSymbol('.'));
+    single_tokenization_test!(can_tokenize_backslash, " \\ " => Token::TokSymbol('\\'));
+    single_tokenization_test!(can_tokenize_op_add, " + " => Token::TokOperator("+".to_string()));
+    single_tokenization_test!(can_tokenize_op_sub, " - " => Token::TokOperator("-".to_string()));
+    single_tokenization_test!(can_tokenize_op_mul, " * " => Token::TokOperator("*".to_string()));
+    single_tokenization_test!(can_tokenize_op_div, " / " => Token::TokOperator("/".to_string()));
+    single_tokenization_test!(can_tokenize_op_le, " < " => Token::TokOperator("<".to_string()));
+    single_tokenization_test!(can_tokenize_op_ge, " > " => Token::TokOperator(">".to_string()));
+    single_tokenization_test!(can_tokenize_op_mod, " % " => Token::TokOperator("%".to_string()));
+    single_tokenization_test!(can_tokenize_op_less_equal, " <= " => Token::TokOperator("<=".to_string()));
+    single_tokenization_test!(can_tokenize_op_greater_equal, " >= " => Token::TokOperator(">=".to_string()));
+    single_tokenization_test!(can_tokenize_op_equal, " == " => Token::TokOperator("==".to_string()));
+    single_tokenization_test!(can_tokenize_op_not_equal, " != " => Token::TokOperator("!=".to_string()));
+    single_tokenization_test!(can_tokenize_op_and, " && " => Token::TokOperator("&&".to_string()));
+    single_tokenization_test!(can_tokenize_op_or, " || " => Token::TokOperator("||".to_string()));
+    single_tokenization_test!(can_tokenize_op_assign, " = " => Token::TokOperator("=".to_string()));
+    single_tokenization_test!(can_tokenize_op_not, " !x " => Token::TokOperator("!".to_string()));
+    single_tokenization_test!(can_reject_single_ampersand, " & " => Token::TokError(LexError::UnexpectedCharacter('&')));
     single_tokenization_test!(can_tokenize_op_comma, " , " => Token::TokSymbol(','));
     single_tokenization_test!(can_tokenize_eof, "  " => Token::TokEof);
     single_tokenization_test!(can_tokenize_float, "   1.6   " => Token::TokNumber(1.6));
+    single_tokenization_test!(can_tokenize_integer, " 42 " => Token::TokNumber(42.0));
+    single_tokenization_test!(can_tokenize_float_without_integral, " .5 " => Token::TokNumber(0.5));
+    single_tokenization_test!(can_tokenize_exponent, " 1e3 " => Token::TokNumber(1000.0));
+    single_tokenization_test!(can_tokenize_negative_exponent, " 1e-3 " => Token::TokNumber(0.001));
+    single_tokenization_test!(can_tokenize_signed_exponent_float, " 2.5E+2 " => Token::TokNumber(250.0));
+    single_tokenization_test!(can_tokenize_hex, " 0x1F " => Token::TokNumber(31.0));
+    single_tokenization_test!(can_tokenize_binary, " 0b1010 " => Token::TokNumber(10.0));
+    single_tokenization_test!(can_tokenize_digit_separators, " 1_000_000 " => Token::TokNumber(1_000_000.0));
+    single_tokenization_test!(can_tokenize_number_before_operator, " 1e2-3 " => Token::TokNumber(100.0));
+    single_tokenization_test!(can_tokenize_hex_before_operator, " 0x1E-3 " => Token::TokNumber(30.0));
+    single_tokenization_test!(can_reject_multiple_decimal_points, " 1.2.3 " => Token::TokError(LexError::InvalidNumber {
+        literal: "1.2.3".to_string(), reason: "more than one decimal point".to_string() }));
+    single_tokenization_test!(can_reject_number_suffix, " 3abc " => Token::TokError(LexError::InvalidNumber {
+        literal: "3abc".to_string(), reason: "unexpected character 'a' in base 10 literal".to_string() }));
+    single_tokenization_test!(can_reject_missing_exponent, " 1e " => Token::TokError(LexError::InvalidNumber {
+        literal: "1e".to_string(), reason: "expected exponent digits".to_string() }));
+    single_tokenization_test!(can_reject_missing_fraction, " 1. " => Token::TokError(LexError::InvalidNumber {
+        literal: "1.".to_string(), reason: "expected digits after the decimal point".to_string() }));
+    single_tokenization_test!(can_reject_missing_hex_digits, " 0x " => Token::TokError(LexError::InvalidNumber {
+        literal: "0x".to_string(), reason: "expected digits after the radix prefix".to_string() }));
+    single_tokenization_test!(can_reject_invalid_binary_digit, " 0b102 " => Token::TokError(LexError::InvalidNumber {
+        literal: "0b102".to_string(), reason: "unexpected character '2' in base 2 literal".to_string() }));
+    single_tokenization_test!(can_reject_misplaced_separator, " 1__0 " => Token::TokError(LexError::InvalidNumber {
+        literal: "1__0".to_string(), reason: "'_' must separate two digits".to_string() }));
+    single_tokenization_test!(can_reject_float_overflow, " 1e999 " => Token::TokError(LexError::NumberOutOfRange {
+        literal: "1e999".to_string(), reason: "value is too large for a 64-bit float".to_string() }));
+    single_tokenization_test!(can_reject_float_underflow, " 1e-999 " => Token::TokError(LexError::NumberOutOfRange {
+        literal: "1e-999".to_string(), reason: "value is too small for a 64-bit float".to_string() }));
+    single_tokenization_test!(can_reject_hex_overflow, " 0x1_0000_0000_0000_0000 " => Token::TokError(LexError::NumberOutOfRange {
+        literal: "0x1_0000_0000_0000_0000".to_string(), reason: "value does not fit in 64 bits".to_string() }));
     single_tokenization_test!(can_tokenize_def, " def " => Token::TokDef);
     single_tokenization_test!(can_tokenize_extern, " extern " => Token::TokExtern);
+    single_tokenization_test!(can_tokenize_struct, " struct " => Token::TokStruct);
+    single_tokenization_test!(can_tokenize_const, " const " => Token::TokConst);
+    single_tokenization_test!(can_tokenize_global, " global " => Token::TokGlobal);
+    single_tokenization_test!(can_tokenize_import, " import " => Token::TokImport);
+    single_tokenization_test!(can_tokenize_qualified_name, " math::sqrt(x) " => Token::TokIdentifier("math::sqrt".to_string()));
+    single_tokenization_test!(can_tokenize_nested_qualified_name, " a::b::c " => Token::TokIdentifier("a::b::c".to_string()));
+    single_tokenization_test!(can_tokenize_name_before_separator_without_name, " math:: sqrt " => Token::TokIdentifier("math".to_string()));
     single_tokenization_test!(can_tokenize_strings, " saiftyfirst " => Token::TokIdentifier("saiftyfirst".to_string()));
     single_tokenization_test!(can_tokenize_atan2, " atan2 " => Token::TokIdentifier("atan2".to_string()));
-    single_tokenization_test!(can_tokenize_comments, " # defo herlmeer weg\n" => Token::TokComment("# defo herlmeer weg".to_string()));
+    single_tokenization_test!(can_skip_comments, " # defo herlmeer weg\n" => Token::TokEof);
+    single_tokenization_test!(can_skip_comments_before_token, " # defo herlmeer weg\n atan2" => Token::TokIdentifier("atan2".to_string()));
+    single_tokenization_test!(can_skip_comments_without_space, "x# defo herlmeer weg" => Token::TokIdentifier("x".to_string()));
+
+    single_tokenization_test!(can_tokenize_unicode_identifier, " café " => Token::TokIdentifier("café".to_string()));
+    single_tokenization_test!(can_tokenize_underscore_identifier, " _tmp1 " => Token::TokIdentifier("_tmp1".to_string()));
+    single_tokenization_test!(can_tokenize_non_latin_identifier, " 変数 " => Token::TokIdentifier("変数".to_string()));
+    single_tokenization_test!(can_skip_unicode_comments, " # café ☕\n x" => Token::TokIdentifier("x".to_string()));
+    single_tokenization_test!(can_reject_unexpected_character, " $ " => Token::TokError(LexError::UnexpectedCharacter('$')));
+    single_tokenization_test!(can_reject_unexpected_unicode_character, " ☕ " => Token::TokError(LexError::UnexpectedCharacter('☕')));
+    single_tokenization_test!(can_reject_unicode_number_suffix, " 3é " => Token::TokError(LexError::InvalidNumber {
+        literal: "3é".to_string(), reason: "unexpected character 'é' in base 10 literal".to_string() }));
+    single_tokenization_test!(can_tokenize_string, r#" "café ☕" "# => Token::TokString("café ☕".to_string()));
+    single_tokenization_test!(can_tokenize_empty_string, r#""""# => Token::TokString(String::new()));
+    single_tokenization_test!(can_tokenize_string_escapes, r#""a\tb\n\"c\"\\\0""# => Token::TokString("a\tb\n\"c\"\\\0".to_string()));
+    single_tokenization_test!(can_reject_unterminated_string, " \"abc\nx" => Token::TokError(LexError::UnterminatedString));
+    single_tokenization_test!(can_reject_string_ending_in_escape, r#" "abc\"# => Token::TokError(LexError::UnterminatedString));
+    single_tokenization_test!(can_reject_invalid_escape, r#" "a\qb" "# => Token::TokError(LexError::InvalidEscape('q')));
+
+    #[test]
+    fn can_continue_after_invalid_escape() {
+        let mut tokenizer = Lexer::new(r#""a\q" x"#);
+
+        assert_eq!(tokenizer.pop_with_trivia().span, Span { start: 0, end: 5 });
+        assert_eq!(tokenizer.pop(), Token::TokIdentifier("x".to_string()));
+    }
+
+    #[test]
+    fn can_round_trip_escaped_string() {
+        let value = "tab\t \"quote\" \\ nul\0 line\r\n";
+        assert_eq!(Lexer::new(&escape_string(value)).pop(), Token::TokString(value.to_string()));
+    }
+
+    #[test]
+    fn can_track_byte_offsets_after_unicode() {
+        let mut tokenizer = Lexer::new("# café\nnaïve + ☕");
+
+        assert_eq!(tokenizer.pop_with_trivia().span, Span { start: 8, end: 14 });
+        assert_eq!(tokenizer.pop_with_trivia().span, Span { start: 15, end: 16 });
+        let got = tokenizer.pop_with_trivia();
+        assert_eq!(got.token, Token::TokError(LexError::UnexpectedCharacter('☕')));
+        assert_eq!(got.span, Span { start: 17, end: 20 });
+        assert_eq!(tokenizer.pop(), Token::TokEof);
+    }
+
+    #[test]
+    fn can_attach_leading_and_trailing_trivia() {
+        let mut tokenizer = Lexer::new("# first\n  x # second\n  +");
+
+        let got = tokenizer.pop_with_trivia();
+        assert_eq!(got, SpannedToken {
+            leading: vec![Trivia::Comment("# first".to_string()), Trivia::Whitespace("\n  ".to_string())],
+            token: Token::TokIdentifier("x".to_string()),
+            trailing: vec![Trivia::Whitespace(" ".to_string()), Trivia::Comment("# second".to_string())],
+            span: Span { start: 10, end: 11 }
+        });
+
+        let got = tokenizer.pop_with_trivia();
+        assert_eq!(got.leading, vec![Trivia::Whitespace("\n  ".to_string())]);
+        assert_eq!(got.token, Token::TokOperator("+".to_string()));
+    }
+
+    #[test]
+    fn can_extract_doc_comment() {
+        let tokenizer = Lexer::new("# not attached\n\n# computes foo\n#  of bar\ndef foo(bar) bar");
+
+        assert_eq!(tokenizer.peek_with_trivia().doc_comment(), Some("computes foo\n of bar".to_string()));
+    }
+
+    #[test]
+    fn can_iterate_over_tokens() {
+        let got: Vec<Token> = Lexer::new("def f(x) x + $ 1")
+            .map(|res| res.map(|spanned| spanned.token).unwrap_or_else(Token::TokError))
+            .collect();
+
+        assert_eq!(got, vec![
+            Token::TokDef,
+            Token::TokIdentifier("f".to_string()),
+            Token::TokSymbol('('),
+            Token::TokIdentifier("x".to_string()),
+            Token::TokSymbol(')'),
+            Token::TokIdentifier("x".to_string()),
+            Token::TokOperator("+".to_string()),
+            Token::TokError(LexError::UnexpectedCharacter('$')),
+            Token::TokNumber(1.0)
+        ]);
+    }
+
+    #[test]
+    fn can_tokenize_adjacent_operators() {
+        let got: Vec<Token> = Lexer::new("a<=-b!=!c").map(|res| res.unwrap().token).collect();
+
+        assert_eq!(got, vec![
+            Token::TokIdentifier("a".to_string()),
+            Token::TokOperator("<=".to_string()),
+            Token::TokOperator("-".to_string()),
+            Token::TokIdentifier("b".to_string()),
+            Token::TokOperator("!=".to_string()),
+            Token::TokOperator("!".to_string()),
+            Token::TokIdentifier("c".to_string())
+        ]);
+    }
+
+    #[test]
+    fn can_look_ahead_multiple_tokens() {
+        let mut tokenizer = Lexer::new("foo ( 1");
+
+        assert_eq!(tokenizer.peek_nth(2), &Token::TokNumber(1.0));
+        assert_eq!(tokenizer.peek_nth(3), &Token::TokEof);
+        assert_eq!(tokenizer.peek_nth(1), &Token::TokSymbol('('));
+        assert_eq!(tokenizer.pop(), Token::TokIdentifier("foo".to_string()));
+        assert_eq!(tokenizer.peek(), &Token::TokSymbol('('));
+        assert_eq!(tokenizer.pop(), Token::TokSymbol('('));
+        assert_eq!(tokenizer.pop(), Token::TokNumber(1.0));
+        assert_eq!(tokenizer.pop(), Token::TokEof);
+    }
+
+    #[test]
+    fn can_stream_from_reader() {
+        let src = "# café ☕\n\n\n  def naïve(a, b) # trailing\n    a * b\n\nnaïve(1e-3, 0x1F)";
+        let expected: Vec<SpannedToken> = Lexer::new(src).map(Result::unwrap).collect();
+
+        for chunk_size in [1, 2, 3, 7, 64] {
+            let reader = ChunkedReader { data: src.as_bytes(), chunk_size };
+            let got: Vec<SpannedToken> = Lexer::from_reader(reader).map(Result::unwrap).collect();
+            assert_eq!(got, expected);
+        }
+    }
+
+    #[test]
+    fn can_report_invalid_utf8_from_reader() {
+        let mut tokenizer = Lexer::from_reader(&[b'x', b' ', 0xff, b'\n'][..]);
+
+        assert!(matches!(tokenizer.next(), Some(Err(LexError::Io(_)))));
+        assert!(tokenizer.next().is_none());
+    }
 }
\ No newline at end of file
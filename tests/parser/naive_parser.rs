@@ -4,6 +4,9 @@ mod tests {
     use kaleidoscope::syntax::ast::FuncAst::*;
     use kaleidoscope::syntax::ast::ExprAst::*;
 
+    use kaleidoscope::syntax::ast::{GlobalAst, ImportAst, Param, StructAst};
+    use kaleidoscope::syntax::types::{FunctionType, StructName, Type};
+
     use kaleidoscope::parse::parser::*;
 
     /*
@@ -32,12 +35,24 @@ mod tests {
         }
     }
 
+    macro_rules! base_failing_parser_test {
+        ($name:ident, $src:expr => $should_contain:expr) => {
+            #[test]
+            fn $name() {
+                let mut parser = Parser::new($src);
+
+                let got = parser.build_next_ast().unwrap_err().to_string();
+                assert!(got.contains($should_contain), "unexpected error: {}", got);
+            }
+        }
+    }
+
     base_passing_parser_test!(
         can_parse_no_arg_extern,
         r###"
             extern atan2()
         "###, 1 =>
-        vec![FuncAst(Prototype { name: "atan2".to_string(), args: vec![] })]
+        vec![FuncAst(Prototype { name: "atan2".to_string(), args: vec![], return_type: None })]
     );
 
     base_passing_parser_test!(
@@ -45,7 +60,7 @@ mod tests {
         r###"
             extern atan2(arg, arg2)
         "###, 1 =>
-        vec![FuncAst(Prototype { name: "atan2".to_string(), args: vec!["arg".to_string(), "arg2".to_string()] })]
+        vec![FuncAst(Prototype { name: "atan2".to_string(), args: vec!["arg".into(), "arg2".into()], return_type: None })]
     );
 
     base_passing_parser_test!(
@@ -53,7 +68,7 @@ mod tests {
         r###"
             x + 1
         "###, 1 =>
-        vec![ExprAst(BinaryExpr { op: '+', lhs: Box::new(VariableExpr { name: "x".to_string() }), rhs: Box::new(NumberExpr { number: 1.0 }) })]
+        vec![ExprAst(BinaryExpr { op: "+".to_string(), lhs: Box::new(VariableExpr { name: "x".to_string() }), rhs: Box::new(NumberExpr { number: 1.0 }) })]
     );
 
     base_passing_parser_test!(
@@ -63,7 +78,7 @@ mod tests {
         "###, 1 =>
         vec![
             ExprAst(BinaryExpr {
-                op: '-',
+                op: "-".to_string(),
                 lhs: if let ExprAst(expr) = Parser::new("x + 2").build_next_ast().unwrap() {
                     Box::new(expr)
                 } else {
@@ -79,114 +94,672 @@ mod tests {
     );
 
     base_passing_parser_test!(
-        can_parse_precedence_changing_arithmetic_expression,
+        can_parse_single_argument_function,
         r###"
-            x + 2 -4 * q / y + 2
+            def my_tan(arg1)
+                arg1
         "###, 1 =>
         vec![
-            ExprAst(BinaryExpr {
-                op: '+',
-                lhs: Box::new(BinaryExpr {
-                    op: '-',
-                    lhs: if let ExprAst(expr) = Parser::new("x + 2").build_next_ast().unwrap() {
-                        Box::new(expr)
-                    } else {
-                        panic!("")
-                    },
-                    rhs: if let ExprAst(expr) = Parser::new("4 * q / y").build_next_ast().unwrap() {
-                        Box::new(expr)
-                    } else {
-                        panic!("")
-                    }
-                }),
-                rhs: Box::new(NumberExpr { number: 2.0 })
-          })
+            FuncAst(Function {
+                proto: Box::new(Prototype { name: "my_tan".to_string(), args: vec!["arg1".into()], return_type: None }),
+                body: Box::new(VariableExpr { name: "arg1".to_string() })
+            })
         ]
     );
 
     base_passing_parser_test!(
-        can_parse_parethesis_containing_arithmetic_expression,
+        can_parse_multi_argument_function,
         r###"
-            x / (2 - 4 + q) / y
+            def my_tan(arg1, arg2)
+                arg1 + arg2
         "###, 1 =>
         vec![
-            ExprAst(BinaryExpr {
-                op: '/',
-                lhs: Box::new(BinaryExpr {
-                    op: '/',
-                    lhs: if let ExprAst(expr) = Parser::new("x").build_next_ast().unwrap() {
-                        Box::new(expr)
-                    } else {
-                        panic!("")
-                    },
-                    rhs: if let ExprAst(expr) = Parser::new("2 -4 + q").build_next_ast().unwrap() {
-                        Box::new(expr)
-                    } else {
-                        panic!("")
-                    }
+            FuncAst(Function {
+                proto: Box::new(Prototype { name: "my_tan".to_string(), args: vec!["arg1".into(), "arg2".into()], return_type: None }),
+                body: Box::new(BinaryExpr {
+                    op: "+".to_string(),
+                    lhs:Box::new(VariableExpr { name: "arg1".to_string() }),
+                    rhs:Box::new(VariableExpr { name: "arg2".to_string() }),
                 }),
-                rhs: Box::new(VariableExpr { name: "y".to_string() })
             })
         ]
     );
 
     base_passing_parser_test!(
-        can_parse_parse_compound_b2b_statements,
+        can_parse_expressions_separated_by_comments,
         r###"
-            x * (z * q) / y
-            extern atan2(arg, arg2)
+            # the tangent of arg1
+            def my_tan(arg1) # inline comment
+                arg1 # trailing comment
+            # between expressions
+            x + 1
         "###, 2 =>
+        vec![
+            FuncAst(Function {
+                proto: Box::new(Prototype { name: "my_tan".to_string(), args: vec!["arg1".into()], return_type: None }),
+                body: Box::new(VariableExpr { name: "arg1".to_string() })
+            }),
+            ExprAst(BinaryExpr { op: "+".to_string(), lhs: Box::new(VariableExpr { name: "x".to_string() }), rhs: Box::new(NumberExpr { number: 1.0 }) })
+        ]
+    );
+
+    base_passing_parser_test!(
+        can_parse_unary_negation,
+        r###"
+            -x
+        "###, 1 =>
+        vec![ExprAst(UnaryExpr { op: "-".to_string(), operand: Box::new(VariableExpr { name: "x".to_string() }) })]
+    );
+
+    base_passing_parser_test!(
+        can_parse_unary_before_binary_expression,
+        r###"
+            -x * !y
+        "###, 1 =>
         vec![
             ExprAst(BinaryExpr {
-                op: '/',
-                lhs: Box::new(BinaryExpr {
-                    op: '*',
-                    lhs: if let ExprAst(expr) = Parser::new("x").build_next_ast().unwrap() {
-                        Box::new(expr)
-                    } else {
-                        panic!("")
-                    },
-                    rhs: if let ExprAst(expr) = Parser::new("(z * q)").build_next_ast().unwrap() {
+                op: "*".to_string(),
+                lhs: Box::new(UnaryExpr { op: "-".to_string(), operand: Box::new(VariableExpr { name: "x".to_string() }) }),
+                rhs: Box::new(UnaryExpr { op: "!".to_string(), operand: Box::new(VariableExpr { name: "y".to_string() }) })
+            })
+        ]
+    );
+
+    base_passing_parser_test!(
+        can_parse_unary_as_binary_rhs,
+        r###"
+            x - -(y + 1)
+        "###, 1 =>
+        vec![
+            ExprAst(BinaryExpr {
+                op: "-".to_string(),
+                lhs: Box::new(VariableExpr { name: "x".to_string() }),
+                rhs: Box::new(UnaryExpr {
+                    op: "-".to_string(),
+                    operand: if let ExprAst(expr) = Parser::new("y + 1").build_next_ast().unwrap() {
                         Box::new(expr)
                     } else {
                         panic!("")
                     }
+                })
+            })
+        ]
+    );
+
+    base_passing_parser_test!(
+        can_parse_nested_unary_in_call_argument,
+        r###"
+            f(-1, !-x)
+        "###, 1 =>
+        vec![
+            ExprAst(CallExpr {
+                callee: "f".to_string(),
+                args: vec![
+                    UnaryExpr { op: "-".to_string(), operand: Box::new(NumberExpr { number: 1.0 }) },
+                    UnaryExpr { op: "!".to_string(), operand: Box::new(UnaryExpr { op: "-".to_string(), operand: Box::new(VariableExpr { name: "x".to_string() }) }) }
+                ]
+            })
+        ]
+    );
+
+    base_passing_parser_test!(
+        can_parse_right_associative_power,
+        r###"
+            2 ^ 3 ^ 2
+        "###, 1 =>
+        vec![
+            ExprAst(BinaryExpr {
+                op: "^".to_string(),
+                lhs: Box::new(NumberExpr { number: 2.0 }),
+                rhs: Box::new(BinaryExpr { op: "^".to_string(), lhs: Box::new(NumberExpr { number: 3.0 }), rhs: Box::new(NumberExpr { number: 2.0 }) })
+            })
+        ]
+    );
+
+    base_passing_parser_test!(
+        can_parse_power_tighter_than_unary,
+        r###"
+            -x ^ 2 * y
+        "###, 1 =>
+        vec![
+            ExprAst(BinaryExpr {
+                op: "*".to_string(),
+                lhs: Box::new(UnaryExpr {
+                    op: "-".to_string(),
+                    operand: Box::new(BinaryExpr { op: "^".to_string(), lhs: Box::new(VariableExpr { name: "x".to_string() }), rhs: Box::new(NumberExpr { number: 2.0 }) })
                 }),
                 rhs: Box::new(VariableExpr { name: "y".to_string() })
-            }),
-            FuncAst(Prototype { name: "atan2".to_string(), args: vec!["arg".to_string(), "arg2".to_string()] })
+            })
         ]
     );
 
     base_passing_parser_test!(
-        can_parse_single_argument_function,
+        can_parse_left_associative_subtraction,
         r###"
-            def my_tan(arg1)
-                arg1
+            a - b - c
         "###, 1 =>
         vec![
-            FuncAst(Function {
-                proto: Box::new(Prototype { name: "my_tan".to_string(), args: vec!["arg1".to_string()] }),
-                body: Box::new(VariableExpr { name: "arg1".to_string() })
+            ExprAst(BinaryExpr {
+                op: "-".to_string(),
+                lhs: Box::new(BinaryExpr { op: "-".to_string(), lhs: Box::new(VariableExpr { name: "a".to_string() }), rhs: Box::new(VariableExpr { name: "b".to_string() }) }),
+                rhs: Box::new(VariableExpr { name: "c".to_string() })
             })
         ]
     );
 
     base_passing_parser_test!(
-        can_parse_multi_argument_function,
+        can_parse_right_associative_assignment,
         r###"
-            def my_tan(arg1, arg2)
-                arg1 + arg2
+            a = b = c + 1
+        "###, 1 =>
+        vec![
+            ExprAst(BinaryExpr {
+                op: "=".to_string(),
+                lhs: Box::new(VariableExpr { name: "a".to_string() }),
+                rhs: Box::new(BinaryExpr {
+                    op: "=".to_string(),
+                    lhs: Box::new(VariableExpr { name: "b".to_string() }),
+                    rhs: Box::new(BinaryExpr { op: "+".to_string(), lhs: Box::new(VariableExpr { name: "c".to_string() }), rhs: Box::new(NumberExpr { number: 1.0 }) })
+                })
+            })
+        ]
+    );
+
+    base_passing_parser_test!(
+        can_parse_grouped_comparisons,
+        r###"
+            (a < b) < c
+        "###, 1 =>
+        vec![
+            ExprAst(BinaryExpr {
+                op: "<".to_string(),
+                lhs: Box::new(BinaryExpr { op: "<".to_string(), lhs: Box::new(VariableExpr { name: "a".to_string() }), rhs: Box::new(VariableExpr { name: "b".to_string() }) }),
+                rhs: Box::new(VariableExpr { name: "c".to_string() })
+            })
+        ]
+    );
+
+    #[test]
+    fn can_reject_chained_comparisons() {
+        let got = Parser::new("a < b + 1 < c").build_next_ast();
+
+        assert!(got.unwrap_err().to_string().contains("non-associative"));
+    }
+
+    #[test]
+    fn can_reject_assignment_to_non_variable() {
+        assert!(Parser::new("1 = x").build_next_ast().is_err());
+        assert!(Parser::new("a + b = x").build_next_ast().is_err());
+    }
+
+    base_passing_parser_test!(
+        can_parse_logical_operator_precedence,
+        r###"
+            a || b && c == d
+        "###, 1 =>
+        vec![
+            ExprAst(BinaryExpr {
+                op: "||".to_string(),
+                lhs: Box::new(VariableExpr { name: "a".to_string() }),
+                rhs: Box::new(BinaryExpr {
+                    op: "&&".to_string(),
+                    lhs: Box::new(VariableExpr { name: "b".to_string() }),
+                    rhs: Box::new(BinaryExpr { op: "==".to_string(), lhs: Box::new(VariableExpr { name: "c".to_string() }), rhs: Box::new(VariableExpr { name: "d".to_string() }) })
+                })
+            })
+        ]
+    );
+
+    base_passing_parser_test!(
+        can_parse_relational_inside_equality,
+        r###"
+            a <= b != c % 2 >= d
+        "###, 1 =>
+        vec![
+            ExprAst(BinaryExpr {
+                op: "!=".to_string(),
+                lhs: Box::new(BinaryExpr { op: "<=".to_string(), lhs: Box::new(VariableExpr { name: "a".to_string() }), rhs: Box::new(VariableExpr { name: "b".to_string() }) }),
+                rhs: Box::new(BinaryExpr {
+                    op: ">=".to_string(),
+                    lhs: Box::new(BinaryExpr { op: "%".to_string(), lhs: Box::new(VariableExpr { name: "c".to_string() }), rhs: Box::new(NumberExpr { number: 2.0 }) }),
+                    rhs: Box::new(VariableExpr { name: "d".to_string() })
+                })
+            })
+        ]
+    );
+
+    #[test]
+    fn can_reject_chained_equality() {
+        assert!(Parser::new("a == b != c").build_next_ast().is_err());
+    }
+
+    base_passing_parser_test!(
+        can_parse_nested_block_with_trailing_semicolon,
+        r###"
+            { a; { b; }; } * 2
         "###, 1 =>
         vec![
+            ExprAst(BinaryExpr {
+                op: "*".to_string(),
+                lhs: Box::new(BlockExpr {
+                    exprs: vec![
+                        VariableExpr { name: "a".to_string() },
+                        BlockExpr { exprs: vec![VariableExpr { name: "b".to_string() }] }
+                    ]
+                }),
+                rhs: Box::new(NumberExpr { number: 2.0 })
+            })
+        ]
+    );
+
+    #[test]
+    fn can_reject_malformed_blocks() {
+        assert!(Parser::new("{ }").build_next_ast().is_err());
+        assert!(Parser::new("{ a b }").build_next_ast().is_err());
+        assert!(Parser::new("{ a; b").build_next_ast().is_err());
+    }
+
+    #[test]
+    fn can_display_block() {
+        if let ExprAst(got) = Parser::new("{ a; b + 1; }").build_next_ast().unwrap() {
+            assert_eq!(got.to_string(), "{ a; b + 1 }");
+        } else {
+            panic!("")
+        }
+    }
+
+    #[test]
+    fn can_parse_program_with_terminators() {
+        let program = Parser::new(r###"
+            extern sin(x);
+            def foo(x) x + 1; foo(2);;
+            foo(3)
+            ;
+        "###).parse_program();
+
+        assert!(program.errors.is_empty());
+        assert_eq!(program.items, vec![
+            FuncAst(Prototype { name: "sin".to_string(), args: vec!["x".into()], return_type: None }),
             FuncAst(Function {
-                proto: Box::new(Prototype { name: "my_tan".to_string(), args: vec!["arg1".to_string(), "arg2".to_string()] }),
-                body: Box::new(BinaryExpr {
-                    op: '+',
-                    lhs:Box::new(VariableExpr { name: "arg1".to_string() }),
-                    rhs:Box::new(VariableExpr { name: "arg2".to_string() }),
+                proto: Box::new(Prototype { name: "foo".to_string(), args: vec!["x".into()], return_type: None }),
+                body: Box::new(BinaryExpr { op: "+".to_string(), lhs: Box::new(VariableExpr { name: "x".to_string() }), rhs: Box::new(NumberExpr { number: 1.0 }) })
+            }),
+            ExprAst(CallExpr { callee: "foo".to_string(), args: vec![NumberExpr { number: 2.0 }] }),
+            ExprAst(CallExpr { callee: "foo".to_string(), args: vec![NumberExpr { number: 3.0 }] })
+        ]);
+    }
+
+    #[test]
+    fn can_reject_unterminated_items_on_one_line() {
+        let got = Parser::new("1 + 2 3").build_next_ast();
+
+        assert!(got.unwrap_err().to_string().contains("Expected ';' or a line break"));
+    }
+
+    #[test]
+    fn can_collect_errors_and_recover() {
+        let program = Parser::new(r###"
+            def broken(x x;
+            1 + 2 3 4;
+            def ok(x) x
+            extern )
+            ok(1)
+        "###).parse_program();
+
+        assert_eq!(program.errors.len(), 3);
+        assert_eq!(program.items, vec![
+            FuncAst(Function {
+                proto: Box::new(Prototype { name: "ok".to_string(), args: vec!["x".into()], return_type: None }),
+                body: Box::new(VariableExpr { name: "x".to_string() })
+            })
+        ]);
+    }
+
+    #[test]
+    fn can_recover_from_broken_block_with_one_error() {
+        let program = Parser::new(r###"
+            def broken(x) { x = ); x + 1; x * 2 };
+            def ok(x) x
+        "###).parse_program();
+
+        assert_eq!(program.errors.len(), 1);
+        assert_eq!(program.items, vec![
+            FuncAst(Function {
+                proto: Box::new(Prototype { name: "ok".to_string(), args: vec!["x".into()], return_type: None }),
+                body: Box::new(VariableExpr { name: "x".to_string() })
+            })
+        ]);
+    }
+
+    #[test]
+    fn can_tell_end_of_input_from_errors() {
+        let mut parser = Parser::new("x; ;\n  # only trivia left\n");
+
+        assert!(matches!(parser.next(), Some(Ok(_))));
+        assert!(parser.next().is_none());
+        assert!(parser.is_eof());
+    }
+
+    base_failing_parser_test!(
+        can_reject_grouping_closed_by_comma,
+        "(1 + 2 , 3)" => "Unclosed parenthesis opened at byte 0: expected ')', found <primary> , at byte 7."
+    );
+
+    base_failing_parser_test!(
+        can_reject_unterminated_grouping,
+        "(1+2" => "Unclosed parenthesis opened at byte 0: expected ')', found end of input."
+    );
+
+    base_failing_parser_test!(
+        can_reject_unterminated_outer_grouping,
+        "x * ((1 + 2) - y" => "Unclosed parenthesis opened at byte 4"
+    );
+
+    base_failing_parser_test!(
+        can_reject_unterminated_call,
+        "1 + foo(1, 2" => "Unclosed call to foo opened at byte 7: expected ')', found end of input."
+    );
+
+    base_failing_parser_test!(
+        can_reject_call_with_missing_comma,
+        "foo(1 2)" => "Unclosed call to foo opened at byte 3: expected ')', found <number> 2 at byte 6."
+    );
+
+    base_failing_parser_test!(
+        can_reject_unterminated_extern,
+        "extern foo(a, b" => "Unclosed prototype of foo opened at byte 10"
+    );
+
+    base_failing_parser_test!(
+        can_reject_prototype_with_missing_comma,
+        "def foo(a b) a" => "Unclosed prototype of foo opened at byte 7: expected ')', found <identifier> b at byte 10."
+    );
+
+    base_failing_parser_test!(
+        can_reject_unterminated_block,
+        "def foo(a) {\n  a;\n  a + 1\n" => "Unclosed block opened at byte 11: expected '}', found end of input."
+    );
+
+    base_passing_parser_test!(
+        can_parse_typed_prototype,
+        "extern pow(x: f64, n: i64) -> f64", 1
+        => vec![FuncAst(Prototype {
+            name: "pow".to_string(),
+            args: vec![
+                Param { name: "x".to_string(), ty: Some(Type::F64) },
+                Param { name: "n".to_string(), ty: Some(Type::I64) }
+            ],
+            return_type: Some(Type::F64)
+        })]
+    );
+
+    base_passing_parser_test!(
+        can_parse_partially_typed_definition,
+        "def f(x, flag: bool) x", 1
+        => vec![FuncAst(Function {
+                proto: Box::new(Prototype {
+                    name: "f".to_string(),
+                    args: vec!["x".into(), Param { name: "flag".to_string(), ty: Some(Type::Bool) }],
+                    return_type: None
+                }),
+                body: Box::new(VariableExpr { name: "x".to_string() })
+        })]
+    );
+
+    base_passing_parser_test!(
+        can_parse_conversion,
+        "i64(x / 2)", 1
+        => vec![ExprAst(CastExpr {
+            ty: Type::I64,
+            operand: Box::new(BinaryExpr {
+                op: "/".to_string(),
+                lhs: Box::new(VariableExpr { name: "x".to_string() }),
+                rhs: Box::new(NumberExpr { number: 2.0 })
+            })
+        })]
+    );
+
+    base_failing_parser_test!(
+        can_reject_missing_return_type,
+        "extern f(x) -> (" => "Expected a type, found"
+    );
+
+    base_failing_parser_test!(
+        can_reject_conversion_with_two_arguments,
+        "f64(1, 2)" => "Conversion to f64 takes exactly one argument, found 2."
+    );
+
+    base_passing_parser_test!(
+        can_parse_string_argument,
+        r#"println("x:\t", str(x))"#, 1
+        => vec![ExprAst(CallExpr {
+            callee: "println".to_string(),
+            args: vec![
+                StringExpr { value: "x:\t".to_string() },
+                CastExpr { ty: Type::Str, operand: Box::new(VariableExpr { name: "x".to_string() }) }
+            ]
+        })]
+    );
+
+    base_failing_parser_test!(
+        can_reject_unterminated_string_expression,
+        "println(\"abc)\n" => "Unterminated string literal, strings end on the same line at byte 8"
+    );
+
+    base_passing_parser_test!(
+        can_parse_array_literal,
+        "[1, x, []]", 1
+        => vec![ExprAst(ArrayExpr {
+            elements: vec![
+                NumberExpr { number: 1.0 },
+                VariableExpr { name: "x".to_string() },
+                ArrayExpr { elements: vec![] }
+            ]
+        })]
+    );
+
+    base_passing_parser_test!(
+        can_parse_index_tighter_than_unary_operator,
+        "-a[i + 1]", 1
+        => vec![ExprAst(UnaryExpr {
+            op: "-".to_string(),
+            operand: Box::new(IndexExpr {
+                array: Box::new(VariableExpr { name: "a".to_string() }),
+                index: Box::new(BinaryExpr {
+                    op: "+".to_string(),
+                    lhs: Box::new(VariableExpr { name: "i".to_string() }),
+                    rhs: Box::new(NumberExpr { number: 1.0 })
+                })
+            })
+        })]
+    );
+
+    base_passing_parser_test!(
+        can_parse_assignment_to_element,
+        "def f(a: [f64]) a[0] = 2", 1
+        => vec![FuncAst(Function {
+            proto: Box::new(Prototype {
+                name: "f".to_string(),
+                args: vec![Param { name: "a".to_string(), ty: Some(Type::Array) }],
+                return_type: None
+            }),
+            body: Box::new(BinaryExpr {
+                op: "=".to_string(),
+                lhs: Box::new(IndexExpr {
+                    array: Box::new(VariableExpr { name: "a".to_string() }),
+                    index: Box::new(NumberExpr { number: 0.0 })
+                }),
+                rhs: Box::new(NumberExpr { number: 2.0 })
+            })
+        })]
+    );
+
+    base_failing_parser_test!(
+        can_reject_array_of_other_type,
+        "def f(a: [i64]) a" => "Arrays can only hold f64, found [i64]."
+    );
+
+    base_failing_parser_test!(
+        can_reject_unclosed_array,
+        "[1, 2" => "Unclosed array opened at byte 0: expected ']', found end of input."
+    );
+
+    base_passing_parser_test!(
+        can_parse_struct_definition,
+        "struct Point { x, y: i64, }", 1
+        => vec![StructAst(StructAst {
+            name: "Point".to_string(),
+            fields: vec![
+                Param { name: "x".to_string(), ty: None },
+                Param { name: "y".to_string(), ty: Some(Type::I64) }
+            ]
+        })]
+    );
+
+    base_passing_parser_test!(
+        can_parse_struct_parameter_and_field_access,
+        "def f(p: Point) p.x", 1
+        => vec![FuncAst(Function {
+            proto: Box::new(Prototype {
+                name: "f".to_string(),
+                args: vec![Param { name: "p".to_string(), ty: Some(Type::Struct(StructName::new("Point"))) }],
+                return_type: None
+            }),
+            body: Box::new(FieldExpr {
+                object: Box::new(VariableExpr { name: "p".to_string() }),
+                field: "x".to_string()
+            })
+        })]
+    );
+
+    base_passing_parser_test!(
+        can_parse_field_access_tighter_than_unary_operator,
+        "-f(a).b[0].c", 1
+        => vec![ExprAst(UnaryExpr {
+            op: "-".to_string(),
+            operand: Box::new(FieldExpr {
+                object: Box::new(IndexExpr {
+                    array: Box::new(FieldExpr {
+                        object: Box::new(CallExpr { callee: "f".to_string(), args: vec![VariableExpr { name: "a".to_string() }] }),
+                        field: "b".to_string()
+                    }),
+                    index: Box::new(NumberExpr { number: 0.0 })
                 }),
+                field: "c".to_string()
+            })
+        })]
+    );
+
+    base_failing_parser_test!(
+        can_reject_assignment_to_field_of_call,
+        "f(a).x = 1" => "Attempted to assign to non-variable expression f(a).x."
+    );
+
+    base_failing_parser_test!(
+        can_reject_struct_named_like_builtin_type,
+        "struct i64 { x }" => "Cannot declare struct i64, it is the name of a builtin type."
+    );
+
+    base_failing_parser_test!(
+        can_reject_missing_field_name,
+        "p.(x)" => "Expected a field name after '.', found"
+    );
+
+    base_passing_parser_test!(
+        can_parse_lambda_with_body_extending_right,
+        "f(\\x -> x * k, 1)", 1
+        => vec![ExprAst(CallExpr {
+            callee: "f".to_string(),
+            args: vec![
+                LambdaExpr {
+                    params: vec!["x".into()],
+                    return_type: None,
+                    body: Box::new(BinaryExpr {
+                        op: "*".to_string(),
+                        lhs: Box::new(VariableExpr { name: "x".to_string() }),
+                        rhs: Box::new(VariableExpr { name: "k".to_string() })
+                    })
+                },
+                NumberExpr { number: 1.0 }
+            ]
+        })]
+    );
+
+    base_passing_parser_test!(
+        can_parse_typed_lambda,
+        "\\(x: i64, y): bool -> x", 1
+        => vec![ExprAst(LambdaExpr {
+            params: vec![Param { name: "x".to_string(), ty: Some(Type::I64) }, "y".into()],
+            return_type: Some(Type::Bool),
+            body: Box::new(VariableExpr { name: "x".to_string() })
+        })]
+    );
+
+    base_passing_parser_test!(
+        can_parse_function_type,
+        "extern g(f: fn(f64, i64) -> fn() -> bool) -> f64", 1
+        => vec![FuncAst(Prototype {
+            name: "g".to_string(),
+            args: vec![Param {
+                name: "f".to_string(),
+                ty: Some(Type::Function(FunctionType::new(
+                    vec![Type::F64, Type::I64],
+                    Type::Function(FunctionType::new(vec![], Type::Bool))
+                )))
+            }],
+            return_type: Some(Type::F64)
+        })]
+    );
+
+    base_failing_parser_test!(
+        can_reject_function_type_without_return_type,
+        "def f(g: fn(f64)) g" => "Expected '->' and the return type of the function type, found <primary> )."
+    );
+
+    base_failing_parser_test!(
+        can_reject_lambda_without_arrow,
+        "\\x: f64 -> x" => "Expected '->' before the body of the lambda, found <primary> :."
+    );
+
+    base_passing_parser_test!(
+        can_parse_const_and_global_definitions,
+        "const rate = 2.5\nglobal counter: i64 = x = 0", 2
+        => vec![
+            GlobalAst(GlobalAst { name: "rate".to_string(), ty: None, value: NumberExpr { number: 2.5 }, constant: true }),
+            GlobalAst(GlobalAst {
+                name: "counter".to_string(),
+                ty: Some(Type::I64),
+                value: BinaryExpr {
+                    op: "=".to_string(),
+                    lhs: Box::new(VariableExpr { name: "x".to_string() }),
+                    rhs: Box::new(NumberExpr { number: 0.0 })
+                },
+                constant: false
             })
         ]
     );
-}
\ No newline at end of file
+
+    base_failing_parser_test!(
+        can_reject_global_without_name,
+        "global = 1" => "Expected the name of the global, found <operator> =."
+    );
+
+    base_failing_parser_test!(
+        can_reject_const_without_value,
+        "const pi 3" => "Expected '=' and the value of const pi, found <number> 3."
+    );
+
+    base_passing_parser_test!(
+        can_parse_import_and_qualified_names,
+        "import \"lib/math.kal\"\nmath::sqrt(math::pi)", 2
+        => vec![
+            ImportAst(ImportAst { path: "lib/math.kal".to_string() }),
+            ExprAst(CallExpr { callee: "math::sqrt".to_string(), args: vec![VariableExpr { name: "math::pi".to_string() }] })
+        ]
+    );
+
+    base_failing_parser_test!(
+        can_reject_import_without_path,
+        "import math" => "Expected the path of the imported file as a string, found <identifier> math."
+    );
+}
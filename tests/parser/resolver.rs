@@ -0,0 +1,229 @@
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use kaleidoscope::parse::parser::Parser;
+    use kaleidoscope::parse::resolver::*;
+
+    // a directory of its own for every test, the tests run in parallel
+    fn write_files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("kaleidoscope_resolver_{}", test));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        for (path, src) in files {
+            let file = root.join(path);
+            std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+            std::fs::write(file, src).unwrap();
+        }
+        root.canonicalize().unwrap()
+    }
+
+    fn printed_items(module: &Module) -> Vec<String> {
+        module.items.iter().map(|item| item.ast.to_string()).collect()
+    }
+
+    fn resolve_error(resolver: &mut Resolver, path: &str, directory: &Path) -> ImportError {
+        match resolver.resolve(path, directory) {
+            Err(err) => err,
+            Ok(_) => panic!("{} was imported", path)
+        }
+    }
+
+    // the items of a source, with their uses of the prelude renamed
+    fn with_prelude(resolver: &mut Resolver, src: &str) -> Vec<String> {
+        let mut parser = Parser::new(src);
+        let mut items = Vec::new();
+        while let Some(item) = parser.next_spanned() {
+            let mut ast = item.unwrap().ast;
+            resolver.use_prelude(&mut ast);
+            items.push(ast.to_string());
+        }
+        items
+    }
+
+    #[test]
+    fn can_qualify_declarations_and_their_uses() {
+        let root = write_files("qualify", &[("math.kal",
+            "extern sqrt(x)\nconst pi = 3.14159\nstruct Point { x, y }\n\
+             def norm(p: Point) sqrt(p.x * p.x + p.y * p.y)\ndef area(r) pi * r * r\n\
+             def shadow(pi, norm: fn(f64) -> f64) norm(pi)\ndef apply(f: fn(Point) -> f64) f(Point(0, 0)) + norm(Point(1, 1))\n\
+             def scaled(k) \\(area) -> area * k + area(k)")]);
+        let mut resolver = Resolver::new(Vec::new());
+
+        let module = resolver.resolve("math.kal", &root).unwrap().unwrap();
+        assert_eq!(module.namespace, "math");
+        assert_eq!(module.path, root.join("math.kal"));
+        assert_eq!(printed_items(&module), vec![
+            "extern sqrt(x)",
+            "const math::pi = 3.14159",
+            "struct math::Point { x, y }",
+            "def math::norm(p: math::Point) sqrt(p.x * p.x + p.y * p.y)",
+            "def math::area(r) math::pi * r * r",
+            "def math::shadow(pi, norm: fn(f64) -> f64) norm(pi)",
+            "def math::apply(f: fn(math::Point) -> f64) f(math::Point(0, 0)) + math::norm(math::Point(1, 1))",
+            "def math::scaled(k) \\area -> area * k + area(k)"
+        ]);
+    }
+
+    #[test]
+    fn can_import_file_once() {
+        let root = write_files("once", &[("lib/util.kal", "def one() 1"), ("lib/other.kal", "import \"util.kal\"")]);
+        let mut resolver = Resolver::new(Vec::new());
+
+        let other = resolver.resolve("lib/other.kal", &root).unwrap().unwrap();
+        assert!(resolver.resolve("util.kal", &root.join("lib")).unwrap().is_some());
+        resolver.finish();
+        resolver.finish();
+
+        assert!(resolver.resolve("lib/util.kal", &root).unwrap().is_none());
+        assert!(resolver.resolve("util.kal", other.path.parent().unwrap()).unwrap().is_none());
+    }
+
+    #[test]
+    fn can_search_include_path_after_importing_directory() {
+        let root = write_files("include_path", &[
+            ("src/geometry.kal", "def local() 1"),
+            ("first/geometry.kal", "def first() 1"),
+            ("first/shapes.kal", "def shape() 1"),
+            ("second/shapes.kal", "def other_shape() 1"),
+        ]);
+        let mut resolver = Resolver::new(vec![root.join("first"), root.join("second")]);
+
+        let geometry = resolver.resolve("geometry.kal", &root.join("src")).unwrap().unwrap();
+        assert_eq!(geometry.path, root.join("src/geometry.kal"));
+        resolver.finish();
+        let shapes = resolver.resolve("shapes.kal", &root.join("src")).unwrap().unwrap();
+        assert_eq!(shapes.path, root.join("first/shapes.kal"));
+    }
+
+    #[test]
+    fn can_reject_missing_file() {
+        let root = write_files("missing", &[]);
+        let mut resolver = Resolver::new(vec![PathBuf::from("lib")]);
+
+        assert_eq!(resolve_error(&mut resolver, "math.kal", &root),
+                   ImportError::NotFound { path: "math.kal".to_string(), searched: vec![root.clone(), PathBuf::from("lib")] });
+    }
+
+    #[test]
+    fn can_reject_import_cycle() {
+        let root = write_files("cycle", &[("a.kal", "import \"b.kal\""), ("b.kal", "import \"a.kal\"")]);
+        let mut resolver = Resolver::new(Vec::new());
+
+        resolver.resolve("a.kal", &root).unwrap().unwrap();
+        resolver.resolve("b.kal", &root).unwrap().unwrap();
+        let err = resolve_error(&mut resolver, "a.kal", &root);
+        assert_eq!(err, ImportError::Cycle { files: vec![root.join("a.kal"), root.join("b.kal"), root.join("a.kal")] });
+        let (a, b) = (root.join("a.kal").display().to_string(), root.join("b.kal").display().to_string());
+        assert_eq!(err.to_string(), format!("Import cycle: {} imports {} imports {}.", a, b, a));
+    }
+
+    #[test]
+    fn can_reject_import_of_root() {
+        let root = write_files("root", &[("main.kal", "import \"main.kal\"")]);
+        let mut resolver = Resolver::with_root(Vec::new(), &root.join("main.kal"));
+
+        assert_eq!(resolve_error(&mut resolver, "main.kal", &root),
+                   ImportError::Cycle { files: vec![root.join("main.kal"), root.join("main.kal")] });
+    }
+
+    #[test]
+    fn can_reject_top_level_expression() {
+        let root = write_files("expression", &[("script.kal", "def f() 1\nf()")]);
+        let mut resolver = Resolver::new(Vec::new());
+
+        assert_eq!(resolve_error(&mut resolver, "script.kal", &root), ImportError::TopLevelExpression { path: root.join("script.kal") });
+    }
+
+    #[test]
+    fn can_reject_parse_errors() {
+        let root = write_files("parse", &[("broken.kal", "def f(\ndef g() 1")]);
+        let mut resolver = Resolver::new(Vec::new());
+
+        match resolve_error(&mut resolver, "broken.kal", &root) {
+            ImportError::Parse { path, errors } => {
+                assert_eq!(path, root.join("broken.kal"));
+                assert_eq!(errors.len(), 1);
+            },
+            err => panic!("unexpected error: {}", err)
+        }
+    }
+
+    #[test]
+    fn can_reject_file_name_that_is_not_identifier() {
+        let root = write_files("namespace", &[("my-math.kal", "def f() 1"), ("def.kal", "def f() 1")]);
+        let mut resolver = Resolver::new(Vec::new());
+
+        assert_eq!(resolve_error(&mut resolver, "my-math.kal", &root), ImportError::InvalidNamespace { path: root.join("my-math.kal") });
+        assert_eq!(resolve_error(&mut resolver, "def.kal", &root), ImportError::InvalidNamespace { path: root.join("def.kal") });
+    }
+
+    #[test]
+    fn can_reject_namespace_clash() {
+        let root = write_files("clash", &[("lib/math.kal", "def f() 1"), ("other/math.kal", "def g() 1")]);
+        let mut resolver = Resolver::new(Vec::new());
+
+        resolver.resolve("lib/math.kal", &root).unwrap().unwrap();
+        resolver.finish();
+        assert_eq!(resolve_error(&mut resolver, "other/math.kal", &root), ImportError::NamespaceClash {
+            namespace: "math".to_string(), first: root.join("lib/math.kal"), second: root.join("other/math.kal")
+        });
+    }
+
+    #[test]
+    fn can_load_prelude() {
+        let mut resolver = Resolver::new(Vec::new());
+
+        let prelude = resolver.load_prelude().unwrap();
+        assert_eq!(prelude.namespace, "prelude");
+        let items = printed_items(&prelude);
+        for expected in ["extern sqrt(x)", "def prelude::clamp(x, low, high) prelude::min(prelude::max(x, low), high)"] {
+            assert!(items.iter().any(|item| item == expected), "expected {:?} in {:?}", expected, items);
+        }
+        assert!(matches!(resolver.load_prelude(), Err(ImportError::NamespaceClash { .. })));
+    }
+
+    #[test]
+    fn can_use_prelude_until_name_is_declared() {
+        let mut resolver = Resolver::new(Vec::new());
+        resolver.load_prelude().unwrap();
+
+        assert_eq!(with_prelude(&mut resolver, "def f(x) abs(x) + pow(x, 2) + sqrt(x)\ndef g(min) min + max(min, 1)\n\
+                                                 def abs(x) abs(x)\nextern pow(x, y)\nabs(1) + pow(1, 2) + prelude::pow(1, 2)"), vec![
+            "def f(x) prelude::abs(x) + prelude::pow(x, 2) + sqrt(x)",
+            "def g(min) min + prelude::max(min, 1)",
+            "def abs(x) abs(x)",
+            "extern pow(x, y)",
+            "abs(1) + pow(1, 2) + prelude::pow(1, 2)"
+        ]);
+    }
+
+    #[test]
+    fn can_keep_prelude_helpers_out_of_sources() {
+        let mut resolver = Resolver::new(Vec::new());
+        resolver.load_prelude().unwrap();
+
+        assert_eq!(with_prelude(&mut resolver, "def _pow_loop(x) pow(x, 2)\n_random_state + prelude::_pow_loop(2, 3, 1)"), vec![
+            "def _pow_loop(x) prelude::pow(x, 2)",
+            "_random_state + prelude::_pow_loop(2, 3, 1)"
+        ]);
+    }
+
+    #[test]
+    fn can_leave_names_without_prelude() {
+        let mut resolver = Resolver::new(Vec::new());
+
+        assert_eq!(with_prelude(&mut resolver, "def f(x) abs(x)"), vec!["def f(x) abs(x)"]);
+    }
+
+    #[test]
+    fn can_reject_import_named_like_prelude() {
+        let root = write_files("prelude", &[("prelude.kal", "def f() 1")]);
+        let mut resolver = Resolver::new(Vec::new());
+        resolver.load_prelude().unwrap();
+
+        assert_eq!(resolve_error(&mut resolver, "prelude.kal", &root), ImportError::NamespaceClash {
+            namespace: "prelude".to_string(), first: PathBuf::from("<prelude>"), second: root.join("prelude.kal")
+        });
+    }
+}
@@ -0,0 +1,123 @@
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use kaleidoscope::parse::parser::*;
+    use kaleidoscope::syntax::pretty::*;
+
+    // the printed item has to read back as the same AST
+    macro_rules! pretty_print_test {
+        ($name:ident, $src:expr => $should_be:expr) => {
+            #[test]
+            fn $name() {
+                let ast = Parser::new($src).build_next_ast().unwrap();
+
+                let got = ast.to_string();
+                assert_eq!(got, $should_be);
+                assert_eq!(Parser::new(&got).build_next_ast().unwrap(), ast);
+            }
+        }
+    }
+
+    macro_rules! format_test {
+        ($name:ident, $src:expr => $should_be:expr) => {
+            #[test]
+            fn $name() {
+                let got = format_source($src).unwrap();
+                assert_eq!(got, $should_be);
+                assert_eq!(format_source(&got).unwrap(), got, "formatting is not idempotent");
+                assert_eq!(Parser::new(&got).parse_program().items, Parser::new($src).parse_program().items);
+            }
+        }
+    }
+
+    pretty_print_test!(can_drop_parentheses_of_left_associative_chain, "((a - b) - c)" => "a - b - c");
+    pretty_print_test!(can_keep_parentheses_on_left_associative_rhs, "a - (b - c)" => "a - (b - c)");
+    pretty_print_test!(can_drop_parentheses_of_right_associative_chain, "a ^ (b ^ c)" => "a ^ b ^ c");
+    pretty_print_test!(can_keep_parentheses_on_right_associative_lhs, "(a ^ b) ^ c" => "(a ^ b) ^ c");
+    pretty_print_test!(can_keep_parentheses_of_non_associative_chain, "((a < b) == (c < d)) != e" => "(a < b == c < d) != e");
+    pretty_print_test!(can_drop_parentheses_of_tighter_operators, "(a * b) + (c / d) % e" => "a * b + c / d % e");
+    pretty_print_test!(can_keep_parentheses_of_looser_operators, "(a + b) * (c || d)" => "(a + b) * (c || d)");
+    pretty_print_test!(can_print_unary_under_power, "-(x ^ 2)" => "-x ^ 2");
+    pretty_print_test!(can_keep_parentheses_of_unary_power_base, "(-x) ^ 2" => "(-x) ^ 2");
+    pretty_print_test!(can_keep_parentheses_of_unary_operand, "-(a + b) * !(c && d)" => "-(a + b) * !(c && d)");
+    pretty_print_test!(can_print_unary_rhs, "a - (-b) * (!c)" => "a - -b * !c");
+    pretty_print_test!(can_print_assignment_chain, "x = (y = (1 + 2))" => "x = y = 1 + 2");
+    pretty_print_test!(can_print_call_and_block, "f((a + b), { x = 1; (x) })" => "f(a + b, { x = 1; x })");
+    pretty_print_test!(can_print_extern, "extern atan2(y, x)" => "extern atan2(y, x)");
+    pretty_print_test!(can_print_definition, "def f(x, y) (x + y) * 2" => "def f(x, y) (x + y) * 2");
+    pretty_print_test!(can_print_typed_signature, "def f(x: i64, y) -> bool x < y" => "def f(x: i64, y) -> bool x < y");
+    pretty_print_test!(can_print_string_with_escapes, r#"println("a\t\"b\"\\")"# => r#"println("a\t\"b\"\\")"#);
+    pretty_print_test!(can_print_str_parameter, "extern println(s: str)" => "extern println(s: str)");
+    pretty_print_test!(can_print_conversion, "f64((i64(x)))+1" => "f64(i64(x)) + 1");
+    pretty_print_test!(can_print_array_and_index, "[1, (a + b), []][(i)] = -(x[0])" => "[1, a + b, []][i] = -x[0]");
+    pretty_print_test!(can_keep_parentheses_of_indexed_operation, "(a + b)[0] + (-a)[1]" => "(a + b)[0] + (-a)[1]");
+    pretty_print_test!(can_print_array_parameter, "def f(a: [f64]) -> i64 len(a)" => "def f(a: [f64]) -> i64 len(a)");
+    pretty_print_test!(can_print_struct, "struct Point{x,y:i64,}" => "struct Point { x, y: i64 }");
+    pretty_print_test!(can_print_empty_struct, "struct Unit {}" => "struct Unit {}");
+    pretty_print_test!(can_print_field_access, "p.q.x = -(p.x) * (a + b).y" => "p.q.x = -p.x * (a + b).y");
+
+    pretty_print_test!(can_print_postfix_of_number, "(1).x + (2.5)[0]" => "(1).x + (2.5)[0]");
+    pretty_print_test!(can_print_lambdas, "f(\\x -> (x * k), \\(x: i64, y): bool -> x)" => "f(\\x -> x * k, \\(x: i64, y): bool -> x)");
+    pretty_print_test!(can_keep_parentheses_of_lambda_operand, "(\\x -> x) + -(\\y -> y) * (\\z -> z)[0]" => "(\\x -> x) + -(\\y -> y) * (\\z -> z)[0]");
+    pretty_print_test!(can_print_lambda_assignment, "f = (\\x -> x)" => "f = \\x -> x");
+    pretty_print_test!(can_print_const, "const  tau=2*(pi)" => "const tau = 2 * pi");
+    pretty_print_test!(can_print_global, "global counter : i64 = -(1)" => "global counter: i64 = -1");
+    pretty_print_test!(can_print_import, "import   \"lib/math.kal\"" => "import \"lib/math.kal\"");
+    pretty_print_test!(can_print_qualified_names, "math::norm(math::Point(1, 2)) * (math::pi)" => "math::norm(math::Point(1, 2)) * math::pi");
+    pretty_print_test!(can_print_function_type, "def f(g: fn(f64, i64) -> fn() -> bool) g" => "def f(g: fn(f64, i64) -> fn() -> bool) g");
+
+    format_test!(
+        can_format_items_one_per_line,
+        "extern sin(x); def f(x) (x+1)  ;f(2)\n\n\n\nf(3)" =>
+        "extern sin(x)\ndef f(x) x + 1\nf(2)\n\nf(3)\n"
+    );
+
+    format_test!(
+        can_format_block_with_indentation,
+        "def foo(x) { print(x); x = x * 2; { x; }; x + 1 }" =>
+        "def foo(x) {\n    print(x);\n    x = x * 2;\n    {\n        x\n    };\n    x + 1\n}\n"
+    );
+
+    format_test!(
+        can_keep_comments_between_items,
+        "# the sine\nextern sin(x) # from libm\n\n  # a call\nsin(1);# last\n" =>
+        "# the sine\nextern sin(x) # from libm\n\n# a call\nsin(1) # last\n"
+    );
+
+    format_test!(
+        can_keep_items_with_inner_comments_as_written,
+        "def f(x)\n    x +  # one\n    1\nf( 2 )\n" =>
+        "def f(x)\n    x +  # one\n    1\nf(2)\n"
+    );
+
+    format_test!(
+        can_terminate_items_continued_by_the_next_one,
+        "f; (1 + 2) * 3; (4); x; -y; a; [1]\n" =>
+        "f;\n(1 + 2) * 3\n4\nx;\n-y\na;\n[1]\n"
+    );
+
+    format_test!(
+        can_format_only_comments,
+        "\n\n# nothing here\n" =>
+        "# nothing here\n"
+    );
+
+    #[test]
+    fn can_refuse_to_format_broken_source() {
+        assert_eq!(format_source("def f(x x;\n1 + 2 3").unwrap_err().len(), 2);
+    }
+
+    #[test]
+    fn can_format_golden_sources() {
+        for entry in fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden")).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|extension| extension != "ks") {
+                continue;
+            }
+            let src = fs::read_to_string(&path).unwrap();
+            let got = format_source(&src).unwrap();
+            assert_eq!(Parser::new(&got).parse_program().items, Parser::new(&src).parse_program().items, "{:?}", path);
+        }
+    }
+}
@@ -0,0 +1,103 @@
+#[cfg(test)]
+mod tests {
+    use kaleidoscope::syntax::ast::*;
+    use kaleidoscope::syntax::types::Type;
+    use kaleidoscope::syntax::visit::*;
+
+    use kaleidoscope::parse::parser::*;
+
+    fn parse_source_to_ast(src: &str) -> GenericAst {
+        Parser::new(src).build_next_ast().unwrap()
+    }
+
+    fn parse_source_to_expr(src: &str) -> ExprAst {
+        if let GenericAst::ExprAst(expr) = parse_source_to_ast(src) {
+            expr
+        } else {
+            panic!("")
+        }
+    }
+
+    #[derive(Default)]
+    struct VariableCollector {
+        variables: Vec<String>,
+        callees: Vec<String>
+    }
+
+    impl Visitor for VariableCollector {
+        fn visit_variable_expr(&mut self, name: &str) {
+            self.variables.push(name.to_string());
+        }
+
+        fn visit_call_expr(&mut self, callee: &str, args: &[ExprAst]) {
+            self.callees.push(callee.to_string());
+            walk_call_expr(self, args);
+        }
+    }
+
+    struct Renamer {
+        from: String,
+        to: String
+    }
+
+    impl VisitorMut for Renamer {
+        fn visit_prototype_mut(&mut self, _name: &mut String, args: &mut Vec<Param>, _return_type: &mut Option<Type>) {
+            for arg in args.iter_mut().filter(|arg| arg.name == self.from) {
+                arg.name = self.to.clone();
+            }
+        }
+
+        fn visit_variable_expr_mut(&mut self, name: &mut String) {
+            if *name == self.from {
+                *name = self.to.clone();
+            }
+        }
+    }
+
+    struct ConstantFolder;
+
+    impl Folder for ConstantFolder {
+        fn fold_binary_expr(&mut self, op: String, lhs: ExprAst, rhs: ExprAst) -> ExprAst {
+            match (self.fold_expr(lhs), self.fold_expr(rhs)) {
+                (ExprAst::NumberExpr { number: lhs }, ExprAst::NumberExpr { number: rhs }) if op == "+" => ExprAst::NumberExpr { number: lhs + rhs },
+                (ExprAst::NumberExpr { number: lhs }, ExprAst::NumberExpr { number: rhs }) if op == "*" => ExprAst::NumberExpr { number: lhs * rhs },
+                (lhs, rhs) => ExprAst::BinaryExpr { op, lhs: Box::new(lhs), rhs: Box::new(rhs) }
+            }
+        }
+    }
+
+    struct Identity;
+
+    impl Folder for Identity {}
+
+    #[test]
+    fn can_visit_every_node() {
+        let mut collector = VariableCollector::default();
+        collector.visit_generic_ast(&parse_source_to_ast("def foo(a, b) { bar(a, -b); a * (b + c) }"));
+
+        assert_eq!(collector.variables, vec!["a", "b", "a", "b", "c"]);
+        assert_eq!(collector.callees, vec!["bar"]);
+    }
+
+    #[test]
+    fn can_rename_in_place() {
+        let mut ast = parse_source_to_ast("def foo(a, b) a * b + bar(a)");
+        Renamer { from: "a".to_string(), to: "x".to_string() }.visit_generic_ast_mut(&mut ast);
+
+        assert_eq!(ast, parse_source_to_ast("def foo(x, b) x * b + bar(x)"));
+    }
+
+    #[test]
+    fn can_fold_constants() {
+        let folded = ConstantFolder.fold_expr(parse_source_to_expr("x + 2 * 3 + 4 * (1 + 1)"));
+
+        assert_eq!(folded, parse_source_to_expr("x + 6 + 8"));
+    }
+
+    #[test]
+    fn can_fold_into_equal_tree_by_default() {
+        let ast = parse_source_to_ast("def foo(a) { a = !a; f(a ^ 2, { 1; a }) }");
+
+        assert_eq!(Identity.fold_generic_ast(ast.clone()), ast);
+    }
+}
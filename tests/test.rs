@@ -3,8 +3,27 @@ extern crate core;
 pub mod parser {
     pub mod naive_parser;
     pub mod lexer;
+    pub mod golden_ast;
+    pub mod resolver;
+}
+
+pub mod syntax {
+    pub mod visit;
+    pub mod pretty;
+}
+
+pub mod analysis {
+    pub mod semantic;
+    pub mod infer;
+    pub mod type_check;
 }
 
 pub mod codegen {
     pub mod llvm_generator;
-}
\ No newline at end of file
+    pub mod jit;
+}
+
+pub mod driver {
+    pub mod compile;
+    pub mod repl;
+}